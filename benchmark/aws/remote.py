@@ -163,15 +163,13 @@ class Bench:
         subprocess.run(cmd, capture_output=True, check=True)
 
         names = [x.name for x in keys]
-        smvba_addr = [f'{x}:{self.settings.smvba_port}' for x in hosts]
         consensus_addr = [f'{x}:{self.settings.consensus_port}' for x in hosts]
         front_addr = [f'{x}:{self.settings.front_port}' for x in hosts]
         tss_keys = []
         for i in range(nodes):
             tss_keys += [TSSKey.from_file(PathMaker.threshold_key_file(i))]
         ids = [x.id for x in tss_keys]
-        mempool_addr = [f'{x}:{self.settings.mempool_port}' for x in hosts]
-        committee = Committee(names, ids, consensus_addr,smvba_addr, front_addr, mempool_addr)
+        committee = Committee(names, ids, consensus_addr, front_addr)
         committee.print(PathMaker.committee_file())
 
         node_parameters.print(PathMaker.parameters_file())
@@ -290,15 +288,7 @@ class Bench:
             e = FabricError(e) if isinstance(e, GroupException) else e
             raise BenchError('Failed to update nodes', e)
 
-        if node_parameters.protocol == 0:
-            Print.info('Running HotStuff')
-        elif node_parameters.protocol == 1:
-            Print.info('Running ParBFT1')
-        elif node_parameters.protocol == 2:
-            Print.info('Running SMVBA')
-        else:
-            Print.info('Wrong protocol type!')
-            return
+        Print.info(f'Running {node_parameters.protocol}')
 
         Print.info(f'{bench_parameters.faults} faults')
         Print.info(f'Timeout {node_parameters.timeout_delay} ms, Network delay {node_parameters.network_delay} ms')
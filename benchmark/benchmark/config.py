@@ -31,8 +31,9 @@ class TSSKey:
         return cls(data['id'], data['name'], data['secret'])
 
 class Committee:
-    def __init__(self, names, ids, consensus_addr,smvba_addr ,front_addr, mempool_addr):
-        inputs = [names, consensus_addr, smvba_addr,front_addr, mempool_addr]
+    def __init__(self, names, ids, consensus_addr, front_addr):
+        # Consensus, SMVBA and mempool messages share the consensus address.
+        inputs = [names, consensus_addr, front_addr]
         assert all(isinstance(x, list) for x in inputs)
         assert all(isinstance(x, str) for y in inputs for x in y)
         assert len({len(x) for x in inputs}) == 1
@@ -41,25 +42,13 @@ class Committee:
         self.ids = ids
         self.consensus = consensus_addr
         self.front = front_addr
-        self.mempool = mempool_addr
-        self.smvba = smvba_addr
-
-        self.json = {
-            'consensus': self._build_consensus(),
-            'mempool': self._build_mempool()
-        }
-
-    def _build_consensus(self):
-        node = {}
-        for a, b, n, id in zip(self.consensus, self.smvba, self.names, self.ids):
-            node[n] = {'name': n, 'stake': 1, 'address': a, 'smvba_address': b,'id': id}
-        return {'authorities': node, 'epoch': 1}
-
-    def _build_mempool(self):
-        node = {}
-        for n, f, m in zip(self.names, self.front, self.mempool):
-            node[n] = {'name': n, 'front_address': f, 'mempool_address': m}
-        return {'authorities': node, 'epoch': 1}
+
+        # The node derives the consensus and mempool committees from this one.
+        authorities = [
+            {'name': n, 'id': id, 'stake': 1, 'address': a, 'front_address': f}
+            for n, id, a, f in zip(self.names, self.ids, self.consensus, self.front)
+        ]
+        self.json = {'authorities': authorities, 'epoch': 1}
 
     def print(self, filename):
         assert isinstance(filename, str)
@@ -67,7 +56,7 @@ class Committee:
             dump(self.json, f, indent=4, sort_keys=True)
 
     def size(self):
-        return len(self.json['consensus']['authorities'])
+        return len(self.json['authorities'])
 
     @classmethod
     def load(cls, filename):
@@ -75,16 +64,12 @@ class Committee:
         with open(filename, 'r') as f:
             data = load(f)
 
-        consensus_authorities = data['consensus']['authorities'].values()
-        mempool_authorities = data['mempool']['authorities'].values()
-
-        names = [x['name'] for x in consensus_authorities]
-        ids = [x['id'] for x in consensus_authorities]
-        consensus_addr = [x['address'] for x in consensus_authorities]
-        smvba_address = [x['smvba_address'] for x in consensus_authorities]
-        front_addr = [x['front_address'] for x in mempool_authorities]
-        mempool_addr = [x['mempool_address'] for x in mempool_authorities]
-        return cls(names, ids, consensus_addr, smvba_address ,front_addr, mempool_addr)
+        authorities = data['authorities']
+        names = [x['name'] for x in authorities]
+        ids = [x['id'] for x in authorities]
+        consensus_addr = [x['address'] for x in authorities]
+        front_addr = [x['front_address'] for x in authorities]
+        return cls(names, ids, consensus_addr, front_addr)
 
 
 class LocalCommittee(Committee):
@@ -93,10 +78,8 @@ class LocalCommittee(Committee):
         assert isinstance(port, int)
         size = len(names)
         consensus = [f'127.0.0.1:{port + i}' for i in range(size)]
-        smvba = [f'127.0.0.1:{port + i + size}' for i in range(size)]
-        front = [f'127.0.0.1:{port + i + 2*size}' for i in range(size)]
-        mempool = [f'127.0.0.1:{port + i + 3*size}' for i in range(size)]
-        super().__init__(names, ids, consensus, smvba, front, mempool)
+        front = [f'127.0.0.1:{port + i + size}' for i in range(size)]
+        super().__init__(names, ids, consensus, front)
 
 
 class NodeParameters:
@@ -113,17 +96,20 @@ class NodeParameters:
             inputs += [json['consensus']['random_ddos']]
             inputs += [json['consensus']['random_ddos_chance']]
             inputs += [json['consensus']['fallback_length']]
-            inputs += [json['consensus']['exp']]
             inputs += [json['mempool']['queue_capacity']]
             inputs += [json['consensus']['sync_retry_delay']]
             inputs += [json['mempool']['max_payload_size']]
-            inputs += [json['mempool']['min_block_delay']]
-            inputs += [json['protocol']]
+            inputs += [json['mempool']['max_payload_delay']]
+            protocol = json['protocol']
         except KeyError as e:
             raise ConfigError(f'Malformed parameters: missing key {e}')
 
         if not all(isinstance(x, int) for x in inputs):
             raise ConfigError('Invalid parameters type')
+
+        # The node refuses names it does not know.
+        if not isinstance(protocol, str):
+            raise ConfigError('The protocol is selected by name')
         
         self.node_sync_time = json['consensus']['node_sync_time']
         self.timeout_delay = json['consensus']['timeout_delay'] 
@@ -103,15 +103,7 @@ class LocalBench:
                 )
                 self._background_run(cmd, log_file)
             
-            if self.node_parameters.protocol == 0:
-                Print.info('Running HotStuff')
-            elif self.node_parameters.protocol == 1:
-                Print.info('Running ParBFT')
-            elif self.node_parameters.protocol == 2:
-                Print.info('Running SMVBA')
-            else:
-                Print.info('Wrong protocol type!')
-                return
+            Print.info(f'Running {self.node_parameters.protocol}')
 
             Print.info(f'{self.faults} faults')
             Print.info(f'Timeout {self.node_parameters.timeout_delay} ms, Network delay {self.node_parameters.network_delay} ms')
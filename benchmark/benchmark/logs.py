@@ -148,8 +148,8 @@ class LogParser:
                 'max_payload_size': int(
                     search(r'Mempool max payload size .* (\d+)', log).group(1)
                 ),
-                'min_block_delay': int(
-                    search(r'Mempool min block delay .* (\d+)', log).group(1)
+                'max_payload_delay': int(
+                    search(r'Mempool max payload delay .* (\d+)', log).group(1)
                 ),
             }
         }
@@ -213,7 +213,7 @@ class LogParser:
         mempool_queue_capacity = self.configs[0]['mempool']['queue_capacity']
         # mempool_sync_retry_delay = self.configs[0]['mempool']['sync_retry_delay']
         mempool_max_payload_size = self.configs[0]['mempool']['max_payload_size']
-        mempool_min_block_delay = self.configs[0]['mempool']['min_block_delay']
+        mempool_max_payload_delay = self.configs[0]['mempool']['max_payload_delay']
 
         return (
             '\n'
@@ -236,7 +236,7 @@ class LogParser:
             f' Mempool queue capacity: {mempool_queue_capacity:,} B\n'
             # f' Mempool sync retry delay: {mempool_sync_retry_delay:,} ms\n'
             f' Mempool max payloads size: {mempool_max_payload_size:,} B\n'
-            f' Mempool min block delay: {mempool_min_block_delay:,} ms\n'
+            f' Mempool max payload delay: {mempool_max_payload_delay:,} ms\n'
             '\n'
             ' + RESULTS:\n'
             f' Consensus TPS: {round(consensus_tps):,} tx/s\n'
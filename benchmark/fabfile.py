@@ -30,15 +30,22 @@ def local(ctx):
             'random_ddos': False,
             'random_ddos_chance': 10,
             'fallback_length': 2,
-            'exp': 1 # multiplicative factor for exponential fallback
+            'threshold_qc': False, # aggregate QC votes and SPB proofs into threshold signatures
+            'verification_workers': 4 # threads verifying network messages, 0 verifies on the core
         },
         'mempool': {
             'queue_capacity': 100_000,
             'sync_retry_delay': 10_000,
             'max_payload_size': 15_625,
-            'min_block_delay': 0
+            'max_payload_delay': 100, # seal a payload once its oldest transaction waited this long (ms, 0 to seal on size only)
+            'target_commit_latency': 0, # shrink payloads committing later than this (ms, 0 for fixed-size payloads)
+            'ingestion_workers': 1, # tasks building and signing payloads, client connections are spread across them
+            'dag': False, # True for the Narwhal-style DAG mempool
+            'max_header_delay': 100,
+            'queue_policy': 'Fifo' # 'Fifo' for arrival order, 'Fair' for round robin across authors, 'Priority' for client-declared priorities
         },
-        'protocol': 1, # 0 for 2-chain HotStuff, 1 for ParBFT, 2 for SMVBA
+        'protocol': 'HotStuffAndSMVBA', # HotStuff, HotStuffAndSMVBA (ParBFT) or SMVBA
+        'verification': 'Full', # 'Full', {'Sampled': percent} or 'Skip' (benchmark builds only)
     }
     try:
         ret = LocalBench(bench_params, node_params).run(debug=False).result()
@@ -125,15 +132,22 @@ def remote(ctx):
             'random_ddos': False,
             'random_ddos_chance': 20,
             'fallback_length': 2,
-            'exp': 1 # multiplicative factor for exponential fallback
+            'threshold_qc': False, # aggregate QC votes and SPB proofs into threshold signatures
+            'verification_workers': 4 # threads verifying network messages, 0 verifies on the core
         },
         'mempool': {
             'queue_capacity': 100_000,
             'sync_retry_delay': 100_000,
             'max_payload_size': 15_625,
-            'min_block_delay': 100
+            'max_payload_delay': 100, # seal a payload once its oldest transaction waited this long (ms, 0 to seal on size only)
+            'target_commit_latency': 0, # shrink payloads committing later than this (ms, 0 for fixed-size payloads)
+            'ingestion_workers': 1, # tasks building and signing payloads, client connections are spread across them
+            'dag': False, # True for the Narwhal-style DAG mempool
+            'max_header_delay': 100,
+            'queue_policy': 'Fifo' # 'Fifo' for arrival order, 'Fair' for round robin across authors, 'Priority' for client-declared priorities
         },
-        'protocol': 1, # 0 for 2-chain HotStuff, 1 for Ditto, 2 for 2-chain VABA
+        'protocol': 'HotStuffAndSMVBA', # HotStuff, HotStuffAndSMVBA (Ditto) or SMVBA (2-chain VABA)
+        'verification': 'Full', # 'Full', {'Sampled': percent} or 'Skip' (benchmark builds only)
     }
     try:
         Bench(ctx).run(bench_params, node_params, debug=False)
@@ -1,20 +1,30 @@
 use crate::config::{Committee, Stake};
-use crate::core::SeqNumber;
+use crate::core::{SeqNumber, LOCK_PHASE};
 use crate::error::{ConsensusError, ConsensusResult};
-use crate::messages::{HVote, RandomCoin, RandomnessShare, SPBProof, SPBVote, QC};
-use crypto::{PublicKey, Signature};
+use crate::messages::{HVote, QuorumSignature, RandomCoin, RandomnessShare, SPBProof, SPBVote, QC};
+use crypto::{Digest, PublicKey, Signature};
 use std::collections::{BTreeMap, HashMap, HashSet};
-use threshold_crypto::PublicKeySet;
+use threshold_crypto::{PublicKeySet, SignatureShare};
 // use std::convert::TryInto;
 
 #[cfg(test)]
 #[path = "tests/aggregator_tests.rs"]
 pub mod aggregator_tests;
 
+// Votes are only accepted up to this many heights above the last cleaned up
+// height, so a bad node cannot make us allocate aggregators for arbitrary heights.
+pub const MAX_HEIGHT_WINDOW: SeqNumber = 100;
+
+// Likewise, SMVBA votes and coin shares are only accepted up to this many rounds
+// above our current SMVBA round at their height.
+pub const MAX_ROUND_WINDOW: SeqNumber = 10;
+
 // In HotStuff, votes/timeouts aggregated by round
 // In VABA and async fallback, votes aggregated by round, timeouts/coin_share aggregated by view
 pub struct Aggregator {
     committee: Committee,
+    hs_height: SeqNumber,    // last cleaned up HotStuff height
+    smvba_height: SeqNumber, // last cleaned up fallback/SMVBA height
     hs_votes_aggregators: HashMap<SeqNumber, Box<QCMaker>>,
     fallback_votes_aggregators: HashMap<(SeqNumber, SeqNumber), Box<QCMaker>>,
     spb_votes_aggregators: HashMap<(SeqNumber, SeqNumber, u8), Box<ProofMaker>>,
@@ -26,6 +36,8 @@ impl Aggregator {
     pub fn new(committee: Committee) -> Self {
         Self {
             committee,
+            hs_height: 0,
+            smvba_height: 0,
             hs_votes_aggregators: HashMap::new(),
             fallback_votes_aggregators: HashMap::new(),
             spb_votes_aggregators: HashMap::new(),
@@ -34,9 +46,70 @@ impl Aggregator {
         }
     }
 
+    // Returns false for heights already cleaned up, and an error for heights too
+    // far ahead of them.
+    fn in_window(base: SeqNumber, height: SeqNumber) -> ConsensusResult<bool> {
+        ensure!(
+            height <= base + MAX_HEIGHT_WINDOW,
+            ConsensusError::VoteOutOfWindow(height)
+        );
+        Ok(height > base)
+    }
+
+    fn in_round_window(current: SeqNumber, round: SeqNumber) -> ConsensusResult<()> {
+        ensure!(
+            round <= current + MAX_ROUND_WINDOW,
+            ConsensusError::RoundOutOfWindow(round)
+        );
+        Ok(())
+    }
+
+    // Only the init and lock phases are voted for.
+    fn check_phase(phase: u8) -> ConsensusResult<()> {
+        ensure!(phase <= LOCK_PHASE, ConsensusError::InvalidPhase(phase));
+        Ok(())
+    }
+
+    // Whether a HotStuff message is worth keeping: false for heights already
+    // cleaned up, and an error for heights too far ahead.
+    pub fn check_hs_height(&self, height: SeqNumber) -> ConsensusResult<bool> {
+        Self::in_window(self.hs_height, height)
+    }
+
+    // Likewise for the votes on fallback blocks.
+    pub fn check_fallback_vote(
+        &self,
+        vote: &HVote,
+        fallback_length: SeqNumber,
+    ) -> ConsensusResult<bool> {
+        if !Self::in_window(self.smvba_height, vote.height)? {
+            return Ok(false);
+        }
+        ensure!(
+            vote.round <= fallback_length,
+            ConsensusError::RoundOutOfWindow(vote.round)
+        );
+        Ok(true)
+    }
+
+    // Likewise for the SPB votes and pre-votes.
+    pub fn check_spb_vote(
+        &self,
+        vote: &SPBVote,
+        current_round: SeqNumber,
+    ) -> ConsensusResult<bool> {
+        if !Self::in_window(self.smvba_height, vote.height)? {
+            return Ok(false);
+        }
+        Self::in_round_window(current_round, vote.round)?;
+        Self::check_phase(vote.phase)?;
+        Ok(true)
+    }
+
     pub fn add_hs_vote(&mut self, vote: HVote) -> ConsensusResult<Option<QC>> {
-        // TODO [issue #7]: A bad node may make us run out of memory by sending many votes
-        // with different round numbers or different digests.
+        if !self.check_hs_height(vote.height)? {
+            return Ok(None);
+        }
 
         // Add the new vote to our aggregator and see if we have a QC.
         self.hs_votes_aggregators
@@ -45,16 +118,31 @@ impl Aggregator {
             .append(vote, &self.committee)
     }
 
-    pub fn add_fallback_vote(&mut self, vote: HVote) -> ConsensusResult<Option<QC>> {
+    // Fallback blocks are chained up to round `fallback_length`.
+    pub fn add_fallback_vote(
+        &mut self,
+        vote: HVote,
+        fallback_length: SeqNumber,
+    ) -> ConsensusResult<Option<QC>> {
+        if !self.check_fallback_vote(&vote, fallback_length)? {
+            return Ok(None);
+        }
+
         self.fallback_votes_aggregators
             .entry((vote.height, vote.round))
             .or_insert_with(|| Box::new(QCMaker::new()))
             .append(vote, &self.committee)
     }
 
-    pub fn add_spb_vote(&mut self, vote: SPBVote) -> ConsensusResult<Option<SPBProof>> {
-        // TODO [issue #7]: A bad node may make us run out of memory by sending many votes
-        // with different round numbers or different digests.
+    // `current_round` is our SMVBA round at the height of the vote.
+    pub fn add_spb_vote(
+        &mut self,
+        vote: SPBVote,
+        current_round: SeqNumber,
+    ) -> ConsensusResult<Option<SPBProof>> {
+        if !self.check_spb_vote(&vote, current_round)? {
+            return Ok(None);
+        }
 
         // Add the new vote to our aggregator and see if we have a QC.
         self.spb_votes_aggregators
@@ -63,9 +151,14 @@ impl Aggregator {
             .append(vote, &self.committee)
     }
 
-    pub fn add_pre_vote(&mut self, vote: SPBVote) -> ConsensusResult<Option<SPBProof>> {
-        // TODO [issue #7]: A bad node may make us run out of memory by sending many votes
-        // with different round numbers or different digests.
+    pub fn add_pre_vote(
+        &mut self,
+        vote: SPBVote,
+        current_round: SeqNumber,
+    ) -> ConsensusResult<Option<SPBProof>> {
+        if !self.check_spb_vote(&vote, current_round)? {
+            return Ok(None);
+        }
 
         // Add the new vote to our aggregator and see if we have a QC.
         self.pre_votes_aggregators
@@ -78,7 +171,13 @@ impl Aggregator {
         &mut self,
         share: RandomnessShare,
         pk_set: &PublicKeySet,
+        current_round: SeqNumber,
     ) -> ConsensusResult<Option<RandomCoin>> {
+        if !Self::in_window(self.smvba_height, share.height)? {
+            return Ok(None);
+        }
+        Self::in_round_window(current_round, share.round)?;
+
         self.smvba_randomcoin_aggregators
             .entry((share.height, share.round))
             .or_insert_with(|| Box::new(SMVBARandomCoinMaker::new()))
@@ -87,33 +186,58 @@ impl Aggregator {
 
     // used in HotStuff
     pub fn cleanup_hs_vote(&mut self, height: &SeqNumber) {
+        self.hs_height = self.hs_height.max(*height);
         self.hs_votes_aggregators.retain(|k, _| k > height);
     }
 
     pub fn cleanup_spb_vote(&mut self, height: &SeqNumber) {
+        self.smvba_height = self.smvba_height.max(*height);
         self.spb_votes_aggregators
             .retain(|(h, _, ..), _| h > height);
         self.pre_votes_aggregators.retain(|(h, ..), _| h > height);
+        self.fallback_votes_aggregators
+            .retain(|(h, ..), _| h > height);
     }
 
     pub fn cleanup_mvba_random(&mut self, height: &SeqNumber) {
+        self.smvba_height = self.smvba_height.max(*height);
         self.smvba_randomcoin_aggregators
             .retain(|(h, _), _| h > height);
     }
 }
 
+// Returns whether it is the first time this authority votes in the slot, and
+// flags it as equivocating if it already voted for another digest.
+fn first_vote(
+    used: &mut HashMap<PublicKey, Digest>,
+    author: PublicKey,
+    hash: &Digest,
+    height: SeqNumber,
+) -> ConsensusResult<bool> {
+    match used.get(&author) {
+        Some(x) if x == hash => Ok(false),
+        Some(_) => Err(ConsensusError::EquivocatingVote(author, height)),
+        None => {
+            used.insert(author, hash.clone());
+            Ok(true)
+        }
+    }
+}
+
 struct QCMaker {
-    weight: Stake,
-    votes: Vec<(PublicKey, Signature)>,
-    used: HashSet<PublicKey>,
+    weight: HashMap<Digest, Stake>,
+    votes: HashMap<Digest, Vec<(PublicKey, Signature)>>,
+    shares: HashMap<Digest, BTreeMap<usize, SignatureShare>>, // by tss id
+    used: HashMap<PublicKey, Digest>,
 }
 
 impl QCMaker {
     pub fn new() -> Self {
         Self {
-            weight: 0,
-            votes: Vec::new(),
-            used: HashSet::new(),
+            weight: HashMap::new(),
+            votes: HashMap::new(),
+            shares: HashMap::new(),
+            used: HashMap::new(),
         }
     }
 
@@ -122,14 +246,20 @@ impl QCMaker {
         let author = vote.author;
         // Ensure it is the first time this authority votes.
         ensure!(
-            self.used.insert(author),
+            first_vote(&mut self.used, author, &vote.hash, vote.height)?,
             ConsensusError::AuthorityReuseinQC(author)
         );
-        self.votes.push((author, vote.signature));
-        self.weight += committee.stake(&author);
-        if self.weight >= committee.quorum_threshold() {
-            self.weight = 0; // Ensures QC is only made once.
-            return Ok(Some(QC {
+        let votes = self.votes.entry(vote.hash.clone()).or_default();
+        votes.push((author, vote.signature));
+        let shares = self.shares.entry(vote.hash.clone()).or_default();
+        if let Some(share) = vote.signature_share {
+            shares.insert(committee.id(author), share);
+        }
+        let weight = self.weight.entry(vote.hash.clone()).or_insert(0);
+        *weight += committee.stake(&author);
+        if *weight >= committee.quorum_threshold() {
+            *weight = 0; // Ensures QC is only made once.
+            let qc = QC {
                 hash: vote.hash.clone(),
                 height: vote.height,
                 epoch: vote.epoch,
@@ -137,7 +267,23 @@ impl QCMaker {
                 tag: vote.tag,
                 proposer: vote.proposer,
                 acceptor: vote.proposer,
-                votes: self.votes.clone(),
+                votes: Vec::new(),
+                quorum_signature: None,
+            };
+            // 门限签名聚合失败时退回到逐个签名的QC
+            let quorum_signature = committee
+                .quorum_keys
+                .as_ref()
+                .and_then(|keys| QuorumSignature::new(shares.iter(), keys, committee.size()));
+            return Ok(Some(match quorum_signature {
+                Some(quorum_signature) => QC {
+                    quorum_signature: Some(quorum_signature),
+                    ..qc
+                },
+                None => QC {
+                    votes: votes.clone(),
+                    ..qc
+                },
             }));
         }
         Ok(None)
@@ -145,17 +291,19 @@ impl QCMaker {
 }
 
 struct ProofMaker {
-    weight: Stake,
-    votes: Vec<SPBVote>,
-    used: HashSet<PublicKey>,
+    weight: HashMap<Digest, Stake>,
+    votes: HashMap<Digest, Vec<SPBVote>>,
+    shares: HashMap<Digest, BTreeMap<usize, SignatureShare>>, // by tss id
+    used: HashMap<PublicKey, Digest>,
 }
 
 impl ProofMaker {
     pub fn new() -> Self {
         Self {
-            weight: 0,
-            votes: Vec::new(),
-            used: HashSet::new(),
+            weight: HashMap::new(),
+            votes: HashMap::new(),
+            shares: HashMap::new(),
+            used: HashMap::new(),
         }
     }
 
@@ -169,21 +317,44 @@ impl ProofMaker {
         let phase = vote.phase;
         let round = vote.round;
         let height = vote.height;
+        let hash = vote.hash.clone();
         // Ensure it is the first time this authority votes.
         ensure!(
-            self.used.insert(author),
-            ConsensusError::AuthorityReuseinProof(author, self.used.clone())
+            first_vote(&mut self.used, author, &hash, height)?,
+            ConsensusError::AuthorityReuseinProof(author, self.used.keys().cloned().collect())
         );
-        self.votes.push(vote);
-        self.weight += committee.stake(&author);
+        let shares = self.shares.entry(hash.clone()).or_default();
+        if let Some(share) = &vote.signature_share {
+            shares.insert(committee.id(author), share.clone());
+        }
+        let votes = self.votes.entry(hash.clone()).or_default();
+        votes.push(vote);
+        let weight = self.weight.entry(hash).or_insert(0);
+        *weight += committee.stake(&author);
 
-        if self.weight >= committee.quorum_threshold() {
-            self.weight = 0; // Ensures QC is only made once.
-            return Ok(Some(SPBProof {
+        if *weight >= committee.quorum_threshold() {
+            *weight = 0; // Ensures QC is only made once.
+            let proof = SPBProof {
                 height,
                 phase: phase + 1, //为下一个阶段产生proof
                 round,
-                shares: self.votes.clone(),
+                shares: Vec::new(),
+                quorum_signature: None,
+            };
+            // 门限签名聚合失败时退回到逐个投票的proof
+            let quorum_signature = committee
+                .quorum_keys
+                .as_ref()
+                .and_then(|keys| QuorumSignature::new(shares.iter(), keys, committee.size()));
+            return Ok(Some(match quorum_signature {
+                Some(quorum_signature) => SPBProof {
+                    quorum_signature: Some(quorum_signature),
+                    ..proof
+                },
+                None => SPBProof {
+                    shares: votes.clone(),
+                    ..proof
+                },
             }));
         }
         Ok(None)
@@ -3,21 +3,43 @@ use crypto::PublicKey;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::net::SocketAddr;
+use threshold_crypto::PublicKeySet;
 
 pub type Stake = u32;
 pub type EpochNumber = u128;
 
-#[derive(Serialize, Deserialize)]
-pub enum Protocol {
-    HotStuff,
-    HotStuffAndSMVBA,
-    SMVBA,
-    Others,
+// How incoming messages and payloads are verified. Only `Full` is allowed in
+// production builds: the other policies trade safety for throughput and are
+// meant for benchmarks on a trusted testbed (`benchmark` feature).
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+pub enum VerificationPolicy {
+    Full,        // verify every message
+    Sampled(u8), // verify the given percentage of the messages, picked at random
+    Skip,        // verify nothing
 }
 
-impl Default for Protocol {
+impl Default for VerificationPolicy {
     fn default() -> Self {
-        Protocol::HotStuffAndSMVBA
+        VerificationPolicy::Full
+    }
+}
+
+impl VerificationPolicy {
+    pub fn check(&self) -> ConsensusResult<()> {
+        ensure!(
+            cfg!(feature = "benchmark") || *self == VerificationPolicy::Full,
+            ConsensusError::RelaxedVerification(*self)
+        );
+        Ok(())
+    }
+
+    // Whether to verify the next message.
+    pub fn should_verify(&self) -> bool {
+        match self {
+            VerificationPolicy::Full => true,
+            VerificationPolicy::Sampled(percent) => rand::random::<f64>() * 100.0 < *percent as f64,
+            VerificationPolicy::Skip => false,
+        }
     }
 }
 
@@ -33,7 +55,22 @@ pub struct Parameters {
     pub random_ddos: bool,
     pub random_ddos_chance: u64,
     pub fallback_length: u64,
-    pub exp: u64,
+    #[serde(default)]
+    pub threshold_qc: bool, // aggregate HotStuff votes and SPB proofs into threshold signatures
+    #[serde(default = "default_verification_workers")]
+    pub verification_workers: usize, // 0 verifies messages on the verifier task
+    #[serde(default = "default_max_frame_size")]
+    pub max_frame_size: usize, // largest message accepted from a peer, in bytes
+    #[serde(skip)]
+    pub verification: VerificationPolicy, // set by the node, see `VerificationPolicy`
+}
+
+fn default_verification_workers() -> usize {
+    4
+}
+
+fn default_max_frame_size() -> usize {
+    network::DEFAULT_MAX_FRAME_SIZE
 }
 
 impl Default for Parameters {
@@ -49,7 +86,10 @@ impl Default for Parameters {
             random_ddos: false,
             random_ddos_chance: 5,
             fallback_length: 3,
-            exp: 1,
+            threshold_qc: false,
+            verification_workers: default_verification_workers(),
+            max_frame_size: default_max_frame_size(),
+            verification: VerificationPolicy::default(),
         }
     }
 }
@@ -59,36 +99,35 @@ pub struct Authority {
     pub name: PublicKey,
     pub id: usize, // id of the node in the tss public key share set
     pub stake: Stake,
-    pub address: SocketAddr,
-    pub smvba_address: SocketAddr,
+    pub address: SocketAddr, // carries both the OPT and the PES messages
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct Committee {
     pub authorities: HashMap<PublicKey, Authority>,
     pub epoch: EpochNumber,
+    // Key set with threshold 2f; QCs carry a single threshold signature when set.
+    #[serde(default)]
+    pub quorum_keys: Option<PublicKeySet>,
 }
 
 impl Committee {
-    pub fn new(
-        info: Vec<(PublicKey, usize, Stake, SocketAddr, SocketAddr)>,
-        epoch: EpochNumber,
-    ) -> Self {
+    pub fn new(info: Vec<(PublicKey, usize, Stake, SocketAddr)>, epoch: EpochNumber) -> Self {
         Self {
             authorities: info
                 .into_iter()
-                .map(|(name, id, stake, address, smvba_address)| {
+                .map(|(name, id, stake, address)| {
                     let authority = Authority {
                         name,
                         id,
                         stake,
                         address,
-                        smvba_address,
                     };
                     (name, authority)
                 })
                 .collect(),
             epoch,
+            quorum_keys: None,
         }
     }
 
@@ -137,13 +176,6 @@ impl Committee {
             .ok_or_else(|| ConsensusError::NotInCommittee(*name))
     }
 
-    pub fn smvba_address(&self, name: &PublicKey) -> ConsensusResult<SocketAddr> {
-        self.authorities
-            .get(name)
-            .map(|x| x.smvba_address)
-            .ok_or_else(|| ConsensusError::NotInCommittee(*name))
-    }
-
     pub fn broadcast_addresses(&self, myself: &PublicKey) -> Vec<SocketAddr> {
         self.authorities
             .values()
@@ -151,12 +183,4 @@ impl Committee {
             .map(|x| x.address)
             .collect()
     }
-
-    pub fn smvba_broadcast_addresses(&self, myself: &PublicKey) -> Vec<SocketAddr> {
-        self.authorities
-            .values()
-            .filter(|x| x.name != *myself)
-            .map(|x| x.smvba_address)
-            .collect()
-    }
 }
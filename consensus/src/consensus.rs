@@ -1,14 +1,17 @@
-use crate::config::{Committee, Parameters, Protocol};
+use crate::config::{Committee, Parameters};
 use crate::core::{ConsensusMessage, Core};
 use crate::error::ConsensusResult;
 use crate::filter::Filter;
 use crate::leader::LeaderElector;
 use crate::mempool::{ConsensusMempoolMessage, MempoolDriver};
 use crate::messages::Block;
+use crate::protocol::Protocol;
 use crate::synchronizer::Synchronizer;
+use crate::tuning::{Tuner, Tuning};
+use crate::verifier::Verifier;
 use crypto::{PublicKey, SignatureService};
 use log::info;
-use network::{NetReceiver, NetSender};
+use network::{ChannelId, Mux, Shutdown};
 use store::Store;
 use threshold_crypto::PublicKeySet;
 use tokio::sync::mpsc::{channel, Receiver, Sender};
@@ -18,6 +21,11 @@ use tokio::time::{sleep, Duration};
 #[path = "tests/consensus_tests.rs"]
 pub mod consensus_tests;
 
+// The channels of the consensus on the connections between nodes. The OPT path
+// goes first, see `Mux`.
+pub const CONSENSUS_CHANNEL: ChannelId = 0;
+pub const SMVBA_CHANNEL: ChannelId = 1;
+
 pub struct Consensus;
 
 impl Consensus {
@@ -36,7 +44,11 @@ impl Consensus {
         tx_consensus_mempool: Sender<ConsensusMempoolMessage>,
         tx_commit: Sender<Block>,
         protocol: Protocol,
+        rx_tuning: Receiver<Tuning>, // parameters changed while running, see `Tuner`
+        mux: &mut Mux,               // the connections of the node, run by the caller
+        shutdown: Shutdown,          // stops the core
     ) -> ConsensusResult<()> {
+        info!("Consensus protocol set to {}", protocol);
         info!(
             "Consensus timeout delay set to {} ms",
             parameters.timeout_delay
@@ -53,40 +65,42 @@ impl Consensus {
             "Consensus min block delay set to {} ms",
             parameters.min_block_delay
         );
+        info!(
+            "Consensus verification workers set to {}",
+            parameters.verification_workers
+        );
+        info!(
+            "Consensus max frame size set to {} B",
+            parameters.max_frame_size
+        );
+        info!(
+            "Consensus verification policy set to {:?}",
+            parameters.verification
+        );
+        parameters.verification.check()?;
 
-        let (tx_network, rx_network) = channel(10000);
-        let (tx_net_smvba, rx_net_smvba) = channel(10000);
+        let tuner = Tuner::new(rx_tuning, parameters.clone());
         let (tx_filter, rx_filter) = channel(10000);
         let (tx_filter_smvba, rx_filter_smvba) = channel(10000);
 
-        // Make the network sender and receiver.
-        let address = committee.address(&name).map(|mut x| {
-            x.set_ip("0.0.0.0".parse().unwrap());
-            x
-        })?;
-        let network_receiver = NetReceiver::new(address, tx_core.clone());
-        tokio::spawn(async move {
-            network_receiver.run().await;
-        });
-
-        let smvba_address = committee.smvba_address(&name).map(|mut x| {
-            x.set_ip("0.0.0.0".parse().unwrap());
-            x
-        })?;
-        let smvba_receiver = NetReceiver::new(smvba_address, tx_smvba.clone());
-        tokio::spawn(async move {
-            smvba_receiver.run().await;
-        });
+        // Verify the messages from the network before they reach the core.
+        let (tx_verifier, rx_verifier) = channel(10000);
+        let (tx_verifier_smvba, rx_verifier_smvba) = channel(10000);
+        Verifier::spawn(
+            committee.clone(),
+            pk_set.clone(),
+            &parameters,
+            rx_verifier,
+            rx_verifier_smvba,
+            tx_core.clone(),
+            tx_smvba.clone(),
+        );
 
-        let mut network_sender = NetSender::new(rx_network);
-        tokio::spawn(async move {
-            network_sender.run().await;
-        });
-
-        let mut network_sender_smvba = NetSender::new(rx_net_smvba);
-        tokio::spawn(async move {
-            network_sender_smvba.run().await;
-        });
+        // Receive and send our messages on the connections of the node.
+        mux.receiver(CONSENSUS_CHANNEL, tx_verifier, parameters.max_frame_size);
+        mux.receiver(SMVBA_CHANNEL, tx_verifier_smvba, parameters.max_frame_size);
+        let network_sender = mux.sender(CONSENSUS_CHANNEL);
+        let network_sender_smvba = mux.sender(SMVBA_CHANNEL);
 
         // The leader elector algorithm.
         let leader_elector = LeaderElector::new(committee.clone());
@@ -94,13 +108,14 @@ impl Consensus {
         // Make the mempool driver which will mediate our requests to the mempool.
         let mempool_driver = MempoolDriver::new(tx_consensus_mempool);
 
-        // Custom filter to arbitrary delay network messages.
+        // Custom filter to arbitrary delay network messages. The filter, the
+        // synchronizer and the verifier stop once the tasks feeding them did.
         Filter::run(
             rx_filter,
             rx_filter_smvba,
-            tx_network,
-            tx_net_smvba,
-            parameters.clone(),
+            network_sender,
+            network_sender_smvba,
+            tuner.subscribe(),
         ); //对消息进行延迟
 
         // Make the synchronizer. This instance runs in a background thread
@@ -115,89 +130,31 @@ impl Consensus {
         )
         .await;
         sleep(Duration::from_millis(parameters.node_sync_time)).await;
-        match protocol {
-            Protocol::HotStuff => {
-                // Run HotStuff
-                let mut opt_path = Core::new(
-                    name,
-                    committee,
-                    parameters,
-                    signature_service,
-                    pk_set,
-                    store,
-                    leader_elector,
-                    mempool_driver,
-                    synchronizer,
-                    /* core_channel */ rx_core,
-                    tx_core,
-                    rx_smvba,
-                    tx_smvba,
-                    /* network_filter */ tx_filter,
-                    tx_filter_smvba,
-                    /* commit_channel */ tx_commit,
-                    true,
-                    false,
-                );
-                tokio::spawn(async move {
-                    opt_path.run_epoch().await;
-                });
-            }
-            Protocol::HotStuffAndSMVBA => {
-                // Run AsyncHotStuff
-                let mut opt_with_pes_path = Core::new(
-                    name,
-                    committee,
-                    parameters,
-                    signature_service,
-                    pk_set,
-                    store,
-                    leader_elector,
-                    mempool_driver,
-                    synchronizer,
-                    /* core_channel */ rx_core,
-                    tx_core,
-                    rx_smvba,
-                    tx_smvba,
-                    /* network_filter */ tx_filter,
-                    tx_filter_smvba,
-                    /* commit_channel */ tx_commit,
-                    true,
-                    true,
-                );
-                tokio::spawn(async move {
-                    opt_with_pes_path.run_epoch().await;
-                });
-            }
-            Protocol::SMVBA => {
-                // Run TwoChainVABA, which is just fallback with timeout=0, i.e., immediately send timeout after exiting a fallback
-                let mut pes_path = Core::new(
-                    name,
-                    committee,
-                    parameters,
-                    signature_service,
-                    pk_set,
-                    store,
-                    leader_elector,
-                    mempool_driver,
-                    synchronizer,
-                    /* core_channel */ rx_core,
-                    tx_core,
-                    rx_smvba,
-                    tx_smvba,
-                    /* network_filter */ tx_filter,
-                    tx_filter_smvba,
-                    /* commit_channel */ tx_commit,
-                    false,
-                    true,
-                );
-                tokio::spawn(async move {
-                    pes_path.run_epoch().await;
-                });
-            }
-            _ => {
-                return Ok(());
-            }
-        }
+        // HotStuff only runs the optimistic path, SMVBA only the pessimistic one.
+        let mut core = Core::new(
+            name,
+            committee,
+            parameters,
+            signature_service,
+            pk_set,
+            store,
+            leader_elector,
+            mempool_driver,
+            synchronizer,
+            /* core_channel */ rx_core,
+            tx_core,
+            rx_smvba,
+            tx_smvba,
+            /* network_filter */ tx_filter,
+            tx_filter_smvba,
+            /* commit_channel */ tx_commit,
+            protocol.optimistic(),
+            protocol.pessimistic(),
+            tuner,
+        );
+        shutdown.spawn(async move {
+            core.run_epoch().await;
+        });
 
         Ok(())
     }
@@ -1,6 +1,7 @@
 use crate::aggregator::Aggregator;
 use crate::config::{Committee, Parameters, Stake};
 use crate::error::{ConsensusError, ConsensusResult};
+use crate::evidence::{EquivocationDetector, Evidence};
 use crate::filter::FilterInput;
 use crate::leader::LeaderElector;
 use crate::mempool::MempoolDriver;
@@ -9,6 +10,7 @@ use crate::messages::{
     RandomnessShare, SPBProof, SPBValue, SPBVote, QC,
 };
 use crate::synchronizer::Synchronizer;
+use crate::tuning::Tuner;
 use async_recursion::async_recursion;
 use crypto::{Digest, PublicKey, SignatureService};
 use crypto::{Hash as _, Signature};
@@ -57,6 +59,34 @@ pub enum ConsensusMessage {
     FBPropose(Block),
     FBVote(HVote),
     FBLoopBack(Block),
+    Evidence(Evidence),
+}
+
+impl ConsensusMessage {
+    // The epoch and height the message belongs to.
+    pub fn slot(&self) -> Option<(SeqNumber, SeqNumber)> {
+        match self {
+            ConsensusMessage::HsPropose(block)
+            | ConsensusMessage::HsLoopBack(block)
+            | ConsensusMessage::SyncReply(block)
+            | ConsensusMessage::ParLoopBack(block)
+            | ConsensusMessage::FBPropose(block)
+            | ConsensusMessage::FBLoopBack(block) => Some((block.epoch, block.height)),
+            ConsensusMessage::HSVote(vote) | ConsensusMessage::FBVote(vote) => {
+                Some((vote.epoch, vote.height))
+            }
+            ConsensusMessage::SPBPropose(value, _) | ConsensusMessage::SPBFinsh(value, _) => {
+                Some((value.block.epoch, value.block.height))
+            }
+            ConsensusMessage::SPBVote(vote) => Some((vote.epoch, vote.height)),
+            ConsensusMessage::SPBDoneAndShare(done) => Some((done.epoch, done.height)),
+            ConsensusMessage::SMVBAPreVote(prevote) => Some((prevote.epoch, prevote.height)),
+            ConsensusMessage::SMVBAVote(mvote) => Some((mvote.epoch, mvote.height)),
+            ConsensusMessage::SMVBAHalt(halt) => Some((halt.epoch, halt.height)),
+            ConsensusMessage::ParPrePare(prepare) => Some((prepare.epoch, prepare.height)),
+            ConsensusMessage::SyncRequest(..) | ConsensusMessage::Evidence(_) => None,
+        }
+    }
 }
 
 pub struct Core {
@@ -83,6 +113,7 @@ pub struct Core {
     unhandle_message: VecDeque<(SeqNumber, ConsensusMessage)>,
     high_qc: QC,
     aggregator: Aggregator,
+    detector: EquivocationDetector,
     opt_path: bool,
     pes_path: bool,
     smvba_y_flag: HashMap<(SeqNumber, SeqNumber), bool>,
@@ -104,6 +135,7 @@ pub struct Core {
     par_prepare_pess: HashMap<SeqNumber, HashMap<PublicKey, Signature>>,
     fallback_length: SeqNumber,
     fallback_high_qc: HashMap<(SeqNumber, SeqNumber), Option<QC>>,
+    tuner: Tuner,
 }
 impl Core {
     #[allow(clippy::too_many_arguments)]
@@ -126,6 +158,7 @@ impl Core {
         commit_channel: Sender<Block>,
         opt_path: bool,
         pes_path: bool,
+        tuner: Tuner,
     ) -> Self {
         let aggregator = Aggregator::new(committee.clone());
         let fallback_length = parameters.fallback_length.clone();
@@ -153,6 +186,7 @@ impl Core {
             unhandle_message: VecDeque::new(),
             high_qc: QC::genesis(),
             aggregator,
+            detector: EquivocationDetector::default(),
             opt_path,
             pes_path,
             smvba_y_flag: HashMap::new(),
@@ -174,6 +208,7 @@ impl Core {
             par_prepare_pess: HashMap::new(),
             fallback_length,
             fallback_high_qc: HashMap::new(),
+            tuner,
         };
         core.update_smvba_state(1, 1);
         core.update_prepare_state(1);
@@ -185,6 +220,7 @@ impl Core {
         //清除之前的消息
         self.leader_elector = LeaderElector::new(self.committee.clone());
         self.aggregator = Aggregator::new(self.committee.clone());
+        self.detector = EquivocationDetector::default();
         self.height = 1;
         self.epoch = epoch;
         self.high_qc = QC::genesis();
@@ -276,13 +312,39 @@ impl Core {
                 Some(&sender),
                 &self.network_filter,
                 &self.committee,
-                OPT,
             )
             .await?;
         }
         Ok(())
     }
 
+    async fn handle_evidence(&mut self, evidence: Evidence) -> ConsensusResult<()> {
+        // The verifier made sure the evidence is valid before we accuse anyone.
+        // Persist it under its own key space, and gossip it the first time we see it
+        // so the application layer of every node can act on it.
+        let key = [b"evidence".to_vec(), evidence.digest().to_vec()].concat();
+        if self.store.read(key.clone()).await?.is_some() {
+            return Ok(());
+        }
+        warn!(
+            "Authority {} equivocated: {:?}",
+            evidence.offender(),
+            evidence
+        );
+        let value = bincode::serialize(&evidence).expect("Failed to serialize evidence");
+        self.store.write(key, value).await;
+
+        let message = ConsensusMessage::Evidence(evidence);
+        Synchronizer::transmit(
+            message,
+            &self.name,
+            None,
+            &self.network_filter,
+            &self.committee,
+        )
+        .await
+    }
+
     fn is_optmistic(&self) -> bool {
         return !self.parameters.ddos && !self.parameters.random_ddos;
     }
@@ -294,13 +356,13 @@ impl Core {
         round: SeqNumber,
         qc: Option<QC>,
         tag: u8,
-    ) -> Block {
+    ) -> Option<Block> {
         // Make a new block.
         let payload = self
             .mempool_driver
             .get(self.parameters.max_payload_size, tag)
             .await;
-        let block = Block::new(
+        let block = match Block::new(
             qc.unwrap_or(QC::genesis()),
             self.name,
             height,
@@ -310,7 +372,15 @@ impl Core {
             self.signature_service.clone(),
             tag,
         )
-        .await;
+        .await
+        {
+            Ok(block) => block,
+            Err(e) => {
+                // E.g. the signer refuses a second block at this height: skip the proposal.
+                warn!("Skipping proposal at height {}: {}", height, e);
+                return None;
+            }
+        };
 
         if !block.payload.is_empty() {
             info!(
@@ -333,7 +403,7 @@ impl Core {
         }
         debug!("Created {:?}", block);
 
-        block
+        Some(block)
     }
 
     #[async_recursion]
@@ -395,9 +465,14 @@ impl Core {
 
         // Cleanup the vote aggregator.
         self.aggregator.cleanup_hs_vote(&self.height);
+        // Fallback messages are accepted up to two heights back.
+        self.detector.cleanup(&self.height.saturating_sub(2));
         // Reset the timer and advance round.
         self.height = height + 1;
         debug!("Moved to round {}", self.height);
+        if let Some(parameters) = self.tuner.next_height() {
+            self.parameters = parameters;
+        }
         self.update_prepare_state(self.height);
         self.update_smvba_state(self.height, 1);
     }
@@ -413,7 +488,6 @@ impl Core {
             None,
             &self.network_filter,
             &self.committee,
-            OPT,
         )
         .await?;
         self.process_opt_block(&block).await?;
@@ -443,8 +517,16 @@ impl Core {
             }
         );
 
-        // Check the block is correctly formed.
-        block.verify(&self.committee)?;
+        // Only blocks within the window of the aggregator are remembered.
+        if self
+            .aggregator
+            .check_hs_height(block.height)
+            .unwrap_or(false)
+        {
+            if let Some(evidence) = self.detector.check_block(block) {
+                self.handle_evidence(evidence).await?;
+            }
+        }
 
         // 2. 终止 height-2 的 SMVBA
         if self.pes_path && self.height > 2 {
@@ -549,7 +631,6 @@ impl Core {
                         Some(&leader),
                         &self.network_filter,
                         &self.committee,
-                        OPT,
                     )
                     .await?;
                 } else {
@@ -562,7 +643,6 @@ impl Core {
                     None,
                     &self.network_filter,
                     &self.committee,
-                    OPT,
                 )
                 .await?;
                 self.handle_opt_vote(&vote).await?;
@@ -584,7 +664,10 @@ impl Core {
         // Ensure we won't vote for contradicting blocks.
         self.increase_last_voted_round(block.height);
         // TODO [issue #15]: Write to storage preferred_round and last_voted_round.
-        Some(HVote::new(&block, self.name, OPT, self.signature_service.clone()).await)
+        HVote::new(&block, self.name, OPT, self.signature_service.clone())
+            .await
+            .map_err(|e| warn!("Not voting for {}: {}", block, e))
+            .ok()
     }
 
     #[async_recursion]
@@ -595,8 +678,13 @@ impl Core {
             return Ok(());
         }
 
-        // Ensure the vote is well formed.
-        vote.verify(&self.committee)?;
+        // Votes the aggregator would not take are not remembered either.
+        if !self.aggregator.check_hs_height(vote.height)? {
+            return Ok(());
+        }
+        if let Some(evidence) = self.detector.check_vote(vote) {
+            self.handle_evidence(evidence).await?;
+        }
 
         // Add the new vote to our aggregator and see if we have a quorum.
         if let Some(qc) = self.aggregator.add_hs_vote(vote.clone())? {
@@ -607,10 +695,12 @@ impl Core {
 
             // Make a new block if we are the next leader.
             if self.name == self.leader_elector.get_leader(self.height) {
-                let block = self
+                if let Some(block) = self
                     .generate_proposal(self.height, 0, Some(self.high_qc.clone()), OPT)
-                    .await;
-                self.broadcast_opt_propose(block).await?;
+                    .await
+                {
+                    self.broadcast_opt_propose(block).await?;
+                }
             }
             if self.pes_path && !self.is_optmistic() {
                 self.invoke_fallback(self.height, Some(self.high_qc.clone()))
@@ -635,8 +725,9 @@ impl Core {
     }
 
     async fn invoke_fallback(&mut self, height: SeqNumber, qc: Option<QC>) -> ConsensusResult<()> {
-        let block = self.generate_proposal(height, 1, qc, PES).await;
-        self.broadcast_fallback_propose(block).await?;
+        if let Some(block) = self.generate_proposal(height, 1, qc, PES).await {
+            self.broadcast_fallback_propose(block).await?;
+        }
         Ok(())
     }
 
@@ -651,7 +742,6 @@ impl Core {
             None,
             &self.network_filter_smvba,
             &self.committee,
-            PES,
         )
         .await?;
         self.process_fallback_propose(&block).await?;
@@ -665,7 +755,10 @@ impl Core {
         if block.height + 2 <= self.height {
             return None;
         }
-        Some(HVote::new(&block, self.name, PES, self.signature_service.clone()).await)
+        HVote::new(&block, self.name, PES, self.signature_service.clone())
+            .await
+            .map_err(|e| warn!("Not voting for {}: {}", block, e))
+            .ok()
     }
 
     #[async_recursion]
@@ -675,19 +768,30 @@ impl Core {
             ConsensusError::TimeOutMessage(vote.epoch, vote.height)
         );
 
-        if self.parameters.exp == 1 {
-            vote.verify(&self.committee)?;
+        if !self
+            .aggregator
+            .check_fallback_vote(vote, self.fallback_length)?
+        {
+            return Ok(());
+        }
+        if let Some(evidence) = self.detector.check_vote(vote) {
+            self.handle_evidence(evidence).await?;
         }
 
-        if let Some(qc) = self.aggregator.add_fallback_vote(vote.clone())? {
+        if let Some(qc) = self
+            .aggregator
+            .add_fallback_vote(vote.clone(), self.fallback_length)?
+        {
             self.fallback_high_qc
                 .insert((qc.height, qc.round), Some(qc.clone()));
             if qc.proposer == self.name {
                 if qc.round < self.fallback_length {
-                    let block = self
+                    if let Some(block) = self
                         .generate_proposal(qc.height, qc.round + 1, Some(qc.clone()), PES)
-                        .await;
-                    self.broadcast_fallback_propose(block).await?;
+                        .await
+                    {
+                        self.broadcast_fallback_propose(block).await?;
+                    }
                 } else if qc.round == self.fallback_length {
                     //启动prepare
                     self.active_prepare_pahse(qc.height, qc, PES).await?;
@@ -704,10 +808,6 @@ impl Core {
             ConsensusError::TimeOutMessage(block.epoch, block.height)
         );
 
-        if self.parameters.exp == 1 {
-            block.verify(&self.committee)?
-        }
-
         if block.epoch > self.epoch {
             let b = block.clone();
             self.unhandle_message
@@ -746,7 +846,6 @@ impl Core {
                     Some(&block.author),
                     &self.network_filter_smvba,
                     &self.committee,
-                    PES,
                 )
                 .await?;
             } else {
@@ -791,7 +890,6 @@ impl Core {
             None,
             &self.network_filter_smvba,
             &self.committee,
-            PES,
         )
         .await?;
 
@@ -806,9 +904,6 @@ impl Core {
             prepare.epoch == self.epoch && prepare.height + 2 > self.height,
             ConsensusError::TimeOutMessage(prepare.epoch, prepare.height)
         );
-        if self.parameters.exp == 1 {
-            prepare.verify(&self.committee, self.fallback_length)?;
-        }
 
         let opt_set = self
             .par_prepare_opts
@@ -846,7 +941,6 @@ impl Core {
                         None,
                         &self.network_filter_smvba,
                         &self.committee,
-                        PES,
                     )
                     .await?;
                 }
@@ -909,19 +1003,24 @@ impl Core {
             phase: INIT_PHASE,
             round,
             shares: Vec::new(),
+            quorum_signature: None,
         };
 
         if self.spb_proposes.contains_key(&(height, 1)) {
             let last_value = self.spb_proposes.get(&(height, 1)).unwrap().clone();
 
-            let block = self
+            let block = match self
                 .generate_proposal(
                     height,
                     self.fallback_length + 1,
                     Some(last_value.block.qc.clone()),
                     PES,
                 )
-                .await;
+                .await
+            {
+                Some(block) => block,
+                None => return Ok(()),
+            };
 
             let value = SPBValue::new(
                 block,
@@ -931,9 +1030,7 @@ impl Core {
                 last_value.signatures.clone(),
             );
 
-            self.broadcast_pes_propose(value, proof)
-                .await
-                .expect("Failed to send the PES block");
+            self.broadcast_pes_propose(value, proof).await?;
         }
         Ok(())
     }
@@ -958,9 +1055,13 @@ impl Core {
         if val == OPT {
             block = Block::default();
         } else {
-            block = self
+            block = match self
                 .generate_proposal(height, self.fallback_length + 1, qc, PES)
-                .await;
+                .await
+            {
+                Some(block) => block,
+                None => return Ok(()),
+            };
         }
         // let block = self
         //     .generate_proposal(height, self.fallback_length + 1, qc, PES)
@@ -972,11 +1073,17 @@ impl Core {
             round,
             height,
             shares: Vec::new(),
+            quorum_signature: None,
         };
         self.broadcast_pes_propose(value, proof).await?;
         Ok(())
     }
 
+    // Our SMVBA round at this height (the first one until we start it).
+    fn smvba_round(&self, height: SeqNumber) -> SeqNumber {
+        *self.smvba_current_round.get(&height).unwrap_or(&1)
+    }
+
     fn smvba_msg_filter(
         &mut self,
         epoch: SeqNumber,
@@ -1020,7 +1127,6 @@ impl Core {
             None,
             &self.network_filter_smvba,
             &self.committee,
-            PES,
         )
         .await?;
 
@@ -1047,11 +1153,6 @@ impl Core {
             ConsensusError::TimeOutMessage(proof.height, proof.round)
         );
 
-        if self.parameters.exp == 1 {
-            //验证Proof是否正确
-            value.verify(&self.committee, &proof, self.fallback_length)?;
-        }
-
         if value.block.epoch > self.epoch {
             self.unhandle_message.push_back((
                 value.block.epoch,
@@ -1096,7 +1197,6 @@ impl Core {
                     Some(&value.block.author),
                     &self.network_filter_smvba,
                     &self.committee,
-                    PES,
                 )
                 .await?;
             } else {
@@ -1111,7 +1211,10 @@ impl Core {
         if value.phase > LOCK_PHASE {
             return None;
         }
-        Some(SPBVote::new(value.clone(), self.name, self.signature_service.clone()).await)
+        SPBVote::new(value.clone(), self.name, self.signature_service.clone())
+            .await
+            .map_err(|e| warn!("Not voting for {:?}: {}", value, e))
+            .ok()
     }
 
     #[async_recursion]
@@ -1128,10 +1231,17 @@ impl Core {
             ConsensusError::TimeOutMessage(spb_vote.height, spb_vote.round)
         );
 
-        if self.parameters.exp == 1 {
-            spb_vote.verify(&self.committee)?;
+        let current_round = self.smvba_round(spb_vote.height);
+        if !self.aggregator.check_spb_vote(spb_vote, current_round)? {
+            return Ok(());
+        }
+        if let Some(evidence) = self.detector.check_spb_vote(spb_vote) {
+            self.handle_evidence(evidence).await?;
         }
-        if let Some(proof) = self.aggregator.add_spb_vote(spb_vote.clone())? {
+        if let Some(proof) = self
+            .aggregator
+            .add_spb_vote(spb_vote.clone(), current_round)?
+        {
             debug!("Create spb proof {:?}!", proof);
 
             let mut value = self
@@ -1155,7 +1265,6 @@ impl Core {
                     None,
                     &self.network_filter_smvba,
                     &self.committee,
-                    PES,
                 )
                 .await?;
 
@@ -1174,10 +1283,6 @@ impl Core {
             ConsensusError::TimeOutMessage(proof.height, proof.round)
         );
 
-        if self.parameters.exp == 1 {
-            value.verify(&self.committee, &proof, self.fallback_length)?;
-        }
-
         self.spb_finishs
             .entry((proof.height, proof.round))
             .or_insert(HashMap::new())
@@ -1245,7 +1350,6 @@ impl Core {
             None,
             &self.network_filter_smvba,
             &self.committee,
-            PES,
         )
         .await?;
 
@@ -1261,10 +1365,6 @@ impl Core {
             ConsensusError::TimeOutMessage(prevote.height, prevote.round)
         );
 
-        if self.parameters.exp == 1 {
-            prevote.verify(&self.committee, self.fallback_length)?;
-        }
-
         let y_flag = self
             .smvba_y_flag
             .entry((prevote.height, prevote.round))
@@ -1329,7 +1429,6 @@ impl Core {
                 None,
                 &self.network_filter_smvba,
                 &self.committee,
-                PES,
             )
             .await?;
             self.handle_smvba_mvote(vote).await?;
@@ -1346,10 +1445,6 @@ impl Core {
             ConsensusError::TimeOutMessage(mvote.height, mvote.round)
         );
 
-        if self.parameters.exp == 1 {
-            mvote.verify(&self.committee, &self.pk_set, self.fallback_length)?;
-        }
-
         let set = self
             .smvba_votes
             .entry((mvote.height, mvote.round))
@@ -1361,7 +1456,8 @@ impl Core {
 
         match mvote.tag {
             MVoteTag::Yes(value, _, vote) => {
-                if let Some(fin_proof) = self.aggregator.add_pre_vote(vote)? {
+                let current_round = self.smvba_round(vote.height);
+                if let Some(fin_proof) = self.aggregator.add_pre_vote(vote, current_round)? {
                     let mhalt = MHalt::new(
                         self.name,
                         mvote.leader,
@@ -1378,7 +1474,6 @@ impl Core {
                         None,
                         &self.network_filter_smvba,
                         &self.committee,
-                        PES,
                     )
                     .await?;
                     self.handle_smvba_halt(mhalt).await?;
@@ -1404,10 +1499,6 @@ impl Core {
             ConsensusError::TimeOutMessage(mdone.height, mdone.round)
         );
 
-        if self.parameters.exp == 1 {
-            mdone.verify(&self.committee, &self.pk_set)?;
-        }
-
         let d_flag = self
             .smvba_d_flag
             .entry((mdone.height, mdone.round))
@@ -1447,10 +1538,6 @@ impl Core {
             ConsensusError::TimeOutMessage(share.height, share.round)
         );
 
-        if self.parameters.exp == 1 {
-            share.verify(&self.committee, &self.pk_set)?;
-        }
-
         if self
             .leader_elector
             .get_coin_leader(share.height, share.round)
@@ -1461,9 +1548,10 @@ impl Core {
         let height = share.height;
         let round = share.round;
 
-        if let Some(coin) = self
-            .aggregator
-            .add_smvba_random(share.clone(), &self.pk_set)?
+        let current_round = self.smvba_round(height);
+        if let Some(coin) =
+            self.aggregator
+                .add_smvba_random(share.clone(), &self.pk_set, current_round)?
         {
             debug!("Coin Leader {:?}", coin);
             self.leader_elector.add_random_coin(coin.clone());
@@ -1499,7 +1587,6 @@ impl Core {
                     None,
                     &self.network_filter_smvba,
                     &self.committee,
-                    PES,
                 )
                 .await?;
                 self.handle_smvba_halt(mhalt).await?;
@@ -1546,7 +1633,6 @@ impl Core {
                     None,
                     &self.network_filter_smvba,
                     &self.committee,
-                    PES,
                 )
                 .await?;
                 self.handle_smvba_prevote(pre_vote).await?;
@@ -1564,10 +1650,6 @@ impl Core {
             ConsensusError::TimeOutMessage(halt.height, halt.round)
         );
 
-        if self.parameters.exp == 1 {
-            halt.verify(&self.committee, &self.pk_set, self.fallback_length)?;
-        }
-
         if self.leader_elector.get_coin_leader(halt.height, halt.round)
             != Some(halt.value.block.author)
         // leader 是否与 finish value的proposer 相符
@@ -1648,17 +1730,17 @@ impl Core {
                         match msg {
                             ConsensusMessage::HsPropose(..) => {
                                 if let Err(e) = self.tx_core.send(msg).await {
-                                    panic!("Failed to send last epoch message: {}", e);
+                                    warn!("Failed to replay last epoch message: {}", e);
                                 }
                             }
                             ConsensusMessage::SPBPropose(..) => {
                                 if let Err(e) = self.tx_smvba.send(msg).await {
-                                    panic!("Failed to send last epoch message: {}", e);
+                                    warn!("Failed to replay last epoch message: {}", e);
                                 }
                             }
                             ConsensusMessage::FBPropose(..) => {
                                 if let Err(e) = self.tx_smvba.send(msg).await {
-                                    panic!("Failed to send last epoch message: {}", e);
+                                    warn!("Failed to replay last epoch message: {}", e);
                                 }
                             }
                             _ => break,
@@ -1675,21 +1757,26 @@ impl Core {
 
         if self.opt_path && self.name == self.leader_elector.get_leader(self.height) {
             //如果是leader就发送propose
-            let block = self
+            if let Some(block) = self
                 .generate_proposal(self.height, 0, Some(self.high_qc.clone()), OPT)
-                .await;
-            self.broadcast_opt_propose(block)
                 .await
-                .expect("Failed to send the first OPT block");
+            {
+                if let Err(e) = self.broadcast_opt_propose(block).await {
+                    warn!("Failed to send the first OPT block: {}", e);
+                }
+            }
         }
 
         if !self.opt_path || (self.pes_path && !self.is_optmistic()) {
             // self.invoke_smvba(self.height, OPT, Vec::new(), None)
             //     .await
             //     .expect("Failed to send the first PES block");
-            self.invoke_fallback(self.height, Some(self.high_qc.clone()))
+            if let Err(e) = self
+                .invoke_fallback(self.height, Some(self.high_qc.clone()))
                 .await
-                .expect("Failed to send the first PES block");
+            {
+                warn!("Failed to send the first PES block: {}", e);
+            }
         }
 
         // This is the main loop: it processes incoming blocks and votes,
@@ -1704,6 +1791,7 @@ impl Core {
                         ConsensusMessage::HsLoopBack(block) => self.process_opt_block(&block).await,
                         ConsensusMessage::SyncRequest(digest, sender) => self.handle_sync_request(digest, sender).await,
                         ConsensusMessage::SyncReply(block) => self.handle_opt_proposal(&block).await,
+                        ConsensusMessage::Evidence(evidence) => self.handle_evidence(evidence).await,
                         _=> Ok(()),
                     }
                 },
@@ -1724,6 +1812,10 @@ impl Core {
                         _=> Ok(()),
                     }
                 },
+                Some(tuning) = self.tuner.recv() => {
+                    self.tuner.schedule(tuning);
+                    Ok(())
+                },
                 else => break,
             };
             match result {
@@ -1,7 +1,8 @@
 use std::collections::HashSet;
 
+use crate::config::VerificationPolicy;
 use crate::core::SeqNumber;
-use crypto::{CryptoError, Digest, PublicKey};
+use crypto::{CryptoError, Digest, PublicKey, SignerError};
 use store::StoreError;
 use thiserror::Error;
 
@@ -40,6 +41,12 @@ pub enum ConsensusError {
     #[error("Node {0} is not in the committee")]
     NotInCommittee(PublicKey),
 
+    #[error("Unknown protocol '{0}', expected one of {1:?}")]
+    UnknownProtocol(String, Vec<&'static str>),
+
+    #[error("Invalid tuning: {0}")]
+    InvalidTuning(String),
+
     #[error("Phase Wrong value:{0} proof:{1}")]
     SPBPhaseWrong(u8, u8),
 
@@ -49,6 +56,9 @@ pub enum ConsensusError {
     #[error("Invalid signature")]
     InvalidSignature(#[from] CryptoError),
 
+    #[error("Signer error: {0}")]
+    SignerError(#[from] SignerError),
+
     #[error("Invalid threshold signature from {0}")]
     InvalidThresholdSignature(PublicKey),
 
@@ -70,6 +80,21 @@ pub enum ConsensusError {
     #[error("Received more than one vote from {0}")]
     AuthorityReuseinQC(PublicKey),
 
+    #[error("Received conflicting votes from {0} at height {1}")]
+    EquivocatingVote(PublicKey, SeqNumber),
+
+    #[error("Received vote for height {0} outside of the aggregation window")]
+    VoteOutOfWindow(SeqNumber),
+
+    #[error("Received vote for round {0} outside of the aggregation window")]
+    RoundOutOfWindow(SeqNumber),
+
+    #[error("Received vote for unknown phase {0}")]
+    InvalidPhase(u8),
+
+    #[error("Received invalid equivocation evidence against {0}")]
+    InvalidEvidence(PublicKey),
+
     #[error("Received more than one proof from {0} {1:?}")]
     AuthorityReuseinProof(PublicKey, HashSet<PublicKey>),
 
@@ -91,6 +116,12 @@ pub enum ConsensusError {
     #[error("Received QC without a quorum")]
     QCRequiresQuorum,
 
+    #[error("Invalid threshold signature in QC")]
+    InvalidQuorumSignature,
+
+    #[error("Verification policy {0:?} is only allowed in benchmark builds")]
+    RelaxedVerification(VerificationPolicy),
+
     #[error("Received TC without a quorum")]
     TCRequiresQuorum,
 
@@ -100,6 +131,9 @@ pub enum ConsensusError {
     #[error("Received SPBVote without a quorum")]
     SPBRequiresQuorum,
 
+    #[error("Received SPB proof for another value")]
+    InvalidSPBProof,
+
     #[error("Malformed block {0}")]
     MalformedBlock(Digest),
 
@@ -113,6 +147,9 @@ pub enum ConsensusError {
     #[error("Invalid payload")]
     InvalidPayload,
 
+    #[error("Channel to {0} is closed")]
+    ChannelClosed(&'static str),
+
     #[error("Block rounds not consecutive! rounds {rd1}, {rd2} and {rd3}")]
     NonConsecutiveRounds {
         rd1: SeqNumber,
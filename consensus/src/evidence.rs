@@ -0,0 +1,190 @@
+use crate::config::Committee;
+use crate::core::{SeqNumber, OPT};
+use crate::error::{ConsensusError, ConsensusResult};
+use crate::messages::{Block, HVote, SPBVote};
+use crypto::{Digest, Domain, Hash, PublicKey};
+use ed25519_dalek::Digest as _;
+use ed25519_dalek::Sha512;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fmt;
+
+#[cfg(test)]
+#[path = "tests/evidence_tests.rs"]
+pub mod evidence_tests;
+
+// Two conflicting messages signed by the same authority for the same slot.
+#[derive(Serialize, Deserialize, Clone)]
+pub enum Evidence {
+    ConflictingBlocks(Block, Block),
+    ConflictingVotes(HVote, HVote),
+    ConflictingSPBVotes(SPBVote, SPBVote),
+}
+
+impl Evidence {
+    pub fn offender(&self) -> PublicKey {
+        match self {
+            Evidence::ConflictingBlocks(x, _) => x.author,
+            Evidence::ConflictingVotes(x, _) => x.author,
+            Evidence::ConflictingSPBVotes(x, _) => x.author,
+        }
+    }
+
+    pub fn verify(&self, committee: &Committee) -> ConsensusResult<()> {
+        match self {
+            Evidence::ConflictingBlocks(a, b) => {
+                // The block digest does not cover the fallback round, so only
+                // HotStuff blocks make verifiable evidence.
+                ensure!(
+                    block_slot(a) == block_slot(b) && a.tag == OPT && a.digest() != b.digest(),
+                    ConsensusError::InvalidEvidence(a.author)
+                );
+                ensure!(
+                    committee.stake(&a.author) > 0,
+                    ConsensusError::UnknownAuthority(a.author)
+                );
+                a.signature
+                    .verify_in(Domain::Block, &a.digest(), &a.author)?;
+                b.signature
+                    .verify_in(Domain::Block, &b.digest(), &b.author)?;
+            }
+            Evidence::ConflictingVotes(a, b) => {
+                ensure!(
+                    vote_slot(a) == vote_slot(b) && a.hash != b.hash,
+                    ConsensusError::InvalidEvidence(a.author)
+                );
+                a.verify(committee)?;
+                b.verify(committee)?;
+            }
+            Evidence::ConflictingSPBVotes(a, b) => {
+                ensure!(
+                    spb_vote_slot(a) == spb_vote_slot(b) && a.hash != b.hash,
+                    ConsensusError::InvalidEvidence(a.author)
+                );
+                a.verify(committee)?;
+                b.verify(committee)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Hash for Evidence {
+    fn digest(&self) -> Digest {
+        let (tag, mut digests) = match self {
+            Evidence::ConflictingBlocks(a, b) => (0u8, [a.digest(), b.digest()]),
+            Evidence::ConflictingVotes(a, b) => (1u8, [a.digest(), b.digest()]),
+            Evidence::ConflictingSPBVotes(a, b) => (2u8, [a.digest(), b.digest()]),
+        };
+        // The same pair of messages makes the same evidence in any order.
+        digests.sort_by(|x, y| x.0.cmp(&y.0));
+        let mut hasher = Sha512::new();
+        hasher.update(tag.to_le_bytes());
+        for x in &digests {
+            hasher.update(x);
+        }
+        Digest(hasher.finalize().as_slice()[..32].try_into().unwrap())
+    }
+}
+
+impl fmt::Debug for Evidence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            Evidence::ConflictingBlocks(a, b) => {
+                write!(f, "Evidence(blocks {:?}, {:?})", a, b)
+            }
+            Evidence::ConflictingVotes(a, b) => write!(f, "Evidence(votes {:?}, {:?})", a, b),
+            Evidence::ConflictingSPBVotes(a, b) => {
+                write!(f, "Evidence(spb votes {:?}, {:?})", a, b)
+            }
+        }
+    }
+}
+
+type BlockSlot = (PublicKey, SeqNumber, SeqNumber, u8); // author, epoch, height, tag
+type VoteSlot = (PublicKey, SeqNumber, SeqNumber, SeqNumber, u8, PublicKey); // author, epoch, height, round, tag/phase, proposer
+
+fn block_slot(block: &Block) -> BlockSlot {
+    (block.author, block.epoch, block.height, block.tag)
+}
+
+fn vote_slot(vote: &HVote) -> VoteSlot {
+    (
+        vote.author,
+        vote.epoch,
+        vote.height,
+        vote.round,
+        vote.tag,
+        vote.proposer,
+    )
+}
+
+fn spb_vote_slot(vote: &SPBVote) -> VoteSlot {
+    (
+        vote.author,
+        vote.epoch,
+        vote.height,
+        vote.round,
+        vote.phase,
+        vote.proposer,
+    )
+}
+
+// Remembers the first message of each authority per slot and packages a second,
+// conflicting one into evidence.
+#[derive(Default)]
+pub struct EquivocationDetector {
+    blocks: HashMap<BlockSlot, Block>,
+    votes: HashMap<VoteSlot, HVote>,
+    spb_votes: HashMap<VoteSlot, SPBVote>,
+}
+
+impl EquivocationDetector {
+    pub fn check_block(&mut self, block: &Block) -> Option<Evidence> {
+        if block.tag != OPT {
+            return None;
+        }
+        let first = self
+            .blocks
+            .entry(block_slot(block))
+            .or_insert_with(|| block.clone());
+        match first.digest() == block.digest() {
+            true => None,
+            false => Some(Evidence::ConflictingBlocks(first.clone(), block.clone())),
+        }
+    }
+
+    pub fn check_vote(&mut self, vote: &HVote) -> Option<Evidence> {
+        let first = self
+            .votes
+            .entry(vote_slot(vote))
+            .or_insert_with(|| vote.clone());
+        match first.hash == vote.hash {
+            true => None,
+            false => Some(Evidence::ConflictingVotes(first.clone(), vote.clone())),
+        }
+    }
+
+    pub fn check_spb_vote(&mut self, vote: &SPBVote) -> Option<Evidence> {
+        let first = self
+            .spb_votes
+            .entry(spb_vote_slot(vote))
+            .or_insert_with(|| vote.clone());
+        match first.hash == vote.hash {
+            true => None,
+            false => Some(Evidence::ConflictingSPBVotes(first.clone(), vote.clone())),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty() && self.votes.is_empty() && self.spb_votes.is_empty()
+    }
+
+    pub fn cleanup(&mut self, height: &SeqNumber) {
+        self.blocks.retain(|(_, _, h, _), _| h > height);
+        self.votes.retain(|(_, _, h, ..), _| h > height);
+        self.spb_votes.retain(|(_, _, h, ..), _| h > height);
+    }
+}
@@ -1,47 +1,81 @@
 use crate::config::Parameters;
-use crate::core::ConsensusMessage;
+use crate::core::{ConsensusMessage, SeqNumber};
 use bytes::Bytes;
 use futures::stream::futures_unordered::FuturesUnordered;
 use futures::stream::StreamExt as _;
-use network::NetMessage;
+use network::{CancelHandler, MuxSender};
 use rand::Rng;
+use std::collections::BTreeMap;
 use std::net::SocketAddr;
-use tokio::sync::mpsc::{Receiver, Sender};
+use tokio::sync::mpsc::Receiver;
+use tokio::sync::watch;
 use tokio::time::{sleep, Duration};
 
 pub type FilterInput = (ConsensusMessage, Vec<SocketAddr>);
 
+// PES messages are retransmitted until acknowledged, or until we sent messages
+// this many heights ahead of them.
+const PES_GC_DEPTH: SeqNumber = 2;
+
 pub struct Filter;
 
 impl Filter {
     pub fn run(
         mut core: Receiver<FilterInput>,
         mut core_smvba: Receiver<FilterInput>,
-        network: Sender<NetMessage>,
-        net_smvba: Sender<NetMessage>,
-        parameters: Parameters,
+        network: MuxSender,   // OPT messages are best effort, see `transmit`
+        net_smvba: MuxSender, // SMVBA assumes reliable links
+        parameters: watch::Receiver<Parameters>, // see `Tuner`
     ) {
         tokio::spawn(async move {
             let mut pending = FuturesUnordered::new();
             let mut pending_smvba = FuturesUnordered::new();
+            let mut cancel_handlers = BTreeMap::new();
             loop {
                 tokio::select! {
-                    Some(input) = core.recv() => pending.push(Self::delay(input, parameters.clone())),
-                    Some(input) = core_smvba.recv() => pending_smvba.push(Self::delay(input, parameters.clone())),
+                    Some(input) = core.recv() => {
+                        pending.push(Self::delay(input, parameters.borrow().clone()))
+                    },
+                    Some(input) = core_smvba.recv() => {
+                        pending_smvba.push(Self::delay(input, parameters.borrow().clone()))
+                    },
                     Some(input) = pending.next() => Self::transmit(input, &network).await,
-                    Some(input) = pending_smvba.next() => Self::transmit(input, &net_smvba).await,
+                    Some(input) = pending_smvba.next() => {
+                        Self::transmit_reliable(input, &net_smvba, &mut cancel_handlers).await
+                    },
                     else => break
                 }
             }
         });
     }
 
-    async fn transmit(input: FilterInput, network: &Sender<NetMessage>) {
+    // The timeouts and the synchronizer recover the OPT messages dropped when
+    // the network lags.
+    async fn transmit(input: FilterInput, network: &MuxSender) {
         let (message, addresses) = input;
         let bytes = bincode::serialize(&message).expect("Failed to serialize core message");
-        let net_message = NetMessage(Bytes::from(bytes), addresses);
-        if let Err(e) = network.send(net_message).await {
-            panic!("Failed to send block through network channel: {}", e);
+        network.broadcast(addresses, Bytes::from(bytes)).await;
+    }
+
+    async fn transmit_reliable(
+        input: FilterInput,
+        sender: &MuxSender,
+        cancel_handlers: &mut BTreeMap<(SeqNumber, SeqNumber), Vec<CancelHandler>>,
+    ) {
+        let (message, addresses) = input;
+        let bytes = bincode::serialize(&message).expect("Failed to serialize core message");
+        let handlers = sender
+            .broadcast_reliable(addresses, Bytes::from(bytes))
+            .await;
+
+        // Stop retransmitting the messages of the heights we moved past.
+        if let Some((epoch, height)) = message.slot() {
+            cancel_handlers
+                .entry((epoch, height))
+                .or_default()
+                .extend(handlers);
+            let oldest = (epoch, height.saturating_sub(PES_GC_DEPTH));
+            *cancel_handlers = cancel_handlers.split_off(&oldest);
         }
     }
 
@@ -4,19 +4,26 @@ mod aggregator;
 mod config;
 mod consensus;
 mod core;
+mod evidence;
 mod filter;
 mod leader;
 mod mempool;
 mod messages;
+mod protocol;
 mod synchronizer;
+mod tuning;
+mod verifier;
 
 #[cfg(test)]
 #[path = "tests/common.rs"]
 mod common;
 
-pub use crate::config::{Committee, Parameters, Protocol};
+pub use crate::config::{Committee, Parameters, Stake, VerificationPolicy};
 pub use crate::consensus::Consensus;
 pub use crate::core::{ConsensusMessage, SeqNumber, OPT, PES};
 pub use crate::error::ConsensusError;
+pub use crate::evidence::Evidence;
 pub use crate::mempool::{ConsensusMempoolMessage, PayloadStatus};
 pub use crate::messages::{Block, QC};
+pub use crate::protocol::{ConsensusProtocol, Protocol};
+pub use crate::tuning::Tuning;
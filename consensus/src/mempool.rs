@@ -2,6 +2,7 @@ use crate::core::SeqNumber;
 use crate::error::{ConsensusError, ConsensusResult};
 use crate::messages::Block;
 use crypto::Digest;
+use log::warn;
 use tokio::sync::mpsc::Sender;
 use tokio::sync::oneshot;
 
@@ -31,26 +32,22 @@ impl MempoolDriver {
     pub async fn get(&mut self, max: usize, tag: u8) -> Vec<Digest> {
         let (sender, receiver) = oneshot::channel();
         let message = ConsensusMempoolMessage::Get(max, sender, tag);
-        self.mempool_channel
-            .send(message)
-            .await
-            .expect("Failed to send message to mempool");
-        receiver
-            .await
-            .expect("Failed to receive payload from mempool")
+        if self.mempool_channel.send(message).await.is_err() {
+            warn!("Mempool is gone, proposing an empty block");
+            return Vec::new();
+        }
+        receiver.await.unwrap_or_default()
     }
 
     pub async fn verify(&mut self, block: Block, tag: u8) -> ConsensusResult<bool> {
         let (sender, receiver) = oneshot::channel();
         let message = ConsensusMempoolMessage::Verify(Box::new(block), sender, tag);
+        let closed = || ConsensusError::ChannelClosed("mempool");
         self.mempool_channel
             .send(message)
             .await
-            .expect("Failed to send message to mempool");
-        match receiver
-            .await
-            .expect("Failed to receive payload status from mempool")
-        {
+            .map_err(|_| closed())?;
+        match receiver.await.map_err(|_| closed())? {
             PayloadStatus::Accept => Ok(true),
             PayloadStatus::Reject => Err(ConsensusError::InvalidPayload),
             PayloadStatus::Wait => Ok(false),
@@ -75,9 +72,8 @@ impl MempoolDriver {
     pub async fn cleanup_par(&mut self, b0: &Block) {
         let digests = b0.payload.iter().cloned().collect();
         let message = ConsensusMempoolMessage::Cleanup(digests, b0.height);
-        self.mempool_channel
-            .send(message)
-            .await
-            .expect("Failed to send message to mempool");
+        if self.mempool_channel.send(message).await.is_err() {
+            warn!("Mempool is gone, skipping cleanup");
+        }
     }
 }
@@ -1,14 +1,17 @@
-use crate::config::Committee;
+use crate::config::{Committee, Stake};
 use crate::core::{SeqNumber, FIN_PHASE, INIT_PHASE, LOCK_PHASE, OPT, PES};
 use crate::error::{ConsensusError, ConsensusResult};
-use crypto::{Digest, Hash, PublicKey, Signature, SignatureService};
+use crypto::{
+    BlockRequest, Digest, Domain, Hash, PublicKey, SPBVoteRequest, Signature, SignatureService,
+    VoteRequest,
+};
 use ed25519_dalek::Digest as _;
 use ed25519_dalek::Sha512;
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
 use std::convert::TryInto;
 use std::fmt::{self};
-use threshold_crypto::{PublicKeySet, SignatureShare};
+use threshold_crypto::{PublicKeySet, Signature as TssSignature, SignatureShare};
 
 #[cfg(test)]
 #[path = "tests/messages_tests.rs"]
@@ -37,7 +40,7 @@ impl Block {
         payload: Vec<Digest>,
         mut signature_service: SignatureService,
         tag: u8,
-    ) -> Self {
+    ) -> ConsensusResult<Self> {
         let block = Self {
             qc,
             author,
@@ -49,8 +52,23 @@ impl Block {
             tag,
         };
 
-        let signature = signature_service.request_signature(block.digest()).await;
-        Self { signature, ..block }
+        // The signer makes sure we never propose two HotStuff blocks at the same height.
+        let signature = signature_service
+            .request_block_signature(block.request())
+            .await?;
+        Ok(Self { signature, ..block })
+    }
+
+    // What the signer hashes and signs.
+    pub fn request(&self) -> BlockRequest {
+        BlockRequest {
+            author: self.author,
+            height: self.height,
+            epoch: self.epoch,
+            payload: self.payload.clone(),
+            parent: self.qc.hash.clone(),
+            tag: self.tag,
+        }
     }
 
     pub fn genesis() -> Self {
@@ -70,7 +88,8 @@ impl Block {
         );
 
         // Check the signature.
-        self.signature.verify(&self.digest(), &self.author)?;
+        self.signature
+            .verify_in(Domain::Block, &self.digest(), &self.author)?;
 
         // Check the embedded QC.
         if self.qc != QC::genesis() {
@@ -83,16 +102,7 @@ impl Block {
 
 impl Hash for Block {
     fn digest(&self) -> Digest {
-        let mut hasher = Sha512::new();
-        hasher.update(self.author.0);
-        hasher.update(self.height.to_le_bytes());
-        hasher.update(self.epoch.to_le_bytes());
-        for x in &self.payload {
-            hasher.update(x);
-        }
-        hasher.update(&self.qc.hash);
-        hasher.update(self.tag.to_le_bytes());
-        Digest(hasher.finalize().as_slice()[..32].try_into().unwrap())
+        self.request().digest()
     }
 }
 
@@ -127,6 +137,7 @@ pub struct HVote {
     pub tag: u8,
     pub author: PublicKey,
     pub signature: Signature,
+    pub signature_share: Option<SignatureShare>, // share of the quorum key for threshold QCs
 }
 
 impl HVote {
@@ -135,7 +146,7 @@ impl HVote {
         author: PublicKey,
         tag: u8,
         mut signature_service: SignatureService,
-    ) -> Self {
+    ) -> ConsensusResult<Self> {
         let vote = Self {
             hash: block.digest(),
             height: block.height,
@@ -145,20 +156,54 @@ impl HVote {
             author,
             tag,
             signature: Signature::default(),
+            signature_share: None,
         };
-        let signature = signature_service.request_signature(vote.digest()).await;
-        Self { signature, ..vote }
+        let (signature, signature_share) = signature_service
+            .request_vote_signature(vote.request())
+            .await?;
+        Ok(Self {
+            signature,
+            signature_share,
+            ..vote
+        })
+    }
+
+    pub fn request(&self) -> VoteRequest {
+        VoteRequest {
+            hash: self.hash.clone(),
+            height: self.height,
+            round: self.round,
+            epoch: self.epoch,
+            tag: self.tag,
+            proposer: self.proposer,
+        }
     }
 
     pub fn verify(&self, committee: &Committee) -> ConsensusResult<()> {
+        self.verify_author(committee)?;
+
+        // Check the signature.
+        self.signature
+            .verify_in(Domain::Vote, &self.digest(), &self.author)?;
+        Ok(())
+    }
+
+    // Every check but the ed25519 signature, which may be verified in a batch.
+    pub fn verify_author(&self, committee: &Committee) -> ConsensusResult<()> {
         // Ensure the authority has voting rights.
         ensure!(
             committee.stake(&self.author) > 0,
             ConsensusError::UnknownAuthority(self.author)
         );
 
-        // Check the signature.
-        self.signature.verify(&self.digest(), &self.author)?;
+        // Check the share of the quorum key, if any.
+        if let (Some(keys), Some(share)) = (&committee.quorum_keys, &self.signature_share) {
+            let pk_share = keys.public_key_share(committee.id(self.author));
+            ensure!(
+                pk_share.verify(share, &self.digest()),
+                ConsensusError::InvalidThresholdSignature(self.author)
+            );
+        }
         Ok(())
     }
 }
@@ -166,14 +211,7 @@ impl HVote {
 impl Hash for HVote {
     fn digest(&self) -> Digest {
         //与QC对应
-        let mut hasher = Sha512::new();
-        hasher.update(&self.hash);
-        hasher.update(self.height.to_le_bytes());
-        hasher.update(self.round.to_le_bytes());
-        hasher.update(self.tag.to_le_bytes());
-        hasher.update(self.epoch.to_le_bytes());
-        hasher.update(self.proposer.0);
-        Digest(hasher.finalize().as_slice()[..32].try_into().unwrap())
+        self.request().digest()
     }
 }
 
@@ -351,22 +389,27 @@ impl SPBValue {
 
         self.block.verify(committee)?;
 
-        proof.verify(committee)?;
+        proof.verify(self, committee)?;
 
         Ok(())
     }
-}
 
-impl Hash for SPBValue {
-    fn digest(&self) -> Digest {
+    // The digest of the value as it was at `phase`.
+    pub fn digest_at(&self, phase: u8) -> Digest {
         let mut hasher = Sha512::new();
         hasher.update(self.block.digest());
         hasher.update(self.round.to_le_bytes());
-        hasher.update(self.phase.to_le_bytes());
+        hasher.update(phase.to_le_bytes());
         Digest(hasher.finalize().as_slice()[..32].try_into().unwrap())
     }
 }
 
+impl Hash for SPBValue {
+    fn digest(&self) -> Digest {
+        self.digest_at(self.phase)
+    }
+}
+
 impl fmt::Debug for SPBValue {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         write!(
@@ -387,6 +430,7 @@ pub struct SPBVote {
     pub proposer: PublicKey,
     pub author: PublicKey,
     pub signature: Signature,
+    pub signature_share: Option<SignatureShare>, // share of the quorum key for threshold proofs
 }
 
 impl SPBVote {
@@ -394,8 +438,8 @@ impl SPBVote {
         value: SPBValue,
         author: PublicKey,
         mut signature_service: SignatureService,
-    ) -> Self {
-        let mut vote = Self {
+    ) -> ConsensusResult<Self> {
+        let vote = Self {
             hash: value.digest(),
             phase: value.phase,
             height: value.block.height,
@@ -404,25 +448,69 @@ impl SPBVote {
             proposer: value.block.author,
             author,
             signature: Signature::default(),
+            signature_share: None,
+        };
+        let (signature, signature_share) = signature_service
+            .request_spb_vote_signature(vote.request())
+            .await?;
+        Ok(Self {
+            signature,
+            signature_share,
+            ..vote
+        })
+    }
+
+    pub fn request(&self) -> SPBVoteRequest {
+        SPBVoteRequest {
+            hash: self.hash.clone(),
+            phase: self.phase,
+            height: self.height,
+            epoch: self.epoch,
+            round: self.round,
+            proposer: self.proposer,
+        }
+    }
+
+    // The digest signed by the votes for `value` at `phase`.
+    pub fn digest_for(value: &SPBValue, phase: u8) -> Digest {
+        let vote = Self {
+            hash: value.digest_at(phase),
+            phase,
+            height: value.block.height,
+            epoch: value.block.epoch,
+            round: value.round,
+            proposer: value.block.author,
+            author: PublicKey::default(),
+            signature: Signature::default(),
+            signature_share: None,
         };
-        vote.signature = signature_service.request_signature(vote.digest()).await;
-        return vote;
+        vote.digest()
     }
 
     //验证门限签名是否正确
     pub fn verify(&self, committee: &Committee) -> ConsensusResult<()> {
+        self.verify_author(committee)?;
+        self.signature
+            .verify_in(Domain::SPBVote, &self.digest(), &self.author)?;
+        Ok(())
+    }
+
+    // See `HVote::verify_author`.
+    pub fn verify_author(&self, committee: &Committee) -> ConsensusResult<()> {
         // Ensure the authority has voting rights.
         ensure!(
             committee.stake(&self.author) > 0,
             ConsensusError::UnknownAuthority(self.author)
         );
-        self.signature.verify(&self.digest(), &self.author)?;
-        // let tss_pk = pk_set.public_key_share(committee.id(self.author));
-        // // Check the signature.
-        // ensure!(
-        //     tss_pk.verify(&self.signature_share, &self.digest()),
-        //     ConsensusError::InvalidThresholdSignature(self.author)
-        // );
+
+        // Check the share of the quorum key, if any.
+        if let (Some(keys), Some(share)) = (&committee.quorum_keys, &self.signature_share) {
+            let pk_share = keys.public_key_share(committee.id(self.author));
+            ensure!(
+                pk_share.verify(share, &self.digest()),
+                ConsensusError::InvalidThresholdSignature(self.author)
+            );
+        }
 
         Ok(())
     }
@@ -430,10 +518,8 @@ impl SPBVote {
 
 impl Hash for SPBVote {
     fn digest(&self) -> Digest {
-        let mut hasher = Sha512::new();
-        hasher.update(self.hash.clone());
-        hasher.update(self.phase.to_be_bytes());
-        Digest(hasher.finalize().as_slice()[..32].try_into().unwrap())
+        // Bind the slot of the vote so conflicting votes make verifiable evidence.
+        self.request().digest()
     }
 }
 
@@ -453,20 +539,38 @@ pub struct SPBProof {
     pub round: SeqNumber,
     pub height: SeqNumber,
     pub shares: Vec<SPBVote>,
+    pub quorum_signature: Option<QuorumSignature>, // replaces `shares` in threshold proofs
 }
 
 impl SPBProof {
-    pub fn verify(&self, committee: &Committee) -> ConsensusResult<()> {
+    // A proof of phase p gathers the votes for `value` at phase p-1.
+    pub fn verify(&self, value: &SPBValue, committee: &Committee) -> ConsensusResult<()> {
         if self.phase <= INIT_PHASE {
             //第一阶段不做检查
             return Ok(());
         }
+        ensure!(
+            self.height == value.block.height && self.round == value.round,
+            ConsensusError::InvalidSPBProof
+        );
+        let digest = SPBVote::digest_for(value, self.phase - 1);
+
+        if let Some(quorum_signature) = &self.quorum_signature {
+            ensure!(
+                quorum_signature.weight(committee) >= committee.quorum_threshold(),
+                ConsensusError::SPBRequiresQuorum
+            );
+            return quorum_signature.verify(&digest, committee);
+        }
 
         if self.phase >= LOCK_PHASE {
             //检查门限签名是否正确
             let mut weight = 0;
+            let mut used = HashSet::new();
             for share in self.shares.iter() {
                 let name = share.author;
+                ensure!(used.insert(name), ConsensusError::AuthorityReuseinSPB(name));
+                ensure!(share.digest() == digest, ConsensusError::InvalidSPBProof);
                 let voting_rights = committee.stake(&name);
                 ensure!(voting_rights > 0, ConsensusError::UnknownAuthority(name));
                 weight += voting_rights;
@@ -698,9 +802,9 @@ impl MVoteTag {
         value: SPBValue,
         proof: SPBProof,
         signature_service: SignatureService,
-    ) -> Self {
-        let spbvote = SPBVote::new(value.clone(), author, signature_service).await;
-        Self::Yes(value, proof, spbvote)
+    ) -> ConsensusResult<Self> {
+        let spbvote = SPBVote::new(value.clone(), author, signature_service).await?;
+        Ok(Self::Yes(value, proof, spbvote))
     }
 
     pub async fn new_no() -> Self {
@@ -905,6 +1009,56 @@ impl fmt::Debug for MHalt {
     }
 }
 
+// A threshold signature of the quorum key, with the bitmap of the signers' tss ids.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct QuorumSignature {
+    pub signers: Vec<u8>,
+    pub signature: TssSignature,
+}
+
+impl QuorumSignature {
+    pub fn new<'a, I>(shares: I, keys: &PublicKeySet, size: usize) -> Option<Self>
+    where
+        I: IntoIterator<Item = (&'a usize, &'a SignatureShare)> + Clone,
+    {
+        let signature = keys.combine_signatures(shares.clone()).ok()?;
+        let mut signers = vec![0u8; (size + 7) / 8];
+        for (id, _) in shares {
+            signers[id / 8] |= 1 << (id % 8);
+        }
+        Some(Self { signers, signature })
+    }
+
+    pub fn signed(&self, id: usize) -> bool {
+        self.signers
+            .get(id / 8)
+            .map_or(false, |x| x & (1 << (id % 8)) != 0)
+    }
+
+    pub fn weight(&self, committee: &Committee) -> Stake {
+        committee
+            .authorities
+            .values()
+            .filter(|x| self.signed(x.id))
+            .map(|x| x.stake)
+            .sum()
+    }
+
+    // The quorum key has threshold 2f: a valid signature implies a quorum of
+    // shares (with equal stakes), the bitmap only tells who signed.
+    pub fn verify(&self, digest: &Digest, committee: &Committee) -> ConsensusResult<()> {
+        let keys = committee
+            .quorum_keys
+            .as_ref()
+            .ok_or(ConsensusError::InvalidQuorumSignature)?;
+        ensure!(
+            keys.public_key().verify(&self.signature, digest),
+            ConsensusError::InvalidQuorumSignature
+        );
+        Ok(())
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize, Default)]
 pub struct QC {
     pub hash: Digest,
@@ -915,6 +1069,7 @@ pub struct QC {
     pub proposer: PublicKey, // proposer of the block
     pub acceptor: PublicKey, // Node that accepts the QC and builds its f-chain extending it
     pub votes: Vec<(PublicKey, Signature)>,
+    pub quorum_signature: Option<QuorumSignature>, // replaces `votes` in threshold QCs
 }
 
 impl QC {
@@ -927,6 +1082,14 @@ impl QC {
     }
 
     pub fn verify(&self, committee: &Committee) -> ConsensusResult<()> {
+        if let Some(quorum_signature) = &self.quorum_signature {
+            ensure!(
+                quorum_signature.weight(committee) >= committee.quorum_threshold(),
+                ConsensusError::QCRequiresQuorum
+            );
+            return quorum_signature.verify(&self.digest(), committee);
+        }
+
         // Ensure the QC has a quorum.
         let mut weight = 0; //票数
         let mut used = HashSet::new(); //防止重复统计
@@ -946,7 +1109,8 @@ impl QC {
         );
 
         // Check the signatures.
-        Signature::verify_batch(&self.digest(), &self.votes).map_err(ConsensusError::from)?;
+        Signature::verify_batch_in(Domain::Vote, &self.digest(), &self.votes)
+            .map_err(ConsensusError::from)?;
         Ok(())
     }
 }
@@ -954,14 +1118,15 @@ impl QC {
 impl Hash for QC {
     fn digest(&self) -> Digest {
         //与HVote对应
-        let mut hasher = Sha512::new();
-        hasher.update(&self.hash);
-        hasher.update(self.height.to_le_bytes());
-        hasher.update(self.round.to_le_bytes());
-        hasher.update(self.tag.to_le_bytes());
-        hasher.update(self.epoch.to_le_bytes());
-        hasher.update(self.proposer.0);
-        Digest(hasher.finalize().as_slice()[..32].try_into().unwrap())
+        VoteRequest {
+            hash: self.hash.clone(),
+            height: self.height,
+            round: self.round,
+            epoch: self.epoch,
+            tag: self.tag,
+            proposer: self.proposer,
+        }
+        .digest()
     }
 }
 
@@ -0,0 +1,175 @@
+use crate::error::ConsensusError;
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+#[cfg(test)]
+#[path = "tests/protocol_tests.rs"]
+pub mod protocol_tests;
+
+// A protocol the consensus core can run, in terms of the paths it takes. New
+// protocols implement this trait and are added to `PROTOCOLS`, after which they
+// can be selected by name.
+pub trait ConsensusProtocol: Sync {
+    // The name in the parameters file and on the command line.
+    fn name(&self) -> &'static str;
+
+    // Whether the core runs the optimistic path (2-chain HotStuff).
+    fn optimistic(&self) -> bool;
+
+    // Whether the core runs the pessimistic path (SMVBA).
+    fn pessimistic(&self) -> bool;
+}
+
+struct HotStuff;
+
+impl ConsensusProtocol for HotStuff {
+    fn name(&self) -> &'static str {
+        "HotStuff"
+    }
+
+    fn optimistic(&self) -> bool {
+        true
+    }
+
+    fn pessimistic(&self) -> bool {
+        false
+    }
+}
+
+// Falls back to SMVBA when the optimistic path stalls.
+struct HotStuffAndSmvba;
+
+impl ConsensusProtocol for HotStuffAndSmvba {
+    fn name(&self) -> &'static str {
+        "HotStuffAndSMVBA"
+    }
+
+    fn optimistic(&self) -> bool {
+        true
+    }
+
+    fn pessimistic(&self) -> bool {
+        true
+    }
+}
+
+// 2-chain VABA: a fallback with timeout 0, entered again as soon as it exits.
+struct Smvba;
+
+impl ConsensusProtocol for Smvba {
+    fn name(&self) -> &'static str {
+        "SMVBA"
+    }
+
+    fn optimistic(&self) -> bool {
+        false
+    }
+
+    fn pessimistic(&self) -> bool {
+        true
+    }
+}
+
+static PROTOCOLS: &[&dyn ConsensusProtocol] = &[&HotStuff, &HotStuffAndSmvba, &Smvba];
+
+// One of the registered protocols.
+#[derive(Clone, Copy)]
+pub struct Protocol(&'static dyn ConsensusProtocol);
+
+impl Protocol {
+    pub const HOTSTUFF: Protocol = Protocol(&HotStuff);
+    pub const HOTSTUFF_AND_SMVBA: Protocol = Protocol(&HotStuffAndSmvba);
+    pub const SMVBA: Protocol = Protocol(&Smvba);
+
+    // The names of the registered protocols.
+    pub fn names() -> Vec<&'static str> {
+        PROTOCOLS.iter().map(|x| x.name()).collect()
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.0.name()
+    }
+
+    pub fn optimistic(&self) -> bool {
+        self.0.optimistic()
+    }
+
+    pub fn pessimistic(&self) -> bool {
+        self.0.pessimistic()
+    }
+}
+
+impl Default for Protocol {
+    fn default() -> Self {
+        Protocol::HOTSTUFF_AND_SMVBA
+    }
+}
+
+impl PartialEq for Protocol {
+    fn eq(&self, other: &Self) -> bool {
+        self.name() == other.name()
+    }
+}
+
+impl FromStr for Protocol {
+    type Err = ConsensusError;
+
+    // Names are case insensitive.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        PROTOCOLS
+            .iter()
+            .find(|x| x.name().eq_ignore_ascii_case(name))
+            .map(|x| Protocol(*x))
+            .ok_or_else(|| ConsensusError::UnknownProtocol(name.to_string(), Self::names()))
+    }
+}
+
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl fmt::Debug for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl Serialize for Protocol {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+impl<'de> Deserialize<'de> for Protocol {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ProtocolVisitor;
+
+        impl<'de> Visitor<'de> for ProtocolVisitor {
+            type Value = Protocol;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "one of {}", Protocol::names().join(", "))
+            }
+
+            fn visit_str<E: de::Error>(self, name: &str) -> Result<Protocol, E> {
+                name.parse().map_err(E::custom)
+            }
+
+            // Protocols used to be numbered.
+            fn visit_u64<E: de::Error>(self, number: u64) -> Result<Protocol, E> {
+                Err(E::custom(format!(
+                    "protocols are selected by name, not by number ({}): use one of {}",
+                    number,
+                    Protocol::names().join(", ")
+                )))
+            }
+        }
+
+        // Self-describing formats only, as is the parameters file.
+        deserializer.deserialize_any(ProtocolVisitor)
+    }
+}
@@ -1,14 +1,13 @@
 use crate::config::Committee;
 use crate::core::ConsensusMessage;
-use crate::error::ConsensusResult;
+use crate::error::{ConsensusError, ConsensusResult};
 use crate::filter::FilterInput;
 use crate::messages::{Block, QC};
-use crate::OPT;
 use crypto::Hash as _;
 use crypto::{Digest, PublicKey};
 use futures::stream::futures_unordered::FuturesUnordered;
 use futures::stream::StreamExt as _;
-use log::{debug, error};
+use log::{debug, error, warn};
 use std::collections::{HashMap, HashSet};
 use std::time::{SystemTime, UNIX_EPOCH};
 use store::Store;
@@ -47,7 +46,12 @@ impl Synchronizer {
             tokio::pin!(timer);
             loop {
                 tokio::select! {
-                    Some(block) = rx_inner.recv() => {
+                    block = rx_inner.recv() => {
+                        // Stop along with the core.
+                        let block = match block {
+                            Some(block) => block,
+                            None => break,
+                        };
                         if pending.insert(block.digest()) {
                             let parent = block.parent().clone();
                             let fut = Self::waiter(store_copy.clone(), parent.clone(), block);
@@ -61,7 +65,9 @@ impl Synchronizer {
                                     .as_millis();
                                 requests.insert(parent.clone(), now);
                                 let message = ConsensusMessage::SyncRequest(parent, name);
-                                Self::transmit(message, &name, None, &network_filter, &committee,OPT).await.unwrap();
+                                if let Err(e) = Self::transmit(message, &name, None, &network_filter, &committee).await {
+                                    warn!("{}", e);
+                                }
                             }
                         }
                     },
@@ -71,8 +77,9 @@ impl Synchronizer {
                             let _ = pending.remove(&block.digest());
                             let _ = requests.remove(&block.parent());
                             let message = ConsensusMessage::HsLoopBack(block);
-                            if let Err(e) = core_channel.send(message).await {
-                                panic!("Failed to send message through core channel: {}", e);
+                            if core_channel.send(message).await.is_err() {
+                                warn!("Core is gone, stopping the synchronizer");
+                                break;
                             }
                         },
                         Err(e) => error!("{}", e)
@@ -87,7 +94,9 @@ impl Synchronizer {
                             if timestamp + (sync_retry_delay as u128) < now {
                                 debug!("Requesting sync for block {} (retry)", digest);
                                 let message = ConsensusMessage::SyncRequest(digest.clone(), name);
-                                Self::transmit(message, &name, None, &network_filter, &committee,OPT).await.unwrap();
+                                if let Err(e) = Self::transmit(message, &name, None, &network_filter, &committee).await {
+                                    warn!("{}", e);
+                                }
                             }
                         }
                         timer.as_mut().reset(Instant::now() + Duration::from_millis(TIMER_ACCURACY));
@@ -113,27 +122,18 @@ impl Synchronizer {
         to: Option<&PublicKey>,
         network_filter: &Sender<FilterInput>,
         committee: &Committee,
-        tag: u8,
     ) -> ConsensusResult<()> {
         let addresses = if let Some(to) = to {
             debug!("Sending {:?} to {}", message, to);
-            if tag == OPT {
-                vec![committee.address(to)?]
-            } else {
-                vec![committee.smvba_address(to)?]
-            }
+            vec![committee.address(to)?]
         } else {
             debug!("Broadcasting {:?}", message);
-            if tag == OPT {
-                committee.broadcast_addresses(from)
-            } else {
-                committee.smvba_broadcast_addresses(from)
-            }
+            committee.broadcast_addresses(from)
         };
-        if let Err(e) = network_filter.send((message, addresses)).await {
-            panic!("Failed to send block through network channel: {}", e);
-        }
-        Ok(())
+        network_filter
+            .send((message, addresses))
+            .await
+            .map_err(|_| ConsensusError::ChannelClosed("network filter"))
     }
 
     pub async fn get_parent_block(&mut self, block: &Block) -> ConsensusResult<Option<Block>> {
@@ -144,9 +144,10 @@ impl Synchronizer {
         match self.store.read(parent.to_vec()).await? {
             Some(bytes) => Ok(Some(bincode::deserialize(&bytes)?)),
             None => {
-                if let Err(e) = self.inner_channel.send(block.clone()).await {
-                    panic!("Failed to send request to synchronizer: {}", e);
-                }
+                self.inner_channel
+                    .send(block.clone())
+                    .await
+                    .map_err(|_| ConsensusError::ChannelClosed("synchronizer"))?;
                 Ok(None)
             }
         }
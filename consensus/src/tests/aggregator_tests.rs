@@ -1,6 +1,11 @@
 use super::*;
-use crate::common::{committee, keys, qc, vote};
-use crypto::Hash;
+use crate::common::{block, committee, keys, qc, vote};
+use crate::core::{FIN_PHASE, INIT_PHASE, OPT};
+use crate::messages::SPBValue;
+use crypto::{Digest, Hash, SignatureService};
+use rand::rngs::StdRng;
+use rand::SeedableRng as _;
+use threshold_crypto::SecretKeySet;
 #[test]
 fn add_vote() {
     let mut aggregator = Aggregator::new(committee());
@@ -40,6 +45,91 @@ fn make_qc() {
     }
 }
 
+// A committee with distinct tss ids and a quorum key set of threshold 2f.
+fn quorum_committee() -> (Committee, SecretKeySet) {
+    let mut rng = StdRng::from_seed([0; 32]);
+    let sk_set = SecretKeySet::random(2, &mut rng);
+    let mut committee = committee();
+    for (i, authority) in committee.authorities.values_mut().enumerate() {
+        authority.id = i;
+    }
+    committee.quorum_keys = Some(sk_set.public_keys());
+    (committee, sk_set)
+}
+
+#[test]
+fn make_threshold_qc() {
+    let (committee, sk_set) = quorum_committee();
+    let mut aggregator = Aggregator::new(committee.clone());
+    let mut keys = keys();
+    let qc = qc();
+
+    // Add 2f+1 votes carrying shares of the quorum key and ensure they are
+    // combined into a single threshold signature.
+    for i in 0..3 {
+        let (public_key, secret_key) = keys.pop().unwrap();
+        let vote = HVote::new_from_key(
+            qc.hash.clone(),
+            qc.height,
+            qc.proposer,
+            public_key,
+            &secret_key,
+        );
+        let share = sk_set
+            .secret_key_share(committee.id(public_key))
+            .sign(vote.digest());
+        let vote = HVote {
+            signature_share: Some(share),
+            ..vote
+        };
+        assert!(vote.verify(&committee).is_ok());
+        match aggregator.add_hs_vote(vote) {
+            Ok(None) if i < 2 => (),
+            Ok(Some(qc)) if i == 2 => {
+                assert!(qc.votes.is_empty());
+                assert!(qc.quorum_signature.is_some());
+                assert!(qc.verify(&committee).is_ok());
+            }
+            _ => assert!(false),
+        }
+    }
+}
+
+#[tokio::test]
+async fn make_threshold_spb_proof() {
+    let (committee, sk_set) = quorum_committee();
+    let mut aggregator = Aggregator::new(committee.clone());
+    let value = SPBValue::new(block(), 0, INIT_PHASE, OPT, Vec::new());
+
+    // Add 2f+1 votes signed with the quorum key shares and ensure the proof
+    // carries a single threshold signature instead of the votes.
+    let mut keys = keys();
+    for i in 0..3 {
+        let (public_key, secret_key) = keys.pop().unwrap();
+        let quorum_secret = sk_set.secret_key_share(committee.id(public_key));
+        let signature_service =
+            SignatureService::new_with_quorum(secret_key, None, Some(quorum_secret));
+        let vote = SPBVote::new(value.clone(), public_key, signature_service)
+            .await
+            .unwrap();
+        assert!(vote.verify(&committee).is_ok());
+        match aggregator.add_spb_vote(vote, 1) {
+            Ok(None) if i < 2 => (),
+            Ok(Some(proof)) if i == 2 => {
+                assert!(proof.shares.is_empty());
+                assert!(proof.quorum_signature.is_some());
+                assert!(proof.verify(&value, &committee).is_ok());
+
+                // The proof does not certify another value.
+                let mut other = value.clone();
+                other.block.payload.push(Digest::default());
+                assert!(proof.verify(&other, &committee).is_err());
+            }
+            _ => assert!(false),
+        }
+    }
+}
+
 #[test]
 fn cleanup() {
     let mut aggregator = Aggregator::new(committee());
@@ -53,3 +143,77 @@ fn cleanup() {
     aggregator.cleanup_hs_vote(&2);
     assert!(aggregator.hs_votes_aggregators.is_empty());
 }
+
+#[test]
+fn vote_out_of_window() {
+    let mut aggregator = Aggregator::new(committee());
+    let (public_key, secret_key) = keys().pop().unwrap();
+    let block = block();
+
+    // Votes far ahead of the current height are rejected.
+    let height = MAX_HEIGHT_WINDOW + 1;
+    let far_vote = HVote::new_from_key(
+        block.digest(),
+        height,
+        block.author,
+        public_key,
+        &secret_key,
+    );
+    match aggregator.add_hs_vote(far_vote) {
+        Err(ConsensusError::VoteOutOfWindow(x)) => assert_eq!(x, height),
+        _ => assert!(false),
+    }
+
+    // Votes for heights already cleaned up are ignored.
+    aggregator.cleanup_hs_vote(&2);
+    let result = aggregator.add_hs_vote(vote());
+    assert!(result.is_ok());
+    assert!(aggregator.hs_votes_aggregators.is_empty());
+}
+
+#[test]
+fn equivocating_vote() {
+    let mut aggregator = Aggregator::new(committee());
+    let (public_key, secret_key) = keys().pop().unwrap();
+    let block = block();
+    let result = aggregator.add_hs_vote(vote());
+    assert!(result.is_ok());
+
+    // A vote for another digest at the same height is reported.
+    let vote = HVote::new_from_key(Digest::default(), 1, block.author, public_key, &secret_key);
+    match aggregator.add_hs_vote(vote) {
+        Err(ConsensusError::EquivocatingVote(x, 1)) => assert_eq!(x, public_key),
+        _ => assert!(false),
+    }
+}
+
+#[tokio::test]
+async fn spb_vote_out_of_window() {
+    let mut aggregator = Aggregator::new(committee());
+    let (public_key, secret_key) = keys().pop().unwrap();
+
+    // Votes far ahead of our current round are rejected.
+    let round = 1 + MAX_ROUND_WINDOW + 1;
+    let value = SPBValue::new(block(), round, INIT_PHASE, OPT, Vec::new());
+    let vote = SPBVote::new(value, public_key, SignatureService::new(secret_key, None))
+        .await
+        .unwrap();
+    match aggregator.add_spb_vote(vote.clone(), 1) {
+        Err(ConsensusError::RoundOutOfWindow(x)) => assert_eq!(x, round),
+        _ => assert!(false),
+    }
+    assert!(aggregator.add_pre_vote(vote.clone(), 1).is_err());
+    assert!(aggregator.add_spb_vote(vote, round).is_ok());
+
+    // So are votes for phases that are never voted for.
+    let (public_key, secret_key) = keys().remove(0);
+    let value = SPBValue::new(block(), 1, FIN_PHASE, OPT, Vec::new());
+    let vote = SPBVote::new(value, public_key, SignatureService::new(secret_key, None))
+        .await
+        .unwrap();
+    match aggregator.add_spb_vote(vote, 1) {
+        Err(ConsensusError::InvalidPhase(x)) => assert_eq!(x, FIN_PHASE),
+        _ => assert!(false),
+    }
+    assert_eq!(aggregator.spb_votes_aggregators.len(), 1);
+}
@@ -4,7 +4,7 @@ use crate::mempool::{ConsensusMempoolMessage, PayloadStatus};
 use crate::messages::{Block, HVote, QC};
 use crate::OPT;
 use crypto::Hash as _;
-use crypto::{generate_keypair, Digest, PublicKey, SecretKey, Signature};
+use crypto::{generate_keypair, Digest, Domain, PublicKey, SecretKey, Signature};
 use rand::rngs::StdRng;
 use rand::RngCore as _;
 use rand::SeedableRng as _;
@@ -25,9 +25,8 @@ pub fn committee() -> Committee {
             .enumerate()
             .map(|(i, (name, _))| {
                 let address = format!("0.0.0.0:{}", i).parse().unwrap();
-                let smvba_address = format!("0.0.0.0:{}", 100 + i).parse().unwrap();
                 let stake = 1;
-                (name, 0, stake, address, smvba_address)
+                (name, 0, stake, address)
             })
             .collect(),
         /* epoch */ 1,
@@ -38,9 +37,7 @@ impl Committee {
     pub fn increment_base_port(&mut self, base_port: u16) {
         for authority in self.authorities.values_mut() {
             let port = authority.address.port();
-            let port_s = authority.smvba_address.port();
             authority.address.set_port(base_port + port);
-            authority.smvba_address.set_port(base_port + port_s);
         }
     }
 }
@@ -63,7 +60,7 @@ impl Block {
             signature: Signature::default(),
             tag: OPT,
         };
-        let signature = Signature::new(&block.digest(), secret);
+        let signature = Signature::new_in(Domain::Block, &block.digest(), secret);
         Self { signature, ..block }
     }
 }
@@ -91,8 +88,9 @@ impl HVote {
             round: 0,
             tag: OPT,
             signature: Signature::default(),
+            signature_share: None,
         };
-        let signature = Signature::new(&vote.digest(), &secret);
+        let signature = Signature::new_in(Domain::Vote, &vote.digest(), &secret);
         Self { signature, ..vote }
     }
 }
@@ -143,12 +141,16 @@ pub fn qc() -> QC {
         proposer: public_key,
         acceptor: public_key,
         votes: Vec::new(),
+        quorum_signature: None,
     };
     let digest = qc.digest();
     let votes: Vec<_> = (0..3)
         .map(|_| {
             let (public_key, secret_key) = keys.pop().unwrap();
-            (public_key, Signature::new(&digest, &secret_key))
+            (
+                public_key,
+                Signature::new_in(Domain::Vote, &digest, &secret_key),
+            )
         })
         .collect();
     QC { votes, ..qc }
@@ -180,11 +182,17 @@ pub fn chain(keys: Vec<(PublicKey, SecretKey)>) -> Vec<Block> {
                 proposer: block.author,
                 acceptor: block.author,
                 votes: Vec::new(),
+                quorum_signature: None,
             };
             let digest = qc.digest();
             let votes: Vec<_> = keys
                 .iter()
-                .map(|(public_key, secret_key)| (*public_key, Signature::new(&digest, secret_key)))
+                .map(|(public_key, secret_key)| {
+                    (
+                        *public_key,
+                        Signature::new_in(Domain::Vote, &digest, secret_key),
+                    )
+                })
                 .collect();
             latest_qc = QC { votes, ..qc };
 
@@ -2,17 +2,19 @@ use super::*;
 use crate::common::{committee, keys, MockMempool};
 use crate::config::Parameters;
 use crypto::{SecretKey, SecretShare};
-use futures::future::try_join_all;
+use futures::future::{join_all, try_join_all};
+use network::{Authenticator, Mux, ShutdownHandle};
 use std::fs;
 use tokio::sync::mpsc::channel;
 use tokio::task::JoinHandle;
+use tokio::time::{timeout, Duration};
 
 fn spawn_nodes(
     keys: Vec<(PublicKey, SecretKey)>,
     committee: Committee,
     store_path: &str,
     tss_keys: SecretShare,
-) -> Vec<JoinHandle<Block>> {
+) -> Vec<JoinHandle<(Block, ShutdownHandle)>> {
     keys.into_iter()
         .enumerate()
         .map(|(i, (name, secret))| {
@@ -38,6 +40,13 @@ fn spawn_nodes(
             // let mut rng = rand::thread_rng();
             // let sk_set = SecretKeySet::random(threshold, &mut rng);
             // let pk_set = sk_set.public_keys();
+            let authenticator = Authenticator::new(
+                name,
+                signature_service.clone(),
+                committee.authorities.values().map(|x| (x.name, x.address)),
+            );
+            let mut mux = Mux::new(committee.address(&name).unwrap(), authenticator);
+            let shutdown = ShutdownHandle::new();
             tokio::spawn(async move {
                 Consensus::run(
                     name,
@@ -52,12 +61,16 @@ fn spawn_nodes(
                     rx_smvba,
                     tx_consensus_mempool,
                     tx_commit,
-                    Protocol::HotStuffAndSMVBA,
+                    Protocol::HOTSTUFF_AND_SMVBA,
+                    channel(1).1,
+                    &mut mux,
+                    shutdown.subscribe(),
                 )
                 .await
                 .unwrap();
+                shutdown.subscribe().spawn(mux.run());
 
-                rx_commit.recv().await.unwrap()
+                (rx_commit.recv().await.unwrap(), shutdown)
             })
         })
         .collect()
@@ -73,6 +86,11 @@ async fn end_to_end() {
     let handles = spawn_nodes(keys(), committee, store_path, tss_key);
 
     // Ensure all threads terminated correctly.
-    let blocks = try_join_all(handles).await.unwrap();
+    let (blocks, shutdowns): (Vec<_>, Vec<_>) =
+        try_join_all(handles).await.unwrap().into_iter().unzip();
     assert!(blocks.windows(2).all(|w| w[0] == w[1]));
+
+    // Ensure all nodes stop.
+    let stopped = join_all(shutdowns.into_iter().map(|x| x.shutdown()));
+    assert!(timeout(Duration::from_secs(5), stopped).await.is_ok());
 }
@@ -1,15 +1,16 @@
 use super::*;
-use crate::common::{chain, committee, keys, MockMempool};
+use crate::common::{block, chain, committee, keys, MockMempool};
 use crypto::{SecretKey, SecretShare};
 use std::fs;
 use tokio::sync::mpsc::channel;
 
-async fn core(
+async fn make_core(
     name: PublicKey,
     secret: SecretKey,
     store_path: &str,
     pk_set: PublicKeySet,
 ) -> (
+    Core,
     Sender<ConsensusMessage>,
     Receiver<FilterInput>,
     Receiver<FilterInput>,
@@ -41,7 +42,8 @@ async fn core(
         parameters.sync_retry_delay,
     )
     .await;
-    let mut core = Core::new(
+    let tuner = Tuner::new(channel(1).1, parameters.clone());
+    let core = Core::new(
         name,
         committee(),
         parameters,
@@ -60,7 +62,24 @@ async fn core(
         /* commit_channel */ tx_commit,
         true,
         false,
+        tuner,
     );
+    (core, tx_core, rx_network, rx_network_smvba, rx_commit)
+}
+
+async fn core(
+    name: PublicKey,
+    secret: SecretKey,
+    store_path: &str,
+    pk_set: PublicKeySet,
+) -> (
+    Sender<ConsensusMessage>,
+    Receiver<FilterInput>,
+    Receiver<FilterInput>,
+    Receiver<Block>,
+) {
+    let (mut core, tx_core, rx_network, rx_network_smvba, rx_commit) =
+        make_core(name, secret, store_path, pk_set).await;
     tokio::spawn(async move {
         core.run().await;
     });
@@ -150,6 +169,7 @@ async fn generate_proposal() {
             .cloned()
             .map(|x| (x.author, x.signature))
             .collect(),
+        quorum_signature: None,
     };
     let tss_keys = SecretShare::default();
     let pk_set = tss_keys.pkset.clone();
@@ -236,3 +256,27 @@ async fn commit_block() {
 //         _ => assert!(false),
 //     }
 // }
+
+#[tokio::test]
+async fn ignore_far_future_votes() {
+    let (public_key, secret_key) = keys().pop().unwrap();
+    let (mut core, ..) = make_core(
+        public_key,
+        secret_key.clone(),
+        ".db_test_ignore_far_future_votes",
+        SecretShare::default().pkset,
+    )
+    .await;
+
+    // A vote far beyond the window of the aggregator is refused, and not
+    // remembered by the equivocation detector.
+    let vote = HVote::new_from_key(
+        block().digest(),
+        u64::MAX,
+        block().author,
+        public_key,
+        &secret_key,
+    );
+    assert!(core.handle_opt_vote(&vote).await.is_err());
+    assert!(core.detector.is_empty());
+}
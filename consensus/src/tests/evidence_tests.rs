@@ -0,0 +1,62 @@
+use super::*;
+use crate::common::{block, committee, keys};
+use crate::messages::QC;
+
+#[test]
+fn detect_conflicting_blocks() {
+    let mut detector = EquivocationDetector::default();
+    let (public_key, secret_key) = keys().pop().unwrap();
+    let b1 = Block::new_from_key(QC::genesis(), public_key, 1, Vec::new(), &secret_key);
+    let b2 = Block::new_from_key(
+        QC::genesis(),
+        public_key,
+        1,
+        vec![Digest::default()],
+        &secret_key,
+    );
+    assert!(detector.check_block(&b1).is_none());
+    assert!(detector.check_block(&b1).is_none());
+
+    // A second block at the same height makes valid evidence.
+    match detector.check_block(&b2) {
+        Some(evidence) => {
+            assert_eq!(evidence.offender(), public_key);
+            assert!(evidence.verify(&committee()).is_ok());
+        }
+        None => assert!(false),
+    }
+}
+
+#[test]
+fn detect_conflicting_votes() {
+    let mut detector = EquivocationDetector::default();
+    let (public_key, secret_key) = keys().pop().unwrap();
+    let block = block();
+    let v1 = HVote::new_from_key(block.digest(), 1, block.author, public_key, &secret_key);
+    let v2 = HVote::new_from_key(Digest::default(), 1, block.author, public_key, &secret_key);
+    assert!(detector.check_vote(&v1).is_none());
+    match detector.check_vote(&v2) {
+        Some(evidence) => assert!(evidence.verify(&committee()).is_ok()),
+        None => assert!(false),
+    }
+
+    // Votes at different heights do not conflict.
+    let v3 = HVote::new_from_key(Digest::default(), 2, block.author, public_key, &secret_key);
+    assert!(detector.check_vote(&v3).is_none());
+    let evidence = Evidence::ConflictingVotes(v1, v3);
+    match evidence.verify(&committee()) {
+        Err(ConsensusError::InvalidEvidence(x)) => assert_eq!(x, public_key),
+        _ => assert!(false),
+    }
+}
+
+#[test]
+fn evidence_digest_ignores_order() {
+    let (public_key, secret_key) = keys().pop().unwrap();
+    let block = block();
+    let v1 = HVote::new_from_key(block.digest(), 1, block.author, public_key, &secret_key);
+    let v2 = HVote::new_from_key(Digest::default(), 1, block.author, public_key, &secret_key);
+    let e1 = Evidence::ConflictingVotes(v1.clone(), v2.clone());
+    let e2 = Evidence::ConflictingVotes(v2, v1);
+    assert_eq!(e1.digest(), e2.digest());
+}
@@ -0,0 +1,29 @@
+use super::*;
+
+#[test]
+fn select_by_name() {
+    assert_eq!("HotStuff".parse::<Protocol>().unwrap(), Protocol::HOTSTUFF);
+    assert_eq!("smvba".parse::<Protocol>().unwrap(), Protocol::SMVBA);
+    for name in Protocol::names() {
+        assert_eq!(name.parse::<Protocol>().unwrap().name(), name);
+    }
+}
+
+#[test]
+fn reject_unknown_protocol() {
+    match "PBFT".parse::<Protocol>() {
+        Err(ConsensusError::UnknownProtocol(name, known)) => {
+            assert_eq!(name, "PBFT");
+            assert_eq!(known, Protocol::names());
+        }
+        _ => assert!(false),
+    }
+}
+
+#[test]
+fn paths() {
+    let paths = |x: Protocol| (x.optimistic(), x.pessimistic());
+    assert_eq!(paths(Protocol::HOTSTUFF), (true, false));
+    assert_eq!(paths(Protocol::HOTSTUFF_AND_SMVBA), (true, true));
+    assert_eq!(paths(Protocol::SMVBA), (false, true));
+}
@@ -94,6 +94,7 @@ async fn get_missing_parent_block() {
             Ok(None) => assert!(true),
             _ => assert!(false),
         }
+        synchronizer
     });
 
     // Ensure the synchronizer sends a sync request
@@ -116,8 +117,9 @@ async fn get_missing_parent_block() {
     }
 
     // Ensure the synchronizer returns None, thus suspending
-    // the processing of the block.
-    assert!(handle.await.is_ok());
+    // the processing of the block. Keep the synchronizer alive,
+    // it stops along with its owner.
+    let _synchronizer = handle.await.unwrap();
 
     // Add the parent to the store.
     let key = parent_block.digest().to_vec();
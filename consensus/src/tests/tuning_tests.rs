@@ -0,0 +1,52 @@
+use super::*;
+use tokio::sync::mpsc::channel;
+
+#[test]
+fn reject_invalid_tuning() {
+    let tuning = Tuning {
+        random_ddos_chance: Some(150),
+        ..Tuning::default()
+    };
+    assert!(matches!(
+        tuning.check(),
+        Err(ConsensusError::InvalidTuning(_))
+    ));
+    let tuning = Tuning {
+        max_payload_size: Some(0),
+        ..Tuning::default()
+    };
+    assert!(tuning.check().is_err());
+    assert!(Tuning::default().check().is_ok());
+}
+
+#[test]
+fn reject_unknown_fields() {
+    let tuning: Tuning = serde_json::from_str(r#"{"min_block_delay": 10}"#).unwrap();
+    assert_eq!(tuning.min_block_delay, Some(10));
+    assert!(serde_json::from_str::<Tuning>(r#"{"timeout_delay": 10}"#).is_err());
+    assert!(serde_json::from_str::<Tuning>(r#"{"fallback_length": 10}"#).is_err());
+}
+
+#[test]
+fn apply_at_boundaries() {
+    let mut tuner = Tuner::new(channel(1).1, Parameters::default());
+    let parameters = tuner.subscribe();
+    assert!(tuner.next_height().is_none());
+
+    tuner.schedule(Tuning {
+        min_block_delay: Some(10),
+        ddos: Some(true),
+        ..Tuning::default()
+    });
+    tuner.schedule(Tuning {
+        min_block_delay: Some(20),
+        ..Tuning::default()
+    });
+
+    // The later values win, and all apply at the next height.
+    let tuned = tuner.next_height().unwrap();
+    assert_eq!(tuned.min_block_delay, 20);
+    assert!(tuned.ddos);
+    assert_eq!(parameters.borrow().min_block_delay, 20);
+    assert!(tuner.next_height().is_none());
+}
@@ -0,0 +1,130 @@
+use super::*;
+use crate::common::{block, committee, vote};
+use crate::messages::HVote;
+use crypto::SecretShare;
+
+#[tokio::test]
+async fn drop_invalid_messages() {
+    let (tx_verifier, rx_verifier) = channel(10);
+    let (_tx_verifier_smvba, rx_verifier_smvba) = channel(10);
+    let (tx_core, mut rx_core) = channel(10);
+    let (tx_smvba, _rx_smvba) = channel(10);
+    Verifier::spawn(
+        committee(),
+        SecretShare::default().pkset,
+        &Parameters::default(),
+        rx_verifier,
+        rx_verifier_smvba,
+        tx_core,
+        tx_smvba,
+    );
+
+    // Send a vote with a bad signature between two valid messages.
+    let bad_vote = HVote {
+        signature: Signature::default(),
+        ..vote()
+    };
+    tx_verifier
+        .send(ConsensusMessage::HsPropose(block()))
+        .await
+        .unwrap();
+    tx_verifier
+        .send(ConsensusMessage::HSVote(bad_vote))
+        .await
+        .unwrap();
+    tx_verifier
+        .send(ConsensusMessage::HSVote(vote()))
+        .await
+        .unwrap();
+
+    // Only the valid messages reach the core, in order.
+    match rx_core.recv().await {
+        Some(ConsensusMessage::HsPropose(b)) => assert_eq!(b, block()),
+        _ => assert!(false),
+    }
+    match rx_core.recv().await {
+        Some(ConsensusMessage::HSVote(v)) => assert_eq!(v, vote()),
+        _ => assert!(false),
+    }
+}
+
+#[tokio::test]
+async fn skip_verification() {
+    let (tx_verifier, rx_verifier) = channel(10);
+    let (_tx_verifier_smvba, rx_verifier_smvba) = channel(10);
+    let (tx_core, mut rx_core) = channel(10);
+    let (tx_smvba, _rx_smvba) = channel(10);
+    let parameters = Parameters {
+        verification: VerificationPolicy::Skip,
+        ..Parameters::default()
+    };
+    Verifier::spawn(
+        committee(),
+        SecretShare::default().pkset,
+        &parameters,
+        rx_verifier,
+        rx_verifier_smvba,
+        tx_core,
+        tx_smvba,
+    );
+
+    // Nothing is verified under the skip policy.
+    let bad_vote = HVote {
+        signature: Signature::default(),
+        ..vote()
+    };
+    tx_verifier
+        .send(ConsensusMessage::HSVote(bad_vote))
+        .await
+        .unwrap();
+    match rx_core.recv().await {
+        Some(ConsensusMessage::HSVote(v)) => assert_eq!(v, vote()),
+        _ => assert!(false),
+    }
+}
+
+#[tokio::test]
+async fn verify_without_workers() {
+    let (tx_verifier, rx_verifier) = channel(10);
+    let (_tx_verifier_smvba, rx_verifier_smvba) = channel(10);
+    let (tx_core, mut rx_core) = channel(10);
+    let (tx_smvba, _rx_smvba) = channel(10);
+    let parameters = Parameters {
+        verification_workers: 0,
+        ..Parameters::default()
+    };
+    Verifier::spawn(
+        committee(),
+        SecretShare::default().pkset,
+        &parameters,
+        rx_verifier,
+        rx_verifier_smvba,
+        tx_core,
+        tx_smvba,
+    );
+
+    // The policy applies all the same without workers.
+    let bad_vote = HVote {
+        signature: Signature::default(),
+        ..vote()
+    };
+    tx_verifier
+        .send(ConsensusMessage::HSVote(bad_vote))
+        .await
+        .unwrap();
+    tx_verifier
+        .send(ConsensusMessage::HSVote(vote()))
+        .await
+        .unwrap();
+    match rx_core.recv().await {
+        Some(ConsensusMessage::HSVote(v)) => assert_eq!(v, vote()),
+        _ => assert!(false),
+    }
+}
+
+#[test]
+fn relaxed_verification_requires_benchmark() {
+    assert!(VerificationPolicy::Full.check().is_ok());
+    let result = VerificationPolicy::Skip.check();
+    assert_eq!(result.is_ok(), cfg!(feature = "benchmark"));
+}
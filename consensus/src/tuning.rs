@@ -0,0 +1,105 @@
+use crate::config::Parameters;
+use crate::error::{ConsensusError, ConsensusResult};
+use log::info;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::Receiver;
+use tokio::sync::watch;
+
+#[cfg(test)]
+#[path = "tests/tuning_tests.rs"]
+pub mod tuning_tests;
+
+// The parameters that can change while the node runs. Unset fields are left
+// as they are. Not `fallback_length`: SMVBA messages are checked against it, so
+// all the nodes must agree on it from the start.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Tuning {
+    pub min_block_delay: Option<u64>,
+    pub max_payload_size: Option<usize>,
+    pub ddos: Option<bool>,
+    pub random_ddos: Option<bool>,
+    pub random_ddos_chance: Option<u64>,
+    pub network_delay: Option<u64>,
+}
+
+impl Tuning {
+    pub fn check(&self) -> ConsensusResult<()> {
+        ensure!(
+            self.max_payload_size != Some(0),
+            ConsensusError::InvalidTuning("max_payload_size must be more than 0".to_string())
+        );
+        ensure!(
+            self.random_ddos_chance.is_none_or(|x| x <= 100),
+            ConsensusError::InvalidTuning(
+                "random_ddos_chance must be at most 100 (percent)".to_string()
+            )
+        );
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    // The later values win.
+    fn merge(&mut self, other: Tuning) {
+        self.min_block_delay = other.min_block_delay.or(self.min_block_delay);
+        self.max_payload_size = other.max_payload_size.or(self.max_payload_size);
+        self.ddos = other.ddos.or(self.ddos);
+        self.random_ddos = other.random_ddos.or(self.random_ddos);
+        self.random_ddos_chance = other.random_ddos_chance.or(self.random_ddos_chance);
+        self.network_delay = other.network_delay.or(self.network_delay);
+    }
+}
+
+// Holds the tunings received by the core until the next height, and shares the
+// parameters in use with the other tasks of the consensus (filter).
+pub struct Tuner {
+    rx_tuning: Receiver<Tuning>,
+    pending: Tuning,
+    parameters: watch::Sender<Parameters>,
+}
+
+impl Tuner {
+    pub fn new(rx_tuning: Receiver<Tuning>, parameters: Parameters) -> Self {
+        let (parameters, _) = watch::channel(parameters);
+        Self {
+            rx_tuning,
+            pending: Tuning::default(),
+            parameters,
+        }
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<Parameters> {
+        self.parameters.subscribe()
+    }
+
+    pub async fn recv(&mut self) -> Option<Tuning> {
+        self.rx_tuning.recv().await
+    }
+
+    pub fn schedule(&mut self, tuning: Tuning) {
+        info!("Tuning {:?} scheduled", tuning);
+        self.pending.merge(tuning);
+    }
+
+    // Apply the pending tunings at the start of a height; returns the new
+    // parameters if any changed.
+    pub fn next_height(&mut self) -> Option<Parameters> {
+        if self.pending.is_empty() {
+            return None;
+        }
+        let tuning = std::mem::take(&mut self.pending);
+        self.parameters.send_modify(|x| {
+            x.min_block_delay = tuning.min_block_delay.unwrap_or(x.min_block_delay);
+            x.max_payload_size = tuning.max_payload_size.unwrap_or(x.max_payload_size);
+            x.ddos = tuning.ddos.unwrap_or(x.ddos);
+            x.random_ddos = tuning.random_ddos.unwrap_or(x.random_ddos);
+            x.random_ddos_chance = tuning.random_ddos_chance.unwrap_or(x.random_ddos_chance);
+            x.network_delay = tuning.network_delay.unwrap_or(x.network_delay);
+        });
+        info!("Tuning {:?} applied", tuning);
+        Some(self.parameters.borrow().clone())
+    }
+}
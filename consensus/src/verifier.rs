@@ -0,0 +1,224 @@
+use crate::config::{Committee, Parameters, VerificationPolicy};
+use crate::core::{ConsensusMessage, SeqNumber};
+use crate::error::ConsensusResult;
+use crypto::{Digest, Domain, Hash as _, PublicKey, Signature, VerificationService};
+use log::warn;
+use std::sync::Arc;
+use threshold_crypto::PublicKeySet;
+use tokio::sync::mpsc::{channel, Receiver, Sender};
+use tokio::task::JoinHandle;
+
+#[cfg(test)]
+#[path = "tests/verifier_tests.rs"]
+pub mod verifier_tests;
+
+// Largest number of messages verified by a single job.
+const MAX_BATCH: usize = 64;
+
+// A message and whether it goes to the smvba channel of the core.
+type Routed = (ConsensusMessage, bool);
+
+// Verifies the messages received from the network before handing them to the
+// core, in the order they were received: the only place applying the
+// verification policy. Batches go to a pool of workers, or are verified on the
+// verifier task without workers. The ed25519 signatures of the votes of a batch
+// are checked all at once.
+pub struct Verifier {
+    context: Arc<Context>,
+    service: Option<VerificationService>,
+    rx_core: Receiver<ConsensusMessage>,
+    rx_smvba: Receiver<ConsensusMessage>,
+    tx_pending: Sender<JoinHandle<Vec<Routed>>>,
+}
+
+impl Verifier {
+    pub fn spawn(
+        committee: Committee,
+        pk_set: PublicKeySet,
+        parameters: &Parameters,
+        rx_core: Receiver<ConsensusMessage>,
+        rx_smvba: Receiver<ConsensusMessage>,
+        tx_core: Sender<ConsensusMessage>,
+        tx_smvba: Sender<ConsensusMessage>,
+    ) {
+        let workers = parameters.verification_workers;
+        let (tx_pending, rx_pending) = channel(2 * workers.max(1));
+        tokio::spawn(async move {
+            Self::forward(rx_pending, tx_core, tx_smvba).await;
+        });
+
+        let context = Context {
+            committee,
+            pk_set,
+            fallback_length: parameters.fallback_length,
+            policy: parameters.verification,
+        };
+        tokio::spawn(async move {
+            Self {
+                context: Arc::new(context),
+                service: (workers > 0).then(|| VerificationService::new(workers)),
+                rx_core,
+                rx_smvba,
+                tx_pending,
+            }
+            .run()
+            .await;
+        });
+    }
+
+    async fn run(&mut self) {
+        loop {
+            let first = tokio::select! {
+                Some(message) = self.rx_core.recv() => (message, false),
+                Some(message) = self.rx_smvba.recv() => (message, true),
+                else => break,
+            };
+
+            // Take whatever else is already waiting.
+            let mut batch = vec![first];
+            while batch.len() < MAX_BATCH {
+                let message = match self.rx_core.try_recv() {
+                    Ok(message) => (message, false),
+                    Err(_) => match self.rx_smvba.try_recv() {
+                        Ok(message) => (message, true),
+                        Err(_) => break,
+                    },
+                };
+                batch.push(message);
+            }
+
+            let context = self.context.clone();
+            let handle = match self.service.clone() {
+                Some(service) => {
+                    let job = move || context.verify_batch(batch);
+                    tokio::spawn(async move { service.verify(job).await })
+                }
+                None => {
+                    let verified = context.verify_batch(batch);
+                    tokio::spawn(async move { verified })
+                }
+            };
+            if self.tx_pending.send(handle).await.is_err() {
+                break;
+            }
+        }
+    }
+
+    // Deliver the verified messages in the order the batches were made.
+    async fn forward(
+        mut rx_pending: Receiver<JoinHandle<Vec<Routed>>>,
+        tx_core: Sender<ConsensusMessage>,
+        tx_smvba: Sender<ConsensusMessage>,
+    ) {
+        while let Some(handle) = rx_pending.recv().await {
+            let verified = match handle.await {
+                Ok(verified) => verified,
+                Err(e) => {
+                    warn!("Failed to verify batch: {}", e);
+                    continue;
+                }
+            };
+            for (message, smvba) in verified {
+                let channel = if smvba { &tx_smvba } else { &tx_core };
+                if channel.send(message).await.is_err() {
+                    warn!("Core is gone, stopping the verifier");
+                    return;
+                }
+            }
+        }
+    }
+}
+
+struct Context {
+    committee: Committee,
+    pk_set: PublicKeySet,
+    fallback_length: SeqNumber,
+    policy: VerificationPolicy,
+}
+
+impl Context {
+    fn verify_batch(&self, batch: Vec<Routed>) -> Vec<Routed> {
+        // The policy picks the messages to verify, the others go through.
+        let sampled: Vec<_> = batch.iter().map(|_| self.policy.should_verify()).collect();
+
+        // 批量验证投票签名，失败时再逐个验证找出错误的投票
+        let signatures: Vec<_> = batch
+            .iter()
+            .zip(&sampled)
+            .filter(|(_, sampled)| **sampled)
+            .filter_map(|((message, _), _)| vote_signature(message))
+            .collect();
+        let batched = Signature::verify_many(&signatures).is_ok();
+
+        batch
+            .into_iter()
+            .zip(sampled)
+            .filter(|((message, _), sampled)| {
+                !sampled
+                    || match self.verify(message, batched) {
+                        Ok(()) => true,
+                        Err(e) => {
+                            warn!("{}", e);
+                            false
+                        }
+                    }
+            })
+            .map(|(routed, _)| routed)
+            .collect()
+    }
+
+    // The checks the core makes on messages from the network. If `batched`, the
+    // vote signatures are already known to be valid.
+    fn verify(&self, message: &ConsensusMessage, batched: bool) -> ConsensusResult<()> {
+        let committee = &self.committee;
+        let fallback_length = self.fallback_length;
+        match message {
+            ConsensusMessage::HsPropose(block) | ConsensusMessage::SyncReply(block) => {
+                block.verify(committee)
+            }
+            ConsensusMessage::HSVote(vote) if batched => vote.verify_author(committee),
+            ConsensusMessage::HSVote(vote) => vote.verify(committee),
+            ConsensusMessage::Evidence(evidence) => evidence.verify(committee),
+            ConsensusMessage::FBPropose(block) => block.verify(committee),
+            ConsensusMessage::FBVote(vote) if batched => vote.verify_author(committee),
+            ConsensusMessage::FBVote(vote) => vote.verify(committee),
+            ConsensusMessage::ParPrePare(prepare) => prepare.verify(committee, fallback_length),
+            ConsensusMessage::SPBPropose(value, proof)
+            | ConsensusMessage::SPBFinsh(value, proof) => {
+                value.verify(committee, proof, fallback_length)
+            }
+            ConsensusMessage::SPBVote(vote) if batched => vote.verify_author(committee),
+            ConsensusMessage::SPBVote(vote) => vote.verify(committee),
+            ConsensusMessage::SPBDoneAndShare(mdone) => {
+                mdone.verify(committee, &self.pk_set)?;
+                mdone.share.verify(committee, &self.pk_set)
+            }
+            ConsensusMessage::SMVBAPreVote(prevote) => prevote.verify(committee, fallback_length),
+            ConsensusMessage::SMVBAVote(mvote) => {
+                mvote.verify(committee, &self.pk_set, fallback_length)
+            }
+            ConsensusMessage::SMVBAHalt(halt) => {
+                halt.verify(committee, &self.pk_set, fallback_length)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+fn vote_signature(message: &ConsensusMessage) -> Option<(Domain, Digest, PublicKey, Signature)> {
+    match message {
+        ConsensusMessage::HSVote(vote) | ConsensusMessage::FBVote(vote) => Some((
+            Domain::Vote,
+            vote.digest(),
+            vote.author,
+            vote.signature.clone(),
+        )),
+        ConsensusMessage::SPBVote(vote) => Some((
+            Domain::SPBVote,
+            vote.digest(),
+            vote.author,
+            vote.signature.clone(),
+        )),
+        _ => None,
+    }
+}
@@ -7,12 +7,20 @@ use serde::{de, ser, Deserialize, Serialize};
 use std::array::TryFromSliceError;
 use std::convert::{TryFrom, TryInto};
 use std::fmt;
+use std::sync::Arc;
 use threshold_crypto::serde_impl::SerdeSecret;
 use threshold_crypto::{
     PublicKeySet, PublicKeyShare, SecretKeySet, SecretKeyShare, SignatureShare,
 };
 use tokio::sync::mpsc::{channel, Sender};
-use tokio::sync::oneshot;
+use tokio::sync::{oneshot, Semaphore};
+
+pub use crate::signer::{
+    BlockRequest, DoubleSignGuard, Identity, LocalSigner, RemoteSigner, SPBVoteRequest,
+    SignRequest, SignResponse, Signer, SignerError, SignerResult, SignerServer, Slot, VoteRequest,
+};
+
+mod signer;
 
 #[cfg(test)]
 #[path = "tests/crypto_tests.rs"]
@@ -126,6 +134,15 @@ impl SecretKey {
             .map_err(|_| base64::DecodeError::InvalidLength)?;
         Ok(Self(array))
     }
+
+    pub fn public_key(&self) -> PublicKey {
+        // The keypair bytes are the ed25519 secret followed by the public key.
+        PublicKey(
+            self.0[32..]
+                .try_into()
+                .expect("Unexpected secret key length"),
+        )
+    }
 }
 
 impl Serialize for SecretKey {
@@ -168,6 +185,26 @@ where
     (public, secret)
 }
 
+// What a signature covers. Blocks and votes are signed with their kind in front of
+// the digest, so that a signature over a bare digest (see `SignRequest::Message`)
+// never passes for one of them: those only come out of the double-sign guard.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum Domain {
+    Message,
+    Block,
+    Vote,
+    SPBVote,
+}
+
+impl Domain {
+    fn message(&self, digest: &Digest) -> Vec<u8> {
+        match self {
+            Domain::Message => digest.to_vec(),
+            _ => [&[*self as u8][..], &digest.0[..]].concat(),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Default, Debug)]
 pub struct Signature {
     part1: [u8; 32],
@@ -177,13 +214,27 @@ pub struct Signature {
 impl Signature {
     //使用私钥生成签名
     pub fn new(digest: &Digest, secret: &SecretKey) -> Self {
+        Self::new_in(Domain::Message, digest, secret)
+    }
+
+    pub fn new_in(domain: Domain, digest: &Digest, secret: &SecretKey) -> Self {
         let keypair = dalek::Keypair::from_bytes(&secret.0).expect("Unable to load secret key");
-        let sig = keypair.sign(&digest.0).to_bytes();
+        let sig = keypair.sign(&domain.message(digest)).to_bytes();
         let part1 = sig[..32].try_into().expect("Unexpected signature length");
         let part2 = sig[32..64].try_into().expect("Unexpected signature length");
         Signature { part1, part2 }
     }
 
+    pub fn from_bytes(bytes: &[u8; 64]) -> Self {
+        let part1 = bytes[..32].try_into().expect("Unexpected signature length");
+        let part2 = bytes[32..].try_into().expect("Unexpected signature length");
+        Signature { part1, part2 }
+    }
+
+    pub fn to_bytes(&self) -> [u8; 64] {
+        self.flatten()
+    }
+
     fn flatten(&self) -> [u8; 64] {
         [self.part1, self.part2]
             .concat()
@@ -192,64 +243,142 @@ impl Signature {
     }
     //使用公钥验证签名
     pub fn verify(&self, digest: &Digest, public_key: &PublicKey) -> Result<(), CryptoError> {
+        self.verify_in(Domain::Message, digest, public_key)
+    }
+
+    pub fn verify_in(
+        &self,
+        domain: Domain,
+        digest: &Digest,
+        public_key: &PublicKey,
+    ) -> Result<(), CryptoError> {
         let signature = ed25519::signature::Signature::from_bytes(&self.flatten())?;
         let key = dalek::PublicKey::from_bytes(&public_key.0)?;
-        key.verify_strict(&digest.0, &signature)
+        key.verify_strict(&domain.message(digest), &signature)
     }
 
     pub fn verify_batch<'a, I>(digest: &Digest, votes: I) -> Result<(), CryptoError>
     where
         I: IntoIterator<Item = &'a (PublicKey, Signature)>,
     {
+        Self::verify_batch_in(Domain::Message, digest, votes)
+    }
+
+    pub fn verify_batch_in<'a, I>(
+        domain: Domain,
+        digest: &Digest,
+        votes: I,
+    ) -> Result<(), CryptoError>
+    where
+        I: IntoIterator<Item = &'a (PublicKey, Signature)>,
+    {
+        let message = domain.message(digest);
         let mut messages: Vec<&[u8]> = Vec::new();
         let mut signatures: Vec<dalek::Signature> = Vec::new();
         let mut keys: Vec<dalek::PublicKey> = Vec::new();
         for (key, sig) in votes.into_iter() {
-            messages.push(&digest.0[..]);
+            messages.push(&message[..]);
+            signatures.push(ed25519::signature::Signature::from_bytes(&sig.flatten())?);
+            keys.push(dalek::PublicKey::from_bytes(&key.0)?);
+        }
+        dalek::verify_batch(&messages[..], &signatures[..], &keys[..])
+    }
+
+    // Verify signatures over different digests in a single batch.
+    pub fn verify_many<'a, I>(items: I) -> Result<(), CryptoError>
+    where
+        I: IntoIterator<Item = &'a (Domain, Digest, PublicKey, Signature)>,
+    {
+        let mut messages: Vec<Vec<u8>> = Vec::new();
+        let mut signatures: Vec<dalek::Signature> = Vec::new();
+        let mut keys: Vec<dalek::PublicKey> = Vec::new();
+        for (domain, digest, key, sig) in items.into_iter() {
+            messages.push(domain.message(digest));
             signatures.push(ed25519::signature::Signature::from_bytes(&sig.flatten())?);
             keys.push(dalek::PublicKey::from_bytes(&key.0)?);
         }
+        if messages.is_empty() {
+            return Ok(());
+        }
+        let messages: Vec<&[u8]> = messages.iter().map(|x| &x[..]).collect();
         dalek::verify_batch(&messages[..], &signatures[..], &keys[..])
     }
 }
 
+type SignChannel = Sender<(SignRequest, oneshot::Sender<SignerResult<SignResponse>>)>;
+
 #[derive(Clone)]
 pub struct SignatureService {
-    channel: Sender<(Digest, oneshot::Sender<Signature>)>,
-    tss_channel: Option<Sender<(Digest, oneshot::Sender<SignatureShare>)>>,
+    channel: SignChannel,
+    tss: bool, // whether the signer holds a threshold key share
+    // Plain messages go to a thread of their own if set, see `fork`.
+    messages: Option<SignChannel>,
+    message_key: Option<SecretKey>,
 }
 
 impl SignatureService {
     pub fn new(secret: SecretKey, tss_secret: Option<SecretKeyShare>) -> Self {
-        let (tx, mut rx): (Sender<(_, oneshot::Sender<_>)>, _) = channel(100);
-        tokio::spawn(async move {
-            while let Some((digest, sender)) = rx.recv().await {
-                let signature = Signature::new(&digest, &secret);
-                let _ = sender.send(signature);
+        Self::new_with_quorum(secret, tss_secret, None)
+    }
+
+    pub fn new_with_quorum(
+        secret: SecretKey,
+        tss_secret: Option<SecretKeyShare>,
+        quorum_secret: Option<SecretKeyShare>,
+    ) -> Self {
+        let tss = tss_secret.is_some();
+        let signer = LocalSigner::new(secret, tss_secret, quorum_secret);
+        Self::spawn(signer, tss)
+    }
+
+    // Sign with any signer, e.g. a remote one holding the keys in another process.
+    pub fn new_with_signer<S: Signer>(signer: S, identity: &Identity) -> Self {
+        Self::spawn(signer, identity.pkset.is_some())
+    }
+
+    fn spawn<S: Signer>(signer: S, tss: bool) -> Self {
+        let message_key = signer.message_key();
+        Self {
+            channel: Self::run(signer),
+            tss,
+            messages: None,
+            message_key,
+        }
+    }
+
+    fn run<S: Signer>(mut signer: S) -> SignChannel {
+        let (tx, mut rx): (SignChannel, _) = channel(100);
+        // The signer may block (e.g. on a socket), so it gets its own thread.
+        std::thread::spawn(move || {
+            while let Some((request, sender)) = rx.blocking_recv() {
+                let _ = sender.send(signer.sign(request));
             }
         });
-        let (tss_tx, mut tss_rx): (Sender<(_, oneshot::Sender<_>)>, _) = channel(100);
-        if let Some(secret_share) = tss_secret {
-            tokio::spawn(async move {
-                while let Some((digest, sender)) = tss_rx.recv().await {
-                    let signature_share = secret_share.sign(digest);
-                    let _ = sender.send(signature_share);
-                }
-            });
-            return Self {
-                channel: tx,
-                tss_channel: Some(tss_tx),
-            };
-        }
+        tx
+    }
+
+    // A service signing plain messages on a new thread, so that the tasks holding
+    // different forks do not wait for each other. Blocks, votes and shares still
+    // go to the signer of this service, and so do messages if its key is not in
+    // memory (e.g. a remote signer).
+    pub fn fork(&self) -> Self {
+        let messages = self
+            .message_key
+            .clone()
+            .map(|secret| Self::run(LocalSigner::new(secret, None, None)));
         Self {
-            channel: tx,
-            tss_channel: None,
+            messages: messages.or_else(|| self.messages.clone()),
+            ..self.clone()
         }
     }
 
-    pub async fn request_signature(&mut self, digest: Digest) -> Signature {
+    async fn request(&mut self, request: SignRequest) -> SignerResult<SignResponse> {
+        Self::send(&self.channel, request).await
+    }
+
+    async fn send(channel: &SignChannel, request: SignRequest) -> SignerResult<SignResponse> {
         let (sender, receiver): (oneshot::Sender<_>, oneshot::Receiver<_>) = oneshot::channel();
-        if let Err(e) = self.channel.send((digest, sender)).await {
+        if let Err(e) = channel.send((request, sender)).await {
             panic!("Failed to send message Signature Service: {}", e);
         }
         receiver
@@ -257,19 +386,97 @@ impl SignatureService {
             .expect("Failed to receive signature from Signature Service")
     }
 
+    // Messages and coin shares cannot be refused: the signer failing to sign them is fatal.
+    async fn request_or_panic(&mut self, request: SignRequest) -> SignResponse {
+        match self.request(request).await {
+            Ok(response) => response,
+            Err(e) => panic!("Signature Service failed to sign: {}", e),
+        }
+    }
+
+    async fn request_vote(
+        &mut self,
+        request: SignRequest,
+    ) -> SignerResult<(Signature, Option<SignatureShare>)> {
+        match self.request(request).await? {
+            SignResponse::Vote(signature, share) => Ok((signature, share)),
+            _ => Err(SignerError::UnexpectedResponse),
+        }
+    }
+
+    pub async fn request_signature(&mut self, digest: Digest) -> Signature {
+        let channel = self.messages.as_ref().unwrap_or(&self.channel);
+        match Self::send(channel, SignRequest::Message(digest)).await {
+            Ok(SignResponse::Signature(signature)) => signature,
+            Ok(_) => panic!("Unexpected response from Signature Service"),
+            Err(e) => panic!("Signature Service failed to sign: {}", e),
+        }
+    }
+
+    // The signer hashes the block itself, and may refuse it (see `DoubleSignGuard`).
+    pub async fn request_block_signature(
+        &mut self,
+        block: BlockRequest,
+    ) -> SignerResult<Signature> {
+        match self.request(SignRequest::Block(block)).await? {
+            SignResponse::Signature(signature) => Ok(signature),
+            _ => Err(SignerError::UnexpectedResponse),
+        }
+    }
+
+    // The signature of a vote, and the share of the quorum key if the signer holds one.
+    pub async fn request_vote_signature(
+        &mut self,
+        vote: VoteRequest,
+    ) -> SignerResult<(Signature, Option<SignatureShare>)> {
+        self.request_vote(SignRequest::Vote(vote)).await
+    }
+
+    pub async fn request_spb_vote_signature(
+        &mut self,
+        vote: SPBVoteRequest,
+    ) -> SignerResult<(Signature, Option<SignatureShare>)> {
+        self.request_vote(SignRequest::SPBVote(vote)).await
+    }
+
     pub async fn request_tss_signature(&mut self, digest: Digest) -> Option<SignatureShare> {
-        let (sender, receiver): (oneshot::Sender<_>, oneshot::Receiver<_>) = oneshot::channel();
-        if let Some(channel) = &self.tss_channel {
-            if let Err(e) = channel.send((digest, sender)).await {
-                panic!("Failed to send message TSS Signature Service: {}", e);
-            }
-            return Some(
-                receiver
-                    .await
-                    .expect("Failed to receive tss signature share from TSS Signature Service"),
-            );
+        if !self.tss {
+            return None;
+        }
+        match self.request_or_panic(SignRequest::Tss(digest)).await {
+            SignResponse::Share(share) => Some(share),
+            _ => panic!("Unexpected response from Signature Service"),
+        }
+    }
+}
+
+// Runs verification jobs on a pool of at most `workers` blocking threads, so that
+// checking signatures does not stall the tasks receiving the messages.
+#[derive(Clone)]
+pub struct VerificationService {
+    workers: Arc<Semaphore>,
+}
+
+impl VerificationService {
+    pub fn new(workers: usize) -> Self {
+        Self {
+            workers: Arc::new(Semaphore::new(workers.max(1))),
         }
-        return None;
+    }
+
+    pub async fn verify<F, T>(&self, job: F) -> T
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let _permit = self
+            .workers
+            .acquire()
+            .await
+            .expect("Verification Service closed");
+        tokio::task::spawn_blocking(job)
+            .await
+            .expect("Failed to run verification job")
     }
 }
 
@@ -280,6 +487,11 @@ pub struct SecretShare {
     pub name: PublicKeyShare,
     pub secret: SerdeSecret<SecretKeyShare>,
     pub pkset: PublicKeySet,
+    // Optional key set with threshold 2f, used to make threshold QCs.
+    #[serde(default)]
+    pub quorum_secret: Option<SerdeSecret<SecretKeyShare>>,
+    #[serde(default)]
+    pub quorum_pkset: Option<PublicKeySet>,
 }
 
 impl SecretShare {
@@ -294,6 +506,8 @@ impl SecretShare {
             name,
             secret,
             pkset,
+            quorum_secret: None,
+            quorum_pkset: None,
         }
     }
 }
@@ -310,6 +524,8 @@ impl Default for SecretShare {
             name: pk_share,
             secret: SerdeSecret(sk_share),
             pkset: pk_set,
+            quorum_secret: None,
+            quorum_pkset: None,
         }
     }
 }
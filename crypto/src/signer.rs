@@ -0,0 +1,483 @@
+use crate::{Digest, Domain, Hash, PublicKey, SecretKey, SecretShare, Signature};
+use ed25519_dalek::Digest as _;
+use ed25519_dalek::Sha512;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+use std::fs;
+use std::io::{self, Read as _, Write as _};
+use std::os::unix::fs::PermissionsExt as _;
+use std::os::unix::net::{UnixListener, UnixStream};
+use thiserror::Error;
+use threshold_crypto::{PublicKeySet, PublicKeyShare, SecretKeyShare, SignatureShare};
+
+#[cfg(test)]
+#[path = "tests/signer_tests.rs"]
+pub mod signer_tests;
+
+// Largest frame exchanged with a remote signer.
+const MAX_FRAME_SIZE: usize = 1_000_000;
+
+// Number of signed blocks and votes remembered by the double-sign guard.
+const GUARD_WINDOW: usize = 10_000;
+
+// Tag of the HotStuff blocks. Fallback blocks are proposed again at the same height
+// in later SMVBA rounds, so only HotStuff blocks are signed once per height.
+const HOTSTUFF_TAG: u8 = 0;
+
+pub type SignerResult<T> = Result<T, SignerError>;
+
+#[derive(Error, Debug, Serialize, Deserialize)]
+pub enum SignerError {
+    #[error("Refused to sign: {0}")]
+    Refused(String),
+
+    #[error("The signer does not hold the {0} key")]
+    MissingKey(String),
+
+    #[error("Signer unavailable: {0}")]
+    Unavailable(String),
+
+    #[error("Unexpected response from the signer")]
+    UnexpectedResponse,
+}
+
+impl From<io::Error> for SignerError {
+    fn from(e: io::Error) -> Self {
+        SignerError::Unavailable(e.to_string())
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum SignRequest {
+    Identity,
+    // A bare digest, for messages that cannot be equivocated (see `Domain`).
+    Message(Digest),
+    // Blocks and votes are hashed by the signer and checked by its guard.
+    Block(BlockRequest),
+    Vote(VoteRequest),
+    SPBVote(SPBVoteRequest),
+    Tss(Digest),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum SignResponse {
+    Identity(Identity),
+    Signature(Signature),
+    Share(SignatureShare),
+    Vote(Signature, Option<SignatureShare>), // with a share of the quorum key, if held
+}
+
+// The fields of a consensus block the signature covers.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BlockRequest {
+    pub author: PublicKey,
+    pub height: u64,
+    pub epoch: u64,
+    pub payload: Vec<Digest>,
+    pub parent: Digest,
+    pub tag: u8,
+}
+
+impl BlockRequest {
+    pub fn slot(&self) -> Option<Slot> {
+        (self.tag == HOTSTUFF_TAG).then_some(Slot {
+            epoch: self.epoch,
+            height: self.height,
+            domain: Domain::Block,
+            round: 0,
+            tag: self.tag,
+            proposer: self.author,
+        })
+    }
+}
+
+impl Hash for BlockRequest {
+    fn digest(&self) -> Digest {
+        let mut hasher = Sha512::new();
+        hasher.update(self.author.0);
+        hasher.update(self.height.to_le_bytes());
+        hasher.update(self.epoch.to_le_bytes());
+        for x in &self.payload {
+            hasher.update(x);
+        }
+        hasher.update(&self.parent);
+        hasher.update(self.tag.to_le_bytes());
+        Digest(hasher.finalize().as_slice()[..32].try_into().unwrap())
+    }
+}
+
+// A HotStuff or fallback vote.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct VoteRequest {
+    pub hash: Digest,
+    pub height: u64,
+    pub round: u64,
+    pub epoch: u64,
+    pub tag: u8,
+    pub proposer: PublicKey,
+}
+
+impl VoteRequest {
+    pub fn slot(&self) -> Slot {
+        Slot {
+            epoch: self.epoch,
+            height: self.height,
+            domain: Domain::Vote,
+            round: self.round,
+            tag: self.tag,
+            proposer: self.proposer,
+        }
+    }
+}
+
+impl Hash for VoteRequest {
+    fn digest(&self) -> Digest {
+        let mut hasher = Sha512::new();
+        hasher.update(&self.hash);
+        hasher.update(self.height.to_le_bytes());
+        hasher.update(self.round.to_le_bytes());
+        hasher.update(self.tag.to_le_bytes());
+        hasher.update(self.epoch.to_le_bytes());
+        hasher.update(self.proposer.0);
+        Digest(hasher.finalize().as_slice()[..32].try_into().unwrap())
+    }
+}
+
+// A vote for an SPB value at some phase.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SPBVoteRequest {
+    pub hash: Digest,
+    pub phase: u8,
+    pub height: u64,
+    pub epoch: u64,
+    pub round: u64,
+    pub proposer: PublicKey,
+}
+
+impl SPBVoteRequest {
+    pub fn slot(&self) -> Slot {
+        Slot {
+            epoch: self.epoch,
+            height: self.height,
+            domain: Domain::SPBVote,
+            round: self.round,
+            tag: self.phase,
+            proposer: self.proposer,
+        }
+    }
+}
+
+impl Hash for SPBVoteRequest {
+    fn digest(&self) -> Digest {
+        let mut hasher = Sha512::new();
+        hasher.update(&self.hash);
+        hasher.update(self.phase.to_be_bytes());
+        hasher.update(self.height.to_le_bytes());
+        hasher.update(self.epoch.to_le_bytes());
+        hasher.update(self.round.to_le_bytes());
+        hasher.update(self.proposer.0);
+        Digest(hasher.finalize().as_slice()[..32].try_into().unwrap())
+    }
+}
+
+// Where an authority signs a single block or vote, as for the evidence of
+// equivocation. Slots sort by epoch and height first, so the guard forgets the
+// oldest ones.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct Slot {
+    pub epoch: u64,
+    pub height: u64,
+    pub domain: Domain,
+    pub round: u64,
+    pub tag: u8, // or the SPB phase
+    pub proposer: PublicKey,
+}
+
+// The public keys of a signer.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Identity {
+    pub name: PublicKey,
+    pub pkset: Option<PublicKeySet>,
+    pub quorum_pkset: Option<PublicKeySet>,
+    pub share: Option<PublicKeyShare>, // must match the share of our id in `pkset`
+}
+
+// Holds the keys of an authority and signs on its behalf. Signers may block, they
+// run on their own thread.
+pub trait Signer: Send + 'static {
+    fn sign(&mut self, request: SignRequest) -> SignerResult<SignResponse>;
+
+    // The key signing plain messages, if held in memory: they can then be signed
+    // on other threads too (see `SignatureService::fork`).
+    fn message_key(&self) -> Option<SecretKey> {
+        None
+    }
+}
+
+impl Signer for Box<dyn Signer> {
+    fn sign(&mut self, request: SignRequest) -> SignerResult<SignResponse> {
+        (**self).sign(request)
+    }
+
+    fn message_key(&self) -> Option<SecretKey> {
+        (**self).message_key()
+    }
+}
+
+// Signs with keys held in memory.
+pub struct LocalSigner {
+    secret: SecretKey,
+    tss_secret: Option<SecretKeyShare>,
+    quorum_secret: Option<SecretKeyShare>,
+    pkset: Option<PublicKeySet>,
+    quorum_pkset: Option<PublicKeySet>,
+    guard: Option<DoubleSignGuard>,
+}
+
+impl LocalSigner {
+    pub fn new(
+        secret: SecretKey,
+        tss_secret: Option<SecretKeyShare>,
+        quorum_secret: Option<SecretKeyShare>,
+    ) -> Self {
+        Self {
+            secret,
+            tss_secret,
+            quorum_secret,
+            pkset: None,
+            quorum_pkset: None,
+            guard: None,
+        }
+    }
+
+    // A signer that can also tell the public key sets of its threshold keys.
+    pub fn from_share(secret: SecretKey, share: SecretShare) -> Self {
+        Self {
+            secret,
+            tss_secret: Some(share.secret.into_inner()),
+            quorum_secret: share.quorum_secret.map(|x| x.into_inner()),
+            pkset: Some(share.pkset),
+            quorum_pkset: share.quorum_pkset,
+            guard: None,
+        }
+    }
+
+    pub fn with_guard(self, guard: DoubleSignGuard) -> Self {
+        Self {
+            guard: Some(guard),
+            ..self
+        }
+    }
+
+    pub fn identity(&self) -> Identity {
+        Identity {
+            name: self.secret.public_key(),
+            pkset: self.pkset.clone(),
+            quorum_pkset: self.quorum_pkset.clone(),
+            share: self.tss_secret.as_ref().map(|x| x.public_key_share()),
+        }
+    }
+
+    fn check(&mut self, slot: Option<Slot>, digest: &Digest) -> SignerResult<()> {
+        match (&mut self.guard, slot) {
+            (Some(guard), Some(slot)) => guard.check(slot, digest),
+            _ => Ok(()),
+        }
+    }
+
+    fn sign_vote(&mut self, slot: Slot, digest: &Digest) -> SignerResult<SignResponse> {
+        self.check(Some(slot), digest)?;
+        let signature = Signature::new_in(slot.domain, digest, &self.secret);
+        let share = self.quorum_secret.as_ref().map(|x| x.sign(digest));
+        Ok(SignResponse::Vote(signature, share))
+    }
+}
+
+impl Signer for LocalSigner {
+    fn message_key(&self) -> Option<SecretKey> {
+        Some(self.secret.clone())
+    }
+
+    fn sign(&mut self, request: SignRequest) -> SignerResult<SignResponse> {
+        let response = match request {
+            SignRequest::Identity => SignResponse::Identity(self.identity()),
+            SignRequest::Message(digest) => {
+                SignResponse::Signature(Signature::new(&digest, &self.secret))
+            }
+            SignRequest::Block(block) => {
+                let digest = block.digest();
+                self.check(block.slot(), &digest)?;
+                SignResponse::Signature(Signature::new_in(Domain::Block, &digest, &self.secret))
+            }
+            SignRequest::Vote(vote) => self.sign_vote(vote.slot(), &vote.digest())?,
+            SignRequest::SPBVote(vote) => self.sign_vote(vote.slot(), &vote.digest())?,
+            SignRequest::Tss(digest) => match &self.tss_secret {
+                Some(secret) => SignResponse::Share(secret.sign(digest)),
+                None => return Err(SignerError::MissingKey("threshold".to_string())),
+            },
+        };
+        Ok(response)
+    }
+}
+
+// Remembers the digest of the block or vote signed in each slot, and refuses to sign
+// a different one there. The record is kept in a file to survive restarts.
+pub struct DoubleSignGuard {
+    path: Option<String>,
+    signed: BTreeMap<Slot, Digest>,
+}
+
+impl DoubleSignGuard {
+    pub fn new(path: Option<&str>) -> SignerResult<Self> {
+        let signed = match path {
+            Some(path) if fs::metadata(path).is_ok() => {
+                let data = fs::read(path)?;
+                bincode::deserialize(&data)
+                    .map_err(|e| SignerError::Unavailable(format!("Bad state file: {}", e)))?
+            }
+            _ => BTreeMap::new(),
+        };
+        Ok(Self {
+            path: path.map(|x| x.to_string()),
+            signed,
+        })
+    }
+
+    pub fn check(&mut self, slot: Slot, digest: &Digest) -> SignerResult<()> {
+        match self.signed.get(&slot) {
+            Some(signed) if signed == digest => return Ok(()),
+            Some(_) => {
+                return Err(SignerError::Refused(format!(
+                    "Already signed another {:?} at epoch {} height {}",
+                    slot.domain, slot.epoch, slot.height
+                )))
+            }
+            None => (),
+        }
+
+        // Slots older than the record cannot be checked.
+        if self.signed.len() >= GUARD_WINDOW {
+            if let Some(oldest) = self.signed.keys().next() {
+                if &slot < oldest {
+                    return Err(SignerError::Refused(format!(
+                        "{:?} at epoch {} height {} is too old",
+                        slot.domain, slot.epoch, slot.height
+                    )));
+                }
+            }
+        }
+
+        self.signed.insert(slot, digest.clone());
+        while self.signed.len() > GUARD_WINDOW {
+            let oldest = *self.signed.keys().next().unwrap();
+            self.signed.remove(&oldest);
+        }
+        self.persist()
+    }
+
+    fn persist(&self) -> SignerResult<()> {
+        if let Some(path) = &self.path {
+            // Write a new file and swap it in, so that a crash leaves a valid record.
+            let tmp = format!("{}.tmp", path);
+            let data = bincode::serialize(&self.signed).expect("Failed to serialize state");
+            fs::write(&tmp, data)?;
+            fs::rename(&tmp, path)?;
+        }
+        Ok(())
+    }
+}
+
+// Forwards the requests to a signer process over a unix socket.
+pub struct RemoteSigner {
+    path: String,
+    stream: Option<UnixStream>,
+}
+
+impl RemoteSigner {
+    pub fn connect(path: &str) -> SignerResult<Self> {
+        Ok(Self {
+            path: path.to_string(),
+            stream: Some(UnixStream::connect(path)?),
+        })
+    }
+
+    pub fn identity(&mut self) -> SignerResult<Identity> {
+        match self.sign(SignRequest::Identity)? {
+            SignResponse::Identity(identity) => Ok(identity),
+            _ => Err(SignerError::UnexpectedResponse),
+        }
+    }
+
+    fn call(&mut self, request: &SignRequest) -> io::Result<SignerResult<SignResponse>> {
+        let stream = match &mut self.stream {
+            Some(stream) => stream,
+            None => self.stream.insert(UnixStream::connect(&self.path)?),
+        };
+        write_frame(stream, request)?;
+        read_frame(stream)
+    }
+}
+
+impl Signer for RemoteSigner {
+    fn sign(&mut self, request: SignRequest) -> SignerResult<SignResponse> {
+        // Reconnect once, in case the signer restarted.
+        match self.call(&request) {
+            Ok(response) => response,
+            Err(_) => {
+                self.stream = None;
+                self.call(&request)?
+            }
+        }
+    }
+}
+
+// The signer process: serves the requests of one node at a time on a unix socket.
+pub struct SignerServer {
+    signer: LocalSigner,
+}
+
+impl SignerServer {
+    pub fn new(signer: LocalSigner) -> Self {
+        Self { signer }
+    }
+
+    pub fn serve(&mut self, path: &str) -> io::Result<()> {
+        let _ = fs::remove_file(path);
+        let listener = UnixListener::bind(path)?;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+        for stream in listener.incoming() {
+            let mut stream = stream?;
+            // Serve the node until it disconnects.
+            while let Ok(request) = read_frame::<SignRequest>(&mut stream) {
+                let response = self.signer.sign(request);
+                if write_frame(&mut stream, &response).is_err() {
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn write_frame<T: Serialize>(stream: &mut UnixStream, value: &T) -> io::Result<()> {
+    let data = bincode::serialize(value).expect("Failed to serialize frame");
+    stream.write_all(&(data.len() as u32).to_be_bytes())?;
+    stream.write_all(&data)
+}
+
+fn read_frame<T: DeserializeOwned>(stream: &mut UnixStream) -> io::Result<T> {
+    let mut length = [0u8; 4];
+    stream.read_exact(&mut length)?;
+    let length = u32::from_be_bytes(length) as usize;
+    if length > MAX_FRAME_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Frame too large",
+        ));
+    }
+    let mut data = vec![0u8; length];
+    stream.read_exact(&mut data)?;
+    bincode::deserialize(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
@@ -129,3 +129,62 @@ async fn signature_service() {
     // Verify the signature we received.
     assert!(signature.verify(&digest, &public_key).is_ok());
 }
+
+#[test]
+fn verify_many_digests() {
+    // Make signatures over different messages.
+    let messages: [&[u8]; 3] = [b"Hello, world!", b"Hello, again!", b"Goodbye!"];
+    let mut keys = keys();
+    let mut items: Vec<_> = messages
+        .iter()
+        .map(|message| {
+            let (public_key, secret_key) = keys.pop().unwrap();
+            let digest = message.digest();
+            let signature = Signature::new(&digest, &secret_key);
+            (Domain::Message, digest, public_key, signature)
+        })
+        .collect();
+    assert!(Signature::verify_many(&items).is_ok());
+
+    // A single invalid signature fails the whole batch.
+    items[1].1 = messages[0].digest();
+    assert!(Signature::verify_many(&items).is_err());
+}
+
+#[test]
+fn separate_domains() {
+    let (public_key, secret_key) = keys().pop().unwrap();
+    let message: &[u8] = b"Hello, world!";
+    let digest = message.digest();
+
+    // A signature over a bare digest does not pass for a block, and conversely.
+    let signature = Signature::new(&digest, &secret_key);
+    assert!(signature.verify(&digest, &public_key).is_ok());
+    assert!(signature
+        .verify_in(Domain::Block, &digest, &public_key)
+        .is_err());
+    let signature = Signature::new_in(Domain::Block, &digest, &secret_key);
+    assert!(signature
+        .verify_in(Domain::Block, &digest, &public_key)
+        .is_ok());
+    assert!(signature.verify(&digest, &public_key).is_err());
+    assert!(signature
+        .verify_in(Domain::Vote, &digest, &public_key)
+        .is_err());
+}
+
+#[tokio::test]
+async fn verification_service() {
+    // Get a keypair and sign a message.
+    let (public_key, secret_key) = keys().pop().unwrap();
+    let message: &[u8] = b"Hello, world!";
+    let digest = message.digest();
+    let signature = Signature::new(&digest, &secret_key);
+
+    // Verify the signature on the worker pool.
+    let service = VerificationService::new(2);
+    let result = service
+        .verify(move || signature.verify(&digest, &public_key))
+        .await;
+    assert!(result.is_ok());
+}
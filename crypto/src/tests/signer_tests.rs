@@ -0,0 +1,204 @@
+use super::*;
+use crate::crypto_tests::keys;
+use crate::SignatureService;
+use std::thread;
+use std::time::Duration;
+
+fn block(author: PublicKey, height: u64, payload: Vec<Digest>) -> BlockRequest {
+    BlockRequest {
+        author,
+        height,
+        epoch: 1,
+        payload,
+        parent: Digest::default(),
+        tag: HOTSTUFF_TAG,
+    }
+}
+
+fn slot(epoch: u64, height: u64) -> Slot {
+    Slot {
+        epoch,
+        height,
+        domain: Domain::Block,
+        round: 0,
+        tag: HOTSTUFF_TAG,
+        proposer: PublicKey::default(),
+    }
+}
+
+#[test]
+fn refuse_double_sign() {
+    let mut guard = DoubleSignGuard::new(None).unwrap();
+    let first: &[u8] = b"Hello, world!";
+    let second: &[u8] = b"Goodbye!";
+
+    // Signing the same block again is fine, but not another one at the same slot.
+    assert!(guard.check(slot(1, 1), &first.digest()).is_ok());
+    assert!(guard.check(slot(1, 1), &first.digest()).is_ok());
+    assert!(guard.check(slot(1, 1), &second.digest()).is_err());
+    assert!(guard.check(slot(1, 2), &second.digest()).is_ok());
+    assert!(guard.check(slot(2, 1), &second.digest()).is_ok());
+}
+
+#[test]
+fn refuse_conflicting_votes() {
+    let (public_key, secret_key) = keys().pop().unwrap();
+    let mut signer =
+        LocalSigner::new(secret_key, None, None).with_guard(DoubleSignGuard::new(None).unwrap());
+    let first: &[u8] = b"Hello, world!";
+    let second: &[u8] = b"Goodbye!";
+    let vote = |hash: Digest, round| VoteRequest {
+        hash,
+        height: 1,
+        round,
+        epoch: 1,
+        tag: HOTSTUFF_TAG,
+        proposer: public_key,
+    };
+
+    // The signer hashes the vote itself and signs it in the vote domain.
+    let request = vote(first.digest(), 0);
+    match signer.sign(SignRequest::Vote(request.clone())) {
+        Ok(SignResponse::Vote(signature, None)) => assert!(signature
+            .verify_in(Domain::Vote, &request.digest(), &public_key)
+            .is_ok()),
+        _ => assert!(false),
+    }
+    assert!(signer
+        .sign(SignRequest::Vote(vote(second.digest(), 0)))
+        .is_err());
+    assert!(signer
+        .sign(SignRequest::Vote(vote(second.digest(), 1)))
+        .is_ok());
+
+    // The same slot holds a different SPB vote.
+    let spb_vote = SPBVoteRequest {
+        hash: second.digest(),
+        phase: 0,
+        height: 1,
+        epoch: 1,
+        round: 0,
+        proposer: public_key,
+    };
+    assert!(signer.sign(SignRequest::SPBVote(spb_vote)).is_ok());
+}
+
+#[test]
+fn guard_survives_restart() {
+    let path = std::env::temp_dir().join(".signer_test_guard_state");
+    let path = path.to_str().unwrap();
+    let _ = fs::remove_file(path);
+    let message: &[u8] = b"Hello, world!";
+    let other: &[u8] = b"Goodbye!";
+
+    let mut guard = DoubleSignGuard::new(Some(path)).unwrap();
+    assert!(guard.check(slot(1, 1), &message.digest()).is_ok());
+
+    // A fresh guard reads the record of the previous one.
+    let mut guard = DoubleSignGuard::new(Some(path)).unwrap();
+    assert!(guard.check(slot(1, 1), &other.digest()).is_err());
+}
+
+#[tokio::test]
+async fn remote_signer() {
+    let path = std::env::temp_dir().join(".signer_test_remote.sock");
+    let path = path.to_str().unwrap().to_string();
+    let (public_key, secret_key) = keys().pop().unwrap();
+
+    // Run the signer process.
+    let signer =
+        LocalSigner::new(secret_key, None, None).with_guard(DoubleSignGuard::new(None).unwrap());
+    let server_path = path.clone();
+    thread::spawn(move || SignerServer::new(signer).serve(&server_path));
+
+    // Connect to it once the socket is up.
+    let mut remote = loop {
+        match RemoteSigner::connect(&path) {
+            Ok(remote) => break remote,
+            Err(_) => thread::sleep(Duration::from_millis(10)),
+        }
+    };
+    let identity = remote.identity().unwrap();
+    assert_eq!(identity.name, public_key);
+    assert!(identity.pkset.is_none());
+
+    // Sign through the signature service.
+    let mut service = SignatureService::new_with_signer(remote, &identity);
+    let message: &[u8] = b"Hello, world!";
+    let request = block(public_key, 1, Vec::new());
+    let signature = service
+        .request_block_signature(request.clone())
+        .await
+        .unwrap();
+    assert!(signature
+        .verify_in(Domain::Block, &request.digest(), &public_key)
+        .is_ok());
+    assert!(service
+        .request_tss_signature(message.digest())
+        .await
+        .is_none());
+
+    // A conflicting block is refused, without bringing the service down.
+    let conflicting = block(public_key, 1, vec![message.digest()]);
+    assert!(service.request_block_signature(conflicting).await.is_err());
+    assert!(service
+        .request_block_signature(block(public_key, 2, Vec::new()))
+        .await
+        .is_ok());
+}
+
+// Holds the key in memory, but never answers.
+struct StuckSigner(SecretKey);
+
+impl Signer for StuckSigner {
+    fn sign(&mut self, _request: SignRequest) -> SignerResult<SignResponse> {
+        loop {
+            thread::park();
+        }
+    }
+
+    fn message_key(&self) -> Option<SecretKey> {
+        Some(self.0.clone())
+    }
+}
+
+#[tokio::test]
+async fn fork_signs_messages_on_its_own() {
+    let (public_key, secret_key) = keys().pop().unwrap();
+    let identity = Identity {
+        name: public_key,
+        pkset: None,
+        quorum_pkset: None,
+        share: None,
+    };
+    let service = SignatureService::new_with_signer(StuckSigner(secret_key), &identity);
+
+    // The signer of the service is busy, its forks are not.
+    let mut busy = service.clone();
+    tokio::spawn(async move { busy.request_signature(Digest::default()).await });
+    let message: &[u8] = b"Hello, world!";
+    let forks: Vec<_> = (0..4)
+        .map(|_| {
+            let mut fork = service.fork();
+            tokio::spawn(async move { fork.request_signature(message.digest()).await })
+        })
+        .collect();
+    let (tx_timeout, rx_timeout) = tokio::sync::oneshot::channel();
+    thread::spawn(move || {
+        thread::sleep(Duration::from_millis(500));
+        let _ = tx_timeout.send(());
+    });
+    let signatures = async {
+        let mut signatures = Vec::new();
+        for fork in forks {
+            signatures.push(fork.await.unwrap());
+        }
+        signatures
+    };
+    tokio::select! {
+        signatures = signatures => assert!(signatures
+            .iter()
+            .all(|x| x.verify(&message.digest(), &public_key).is_ok())),
+        _ = rx_timeout => assert!(false),
+    }
+}
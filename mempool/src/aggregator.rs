@@ -0,0 +1,85 @@
+use crate::config::Committee;
+use crate::error::{MempoolError, MempoolResult};
+use crate::messages::{Certificate, PayloadAck};
+use crypto::{Digest, PublicKey, Signature};
+use std::collections::{HashMap, HashSet};
+
+#[cfg(test)]
+#[path = "tests/aggregator_tests.rs"]
+pub mod aggregator_tests;
+
+// Collects the acks of our own payloads into availability certificates.
+pub struct Aggregator {
+    committee: Committee,
+    certificates_aggregators: HashMap<Digest, Box<CertificateMaker>>,
+}
+
+impl Aggregator {
+    pub fn new(committee: Committee) -> Self {
+        Self {
+            committee,
+            certificates_aggregators: HashMap::new(),
+        }
+    }
+
+    // Only payloads registered here accept acks, so a bad node cannot make us
+    // allocate aggregators for arbitrary digests.
+    pub fn add_payload(&mut self, digest: Digest) {
+        self.certificates_aggregators
+            .entry(digest)
+            .or_insert_with(|| Box::new(CertificateMaker::new()));
+    }
+
+    pub fn add_ack(&mut self, ack: PayloadAck) -> MempoolResult<Option<Certificate>> {
+        match self.certificates_aggregators.get_mut(&ack.digest) {
+            Some(maker) => maker.append(ack, &self.committee),
+            None => Ok(None),
+        }
+    }
+
+    pub fn cleanup(&mut self, digests: &[Digest]) {
+        for x in digests {
+            self.certificates_aggregators.remove(x);
+        }
+    }
+}
+
+struct CertificateMaker {
+    weight: usize,
+    votes: Vec<(PublicKey, Signature)>,
+    used: HashSet<PublicKey>,
+}
+
+impl CertificateMaker {
+    pub fn new() -> Self {
+        Self {
+            weight: 0,
+            votes: Vec::new(),
+            used: HashSet::new(),
+        }
+    }
+
+    /// Try to append an ack to a (partial) certificate.
+    pub fn append(
+        &mut self,
+        ack: PayloadAck,
+        committee: &Committee,
+    ) -> MempoolResult<Option<Certificate>> {
+        let author = ack.author;
+        // Ensure it is the first time this authority acks.
+        ensure!(
+            self.used.insert(author),
+            MempoolError::AuthorityReuseinCertificate(author)
+        );
+        self.votes.push((author, ack.signature));
+        self.weight += 1;
+        if self.weight == committee.quorum_threshold() {
+            // Ensures the certificate is only made once.
+            return Ok(Some(Certificate {
+                digest: ack.digest,
+                votes: self.votes.clone(),
+            }));
+        }
+        Ok(None)
+    }
+}
@@ -1,15 +1,85 @@
 use crate::error::{MempoolError, MempoolResult};
+use consensus::VerificationPolicy;
 use crypto::PublicKey;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::net::SocketAddr;
 
+// Order in which certified payloads are proposed.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+pub enum QueuePolicy {
+    Fifo,     // arrival order: the oldest payload goes first
+    Fair,     // round robin across payload authors, arrival order per author
+    Priority, // highest priority declared by the clients first, arrival order per priority
+}
+
+impl Default for QueuePolicy {
+    fn default() -> Self {
+        QueuePolicy::Fifo
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct Parameters {
     pub queue_capacity: usize,
     pub sync_retry_delay: u64, //同步最大延迟上界
     pub max_payload_size: usize,
-    pub min_block_delay: u64,
+    #[serde(default = "default_max_payload_delay")]
+    pub max_payload_delay: u64, // seal a payload once its oldest transaction waited this long (ms, 0 to seal on size only)
+    #[serde(default)]
+    pub target_commit_latency: u64, // shrink our payloads when they take longer to commit (ms, 0 for fixed-size payloads)
+    #[serde(default = "default_ingestion_workers")]
+    pub ingestion_workers: usize, // tasks building and signing our payloads, client connections are spread across them
+    #[serde(default)]
+    pub dag: bool, // Narwhal-style DAG mempool: consensus orders certified vertices
+    #[serde(default = "default_max_header_delay")]
+    pub max_header_delay: u64, // DAG only: delay between two headers
+    #[serde(default)]
+    pub queue_policy: QueuePolicy,
+    #[serde(default = "default_replay_retention")]
+    pub replay_retention: usize, // number of committed transactions remembered against replays
+    #[serde(default = "default_max_author_payloads")]
+    pub max_author_payloads: usize, // uncommitted payloads stored per authority (0 for no limit)
+    #[serde(default = "default_max_author_rate")]
+    pub max_author_rate: u64, // payloads per second accepted from each authority (0 for no limit)
+    #[serde(default = "default_payload_gc_depth")]
+    pub payload_gc_depth: u64, // rounds after which uncommitted payloads are evicted (0 to keep them)
+    #[serde(default = "default_max_frame_size")]
+    pub max_frame_size: usize, // largest message accepted from a peer, in bytes
+    #[serde(skip)]
+    pub verification: VerificationPolicy, // set by the node, as for consensus
+}
+
+fn default_replay_retention() -> usize {
+    100_000
+}
+
+fn default_max_author_payloads() -> usize {
+    10_000
+}
+
+fn default_max_author_rate() -> u64 {
+    1_000
+}
+
+fn default_payload_gc_depth() -> u64 {
+    100
+}
+
+fn default_max_frame_size() -> usize {
+    network::DEFAULT_MAX_FRAME_SIZE
+}
+
+fn default_ingestion_workers() -> usize {
+    1
+}
+
+fn default_max_payload_delay() -> u64 {
+    100
+}
+
+fn default_max_header_delay() -> u64 {
+    100
 }
 
 impl Default for Parameters {
@@ -18,16 +88,27 @@ impl Default for Parameters {
             queue_capacity: 10_000,
             sync_retry_delay: 10_000,
             max_payload_size: 100_000,
-            min_block_delay: 100,
+            max_payload_delay: default_max_payload_delay(),
+            target_commit_latency: 0,
+            ingestion_workers: default_ingestion_workers(),
+            dag: false,
+            max_header_delay: default_max_header_delay(),
+            queue_policy: QueuePolicy::default(),
+            replay_retention: default_replay_retention(),
+            max_author_payloads: default_max_author_payloads(),
+            max_author_rate: default_max_author_rate(),
+            payload_gc_depth: default_payload_gc_depth(),
+            max_frame_size: default_max_frame_size(),
+            verification: VerificationPolicy::default(),
         }
     }
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct Authority {
-    pub name: PublicKey,             //节点的公钥
-    pub front_address: SocketAddr,   //前端地址
-    pub mempool_address: SocketAddr, //mempool 地址
+    pub name: PublicKey,           //节点的公钥
+    pub front_address: SocketAddr, //前端地址
+    pub address: SocketAddr,       //节点之间的地址, 与共识共用
 }
 
 pub type EpochNumber = u128;
@@ -43,11 +124,11 @@ impl Committee {
         Self {
             authorities: info
                 .into_iter()
-                .map(|(name, front_address, mempool_address)| {
+                .map(|(name, front_address, address)| {
                     let authority = Authority {
                         name,
                         front_address,
-                        mempool_address,
+                        address,
                     };
                     (name, authority)
                 })
@@ -60,6 +141,15 @@ impl Committee {
         self.authorities.contains_key(name)
     }
 
+    pub fn size(&self) -> usize {
+        self.authorities.len()
+    }
+
+    pub fn quorum_threshold(&self) -> usize {
+        // If N = 3f + 1 + k (0 <= k < 3), the quorum is 2f + 1.
+        2 * ((self.size() - 1) / 3) + 1
+    }
+
     pub fn front_address(&self, name: &PublicKey) -> MempoolResult<SocketAddr> {
         self.authorities
             .get(name)
@@ -67,10 +157,10 @@ impl Committee {
             .ok_or_else(|| MempoolError::NotInCommittee(*name))
     }
 
-    pub fn mempool_address(&self, name: &PublicKey) -> MempoolResult<SocketAddr> {
+    pub fn address(&self, name: &PublicKey) -> MempoolResult<SocketAddr> {
         self.authorities
             .get(name)
-            .map(|x| x.mempool_address)
+            .map(|x| x.address)
             .ok_or_else(|| MempoolError::NotInCommittee(*name))
     }
 
@@ -78,7 +168,7 @@ impl Committee {
         self.authorities
             .values()
             .filter(|x| x.name != *myself)
-            .map(|x| x.mempool_address)
+            .map(|x| x.address)
             .collect()
     }
 }
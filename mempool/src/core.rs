@@ -1,18 +1,23 @@
-use crate::config::{Committee, Parameters};
+use crate::aggregator::Aggregator;
+use crate::config::{Committee, Parameters, QueuePolicy};
 use crate::error::{MempoolError, MempoolResult};
-use crate::messages::Payload;
+use crate::messages::{Certificate, Header, Payload, PayloadAck, Transaction, Vertex};
 use crate::payload::PayloadMaker;
+use crate::queue::PayloadQueue;
+use crate::quota::Quotas;
+use crate::replay::ReplayFilter;
 use crate::synchronizer::Synchronizer;
-use consensus::{Block, ConsensusMempoolMessage, PayloadStatus, SeqNumber, OPT, PES};
+use consensus::{
+    Block, ConsensusMempoolMessage, PayloadStatus, SeqNumber, VerificationPolicy, OPT, PES,
+};
 use crypto::Hash as _;
-use crypto::{Digest, PublicKey};
+use crypto::{Digest, PublicKey, SignatureService};
 #[cfg(feature = "benchmark")]
 use log::info;
-use log::{error, warn};
-use network::NetMessage;
+use log::{debug, error, warn};
+use network::{DropPolicy, NetMessage};
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
-// use std::collections::VecDeque;
+use std::collections::{HashSet, VecDeque};
 #[cfg(feature = "benchmark")]
 use std::convert::TryInto as _;
 use store::Store;
@@ -22,11 +27,51 @@ use tokio::sync::mpsc::{Receiver, Sender};
 #[path = "tests/core_tests.rs"]
 pub mod core_tests;
 
+// Number of committed digests remembered to drop their late certificates.
+const MAX_COMMITTED: usize = 100_000;
+
+// The messages between mempools. Our own payloads reach the core on a channel of
+// their own, so that no peer can pass its payloads off as ours.
 #[derive(Deserialize, Serialize, Debug)]
 pub enum MempoolMessage {
-    OwnPayload(Payload),
     Payload(Payload),
     PayloadRequest(Vec<Digest>, PublicKey),
+    PayloadAck(PayloadAck),
+    Certificate(Certificate),
+    Header(Header),
+    Vertex(Vertex),
+}
+
+impl MempoolMessage {
+    // The single place where messages from the network are verified, as far as
+    // the verification policy asks for it.
+    pub fn verify(&self, committee: &Committee, policy: &VerificationPolicy) -> MempoolResult<()> {
+        if !policy.should_verify() {
+            return Ok(());
+        }
+        match self {
+            MempoolMessage::Payload(payload) => payload.verify(committee),
+            MempoolMessage::PayloadAck(ack) => ack.verify(committee),
+            MempoolMessage::Certificate(certificate) => certificate.verify(committee),
+            MempoolMessage::Header(header) => header.verify(committee),
+            MempoolMessage::Vertex(vertex) => vertex.verify(committee),
+            MempoolMessage::PayloadRequest(..) => Ok(()),
+        }
+    }
+
+    // What happens to the message when the network queue is full. Nobody sends
+    // payloads, acks or certificates again, so losing one stalls the payload
+    // for good; sync requests are retried.
+    pub fn drop_policy(&self) -> DropPolicy {
+        match self {
+            MempoolMessage::Payload(..)
+            | MempoolMessage::PayloadAck(..)
+            | MempoolMessage::Certificate(..)
+            | MempoolMessage::Header(..)
+            | MempoolMessage::Vertex(..) => DropPolicy::Block,
+            MempoolMessage::PayloadRequest(..) => DropPolicy::DropNewest,
+        }
+    }
 }
 
 pub struct Core {
@@ -34,13 +79,21 @@ pub struct Core {
     committee: Committee,
     parameters: Parameters,
     store: Store,
+    signature_service: SignatureService,
     synchronizer: Synchronizer,
     payload_maker: PayloadMaker,
+    replay_filter: ReplayFilter,
     core_channel: Receiver<MempoolMessage>,
+    payload_channel: Receiver<Payload>, // our sealed payloads
     consensus_channel: Receiver<ConsensusMempoolMessage>,
     network_channel: Sender<NetMessage>,
-    opt_queue: HashSet<Digest>,
-    pes_queue: HashSet<Digest>,
+    commit_channel: Sender<Vec<Transaction>>, // fresh transactions of committed payloads
+    aggregator: Aggregator,
+    quotas: Quotas,
+    opt_queue: PayloadQueue,
+    pes_queue: PayloadQueue,
+    committed: HashSet<Digest>,
+    committed_order: VecDeque<Digest>,
 }
 
 impl Core {
@@ -50,26 +103,45 @@ impl Core {
         committee: Committee,
         parameters: Parameters,
         store: Store,
+        signature_service: SignatureService,
         synchronizer: Synchronizer,
         payload_maker: PayloadMaker,
+        replay_filter: ReplayFilter,
         core_channel: Receiver<MempoolMessage>,
+        payload_channel: Receiver<Payload>,
         consensus_channel: Receiver<ConsensusMempoolMessage>,
         network_channel: Sender<NetMessage>,
+        commit_channel: Sender<Vec<Transaction>>,
     ) -> Self {
-        let opt_queue = HashSet::with_capacity(parameters.queue_capacity);
-        let pes_queue = HashSet::with_capacity(parameters.queue_capacity * 3 / 2);
+        let aggregator = Aggregator::new(committee.clone());
+        let quotas = Quotas::new(
+            parameters.max_author_payloads,
+            parameters.max_author_rate,
+            parameters.payload_gc_depth,
+        );
+        let opt_queue = PayloadQueue::new(parameters.queue_policy, parameters.queue_capacity);
+        let pes_queue =
+            PayloadQueue::new(parameters.queue_policy, parameters.queue_capacity * 3 / 2);
         Self {
             name,
             committee,
             parameters,
             store,
+            signature_service,
             synchronizer,
             core_channel,
+            payload_channel,
             consensus_channel,
             network_channel,
+            commit_channel,
+            aggregator,
+            quotas,
             opt_queue,
             pes_queue,
+            committed: HashSet::new(),
+            committed_order: VecDeque::new(),
             payload_maker,
+            replay_filter,
         }
     }
 
@@ -100,8 +172,8 @@ impl Core {
     ) -> MempoolResult<()> {
         // Drop the transaction if our mempool is full.
         ensure!(
-            self.opt_queue.len() < self.opt_queue.capacity()
-                && self.pes_queue.len() < self.pes_queue.capacity(),
+            self.opt_queue.len() < self.parameters.queue_capacity
+                && self.pes_queue.len() < self.parameters.queue_capacity * 3 / 2,
             MempoolError::MempoolFull
         );
 
@@ -130,8 +202,12 @@ impl Core {
 
         // Share the payload with all other nodes.
         let message = MempoolMessage::Payload(payload); //向其他节点发送这个payload
-        self.transmit(&message, None).await
-        // Ok(())
+        self.transmit(&message, None).await?;
+
+        // Ack our own payload; the digest enters the queues once it is certified.
+        self.aggregator.add_payload(digest.clone());
+        let ack = PayloadAck::new(digest.clone(), self.name, self.signature_service.clone()).await;
+        self.handle_ack(ack).await
     }
 
     async fn handle_own_payload(&mut self, payload: Payload) -> MempoolResult<()> {
@@ -144,38 +220,82 @@ impl Core {
         // Otherwise, try to add the transaction to the next payload
         // we will add to the queue.
         let digest = payload.digest();
-        self.process_own_payload(&digest, payload).await?; //payload存入queue中
-        self.opt_queue.insert(digest.clone());
-        self.pes_queue.insert(digest);
-        Ok(())
+        self.process_own_payload(&digest, payload).await
     }
 
     async fn handle_others_payload(&mut self, payload: Payload) -> MempoolResult<()> {
-        // Ensure the author of the payload is in the committee.
-        let author = payload.author;
-        ensure!(
-            self.committee.exists(&author),
-            MempoolError::UnknownAuthority(author)
-        );
-
         // Verify that the payload does not exceed the maximum size.
         ensure!(
             payload.size() <= self.parameters.max_payload_size,
             MempoolError::PayloadTooBig
         );
-
-        // Verify that the payload is correctly signed.
+        let author = payload.author;
         let digest = payload.digest();
-        payload.signature.verify(&digest, &author)?;
 
-        // Store payload.
-        // TODO [issue #18]: A bad node may make us store a lot of junk. There is no
-        // limit to how many payloads they can send us, and we will store them all.
+        // Store payload, within the rate limit and storage quota of its author
+        // unless a block references it (this includes our sync requests).
+        if !self.synchronizer.is_referenced(&digest) {
+            self.quotas.check(&author)?;
+        }
         self.store_payload(digest.to_vec(), &payload).await;
+        self.quotas.add(digest.clone(), author);
 
-        // Add the payload to the queue.
-        self.opt_queue.insert(digest.clone());
-        self.pes_queue.insert(digest);
+        // Let the author know we store the payload. It enters our queues once
+        // we receive its certificate.
+        if author != self.name {
+            let ack = PayloadAck::new(digest, self.name, self.signature_service.clone()).await;
+            let message = MempoolMessage::PayloadAck(ack);
+            self.transmit(&message, Some(&author)).await?;
+        }
+        Ok(())
+    }
+
+    async fn handle_ack(&mut self, ack: PayloadAck) -> MempoolResult<()> {
+        debug!("Processing {:?}", ack);
+
+        // Add the ack to our aggregator and see if we have a certificate.
+        if let Some(certificate) = self.aggregator.add_ack(ack)? {
+            debug!("Assembled {:?}", certificate);
+            let message = MempoolMessage::Certificate(certificate.clone());
+            self.transmit(&message, None).await?;
+            self.process_certificate(certificate).await?;
+        }
+        Ok(())
+    }
+
+    async fn handle_certificate(&mut self, certificate: Certificate) -> MempoolResult<()> {
+        debug!("Processing {:?}", certificate);
+
+        self.process_certificate(certificate).await
+    }
+
+    async fn process_certificate(&mut self, certificate: Certificate) -> MempoolResult<()> {
+        // Late certificates must not queue committed payloads again.
+        if self.committed.contains(&certificate.digest) {
+            debug!("Certificate of committed payload {}", certificate.digest);
+            return Ok(());
+        }
+
+        // The fair and priority policies need the payload; we usually stored it
+        // before acking it.
+        let (author, priority) = match self.parameters.queue_policy {
+            QueuePolicy::Fifo => (PublicKey::default(), 0),
+            _ => match self.store.read(certificate.digest.to_vec()).await? {
+                Some(bytes) => {
+                    let payload: Payload = bincode::deserialize(&bytes)?;
+                    (payload.author, payload.priority())
+                }
+                None => (PublicKey::default(), 0),
+            },
+        };
+
+        // Only certified payloads are referenced by blocks: at least f+1 honest
+        // nodes store them (and never evict them) so the synchronizer is
+        // guaranteed to find them.
+        self.quotas.certify(&certificate.digest);
+        self.opt_queue
+            .insert(certificate.digest.clone(), author, priority);
+        self.pes_queue.insert(certificate.digest, author, priority);
         Ok(())
     }
 
@@ -196,44 +316,45 @@ impl Core {
 
     async fn get_payload(&mut self, max: usize, tag: u8) -> MempoolResult<Vec<Digest>> {
         if (tag == OPT && self.opt_queue.is_empty()) || (tag == PES && self.pes_queue.is_empty()) {
-            if let Some(payload) = self.payload_maker.make().await {
+            // Seal a new payload; it will be proposed once certified.
+            for payload in self.payload_maker.make().await {
                 let digest = payload.digest();
                 self.process_own_payload(&digest, payload).await?;
-                Ok(vec![digest])
-            } else {
-                Ok(Vec::new())
-            }
-        } else if tag == OPT {
-            let digest_len = Digest::default().size();
-            // let mut num = max / digest_len;
-            // let mut digests = vec![];
-            // while num > 0 && self.opt_queue.len() > 0 {
-            //     digests.push(self.opt_queue.pop_back().unwrap());
-            //     num = num - 1;
-            // }
-            let digests = self
-                .opt_queue
-                .iter()
-                .take(max / digest_len)
-                .cloned()
-                .collect();
-            for x in &digests {
-                self.opt_queue.remove(x); //去重
             }
-            Ok(digests)
+            Ok(Vec::new())
         } else {
+            // Serve the oldest payloads first, following the queue policy.
             let digest_len = Digest::default().size();
-            let digests = self
-                .pes_queue
-                .iter()
-                .take(max / digest_len)
-                .cloned()
-                .collect();
-            for x in &digests {
-                self.pes_queue.remove(x); //去重
-            }
+            let queue = match tag {
+                OPT => &mut self.opt_queue,
+                _ => &mut self.pes_queue,
+            };
+            Ok(queue.pop(max / digest_len))
+        }
+    }
 
-            Ok(digests)
+    // Hand the fresh transactions of a committed payload to the node.
+    async fn output(&mut self, transactions: Vec<Transaction>) {
+        if transactions.is_empty() {
+            return;
+        }
+        if let Err(e) = self.commit_channel.send(transactions).await {
+            warn!(
+                "Failed to send transactions through the commit channel: {}",
+                e
+            );
+        }
+    }
+
+    fn mark_committed(&mut self, digest: &Digest) {
+        if !self.committed.insert(digest.clone()) {
+            return;
+        }
+        self.committed_order.push_back(digest.clone());
+        if self.committed_order.len() > MAX_COMMITTED {
+            if let Some(x) = self.committed_order.pop_front() {
+                self.committed.remove(&x);
+            }
         }
     }
 
@@ -241,12 +362,51 @@ impl Core {
         self.synchronizer.verify_payload(*block, tag).await
     }
 
-    async fn cleanup(&mut self, digests: Vec<Digest>, round: SeqNumber) {
+    async fn cleanup(&mut self, digests: Vec<Digest>, round: SeqNumber) -> MempoolResult<()> {
         self.synchronizer.cleanup(round).await;
+        self.aggregator.cleanup(&digests);
         for x in &digests {
             self.opt_queue.remove(x);
             self.pes_queue.remove(x);
+            self.quotas.commit(x);
+            self.mark_committed(x);
+
+            // Remember the committed transactions to drop their replays, and skip
+            // the replays that were committed anyway.
+            if let Some(bytes) = self.store.read(x.to_vec()).await? {
+                let payload: Payload = bincode::deserialize(&bytes)?;
+                let fresh: Vec<Transaction> = self
+                    .replay_filter
+                    .commit(&payload.transactions)
+                    .into_iter()
+                    .cloned()
+                    .collect();
+                if fresh.len() < payload.transactions.len() {
+                    debug!(
+                        "Skipping {} replayed transactions of committed payload {}",
+                        payload.transactions.len() - fresh.len(),
+                        x
+                    );
+                }
+                self.output(fresh).await;
+            }
         }
+
+        // The payload maker adapts to the commit latency of our payloads.
+        self.payload_maker.committed(digests).await;
+
+        // Evict the payloads that were never committed. Blocks may reference the
+        // certified ones (or those they already reference): we keep storing them
+        // for a while.
+        for x in self.quotas.cleanup(round) {
+            self.opt_queue.remove(&x.digest);
+            self.pes_queue.remove(&x.digest);
+            if x.delete && !self.synchronizer.is_referenced(&x.digest) {
+                self.store.delete(x.digest.to_vec()).await;
+            }
+        }
+        self.quotas.report();
+        Ok(())
     }
 
     pub async fn run(&mut self) {
@@ -260,12 +420,20 @@ impl Core {
         loop {
             let result = tokio::select! {
                 Some(message) = self.core_channel.recv() => {
+                    if let Err(e) = message.verify(&self.committee, &self.parameters.verification) {
+                        log(Err(&e));
+                        continue;
+                    }
                     match message {
-                        MempoolMessage::OwnPayload(payload) => self.handle_own_payload(payload).await, //处理本地生成的PayLoad,并向其他节点发送payload
                         MempoolMessage::Payload(payload) => self.handle_others_payload(payload).await,  //将其他人发送过来的payload存入本地
                         MempoolMessage::PayloadRequest(digest, sender) => self.handle_request(digest, sender).await,    //返回digest对应的payload
+                        MempoolMessage::PayloadAck(ack) => self.handle_ack(ack).await,  //收集payload的ack，生成可用性证书
+                        MempoolMessage::Certificate(certificate) => self.handle_certificate(certificate).await,
+                        MempoolMessage::Header(..) | MempoolMessage::Vertex(..) => Ok(()), // 仅用于DAG模式
                     }
                 },
+                //处理本地生成的PayLoad,并向其他节点发送payload
+                Some(payload) = self.payload_channel.recv() => self.handle_own_payload(payload).await,
                 Some(message) = self.consensus_channel.recv() => {//处理共识发送的Payload请求
                     match message {
                         ConsensusMempoolMessage::Get(max, sender,tag) => {
@@ -283,7 +451,10 @@ impl Core {
                             };
                             let _ = sender.send(status);
                         },
-                        ConsensusMempoolMessage::Cleanup(digests, round) => self.cleanup(digests, round).await,
+                        ConsensusMempoolMessage::Cleanup(digests, round) => {
+                            let result = self.cleanup(digests, round).await;
+                            log(result.as_ref());
+                        },
                     }
                     Ok(())
                 },
@@ -0,0 +1,568 @@
+use crate::aggregator::Aggregator;
+use crate::config::{Committee, Parameters, QueuePolicy};
+use crate::core::MempoolMessage;
+use crate::error::{MempoolError, MempoolResult};
+use crate::messages::{Header, Payload, PayloadAck, Round, Transaction, Vertex};
+use crate::payload::PayloadMaker;
+use crate::queue::PayloadQueue;
+use crate::quota::Quotas;
+use crate::replay::ReplayFilter;
+use crate::synchronizer::Synchronizer;
+use consensus::{Block, ConsensusMempoolMessage, PayloadStatus, SeqNumber, OPT, PES};
+use crypto::Hash as _;
+use crypto::{Digest, PublicKey, SignatureService};
+#[cfg(feature = "benchmark")]
+use log::info;
+use log::{debug, error, warn};
+use network::NetMessage;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use store::Store;
+use tokio::sync::mpsc::{Receiver, Sender};
+use tokio::time::{interval, Duration};
+
+#[cfg(test)]
+#[path = "tests/dag_tests.rs"]
+pub mod dag_tests;
+
+// Number of rounds of the DAG kept in memory behind the last committed vertex.
+const GC_DEPTH: Round = 50;
+
+// Narwhal-style mempool: workers (the payload maker) batch transactions and the
+// primary builds a round-based DAG of certified headers. Consensus orders the
+// digests of certified vertices instead of raw payload digests.
+pub struct Primary {
+    name: PublicKey,
+    committee: Committee,
+    parameters: Parameters,
+    store: Store,
+    signature_service: SignatureService,
+    synchronizer: Synchronizer,
+    payload_maker: PayloadMaker,
+    replay_filter: ReplayFilter,
+    core_channel: Receiver<MempoolMessage>,
+    payload_channel: Receiver<Payload>, // our sealed payloads
+    consensus_channel: Receiver<ConsensusMempoolMessage>,
+    network_channel: Sender<NetMessage>,
+    commit_channel: Sender<Vec<Transaction>>, // fresh transactions of committed payloads
+    aggregator: Aggregator,
+    quotas: Quotas,
+    round: Round,
+    payloads: Vec<Digest>,  // own payloads waiting for our next header
+    proposed: bool,         // whether we already made a header for the current round
+    header: Option<Header>, // our header waiting for its certificate
+    dag: BTreeMap<Round, HashMap<PublicKey, Digest>>,
+    vertices: HashMap<Digest, Vertex>,
+    pending_headers: HashMap<PublicKey, Header>, // headers we could not vote for yet
+    last_voted: HashMap<PublicKey, Round>,
+    committed_round: Round,
+    committed: HashMap<Digest, Round>, // committed vertices, until garbage collected
+    opt_queue: PayloadQueue,
+    pes_queue: PayloadQueue,
+}
+
+impl Primary {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        name: PublicKey,
+        committee: Committee,
+        parameters: Parameters,
+        store: Store,
+        signature_service: SignatureService,
+        synchronizer: Synchronizer,
+        payload_maker: PayloadMaker,
+        replay_filter: ReplayFilter,
+        core_channel: Receiver<MempoolMessage>,
+        payload_channel: Receiver<Payload>,
+        consensus_channel: Receiver<ConsensusMempoolMessage>,
+        network_channel: Sender<NetMessage>,
+        commit_channel: Sender<Vec<Transaction>>,
+    ) -> Self {
+        let aggregator = Aggregator::new(committee.clone());
+        let quotas = Quotas::new(
+            parameters.max_author_payloads,
+            parameters.max_author_rate,
+            parameters.payload_gc_depth,
+        );
+        let opt_queue = PayloadQueue::new(parameters.queue_policy, parameters.queue_capacity);
+        let pes_queue =
+            PayloadQueue::new(parameters.queue_policy, parameters.queue_capacity * 3 / 2);
+        Self {
+            name,
+            committee,
+            parameters,
+            store,
+            signature_service,
+            synchronizer,
+            payload_maker,
+            replay_filter,
+            core_channel,
+            payload_channel,
+            consensus_channel,
+            network_channel,
+            commit_channel,
+            aggregator,
+            quotas,
+            round: 1,
+            payloads: Vec::new(),
+            proposed: false,
+            header: None,
+            dag: BTreeMap::new(),
+            vertices: HashMap::new(),
+            pending_headers: HashMap::new(),
+            last_voted: HashMap::new(),
+            committed_round: 0,
+            committed: HashMap::new(),
+            opt_queue,
+            pes_queue,
+        }
+    }
+
+    async fn store_value<T: serde::Serialize>(&mut self, key: Vec<u8>, value: &T) {
+        let value = bincode::serialize(value).expect("Failed to serialize value");
+        self.store.write(key, value).await;
+    }
+
+    async fn transmit(
+        &mut self,
+        message: &MempoolMessage,
+        to: Option<&PublicKey>,
+    ) -> MempoolResult<()> {
+        Synchronizer::transmit(
+            message,
+            &self.name,
+            to,
+            &self.committee,
+            &self.network_channel,
+        )
+        .await
+    }
+
+    async fn process_own_payload(&mut self, payload: Payload) -> MempoolResult<()> {
+        // Drop the payload if our mempool is full.
+        ensure!(
+            self.payloads.len() < self.parameters.queue_capacity,
+            MempoolError::MempoolFull
+        );
+
+        let digest = payload.digest();
+
+        #[cfg(feature = "benchmark")]
+        // NOTE: This log entry is used to compute performance.
+        info!("Payload {:?} contains {} B", digest, payload.size());
+
+        // Store the payload and share it with all other nodes.
+        self.store_value(digest.to_vec(), &payload).await;
+        let message = MempoolMessage::Payload(payload);
+        self.transmit(&message, None).await?;
+
+        // The payload will be referenced by our next header.
+        self.payloads.push(digest);
+        Ok(())
+    }
+
+    async fn handle_others_payload(&mut self, payload: Payload) -> MempoolResult<()> {
+        // Verify that the payload does not exceed the maximum size.
+        ensure!(
+            payload.size() <= self.parameters.max_payload_size,
+            MempoolError::PayloadTooBig
+        );
+        let author = payload.author;
+        let digest = payload.digest();
+
+        // Store payload, within the rate limit and storage quota of its author
+        // unless a header or a block references it.
+        if !self.is_referenced(&digest) {
+            self.quotas.check(&author)?;
+        }
+        self.store_value(digest.to_vec(), &payload).await;
+        self.quotas.add(digest, author);
+        Ok(())
+    }
+
+    // Whether a header we know (or a block, through the synchronizer) references
+    // the payload: it must be stored.
+    fn is_referenced(&self, digest: &Digest) -> bool {
+        self.synchronizer.is_referenced(digest)
+            || self
+                .pending_headers
+                .values()
+                .any(|x| x.payloads.contains(digest))
+            || self
+                .vertices
+                .values()
+                .any(|x| x.header.payloads.contains(digest))
+    }
+
+    async fn handle_request(
+        &mut self,
+        digests: Vec<Digest>,
+        requestor: PublicKey,
+    ) -> MempoolResult<()> {
+        for digest in &digests {
+            let message = match self.vertices.get(digest) {
+                Some(vertex) => MempoolMessage::Vertex(vertex.clone()),
+                None => match self.store.read(digest.to_vec()).await? {
+                    Some(bytes) => MempoolMessage::Payload(bincode::deserialize(&bytes)?),
+                    None => continue,
+                },
+            };
+            self.transmit(&message, Some(&requestor)).await?;
+        }
+        Ok(())
+    }
+
+    async fn try_propose(&mut self) -> MempoolResult<()> {
+        if self.proposed {
+            return Ok(());
+        }
+
+        // Link to the certified vertices of the previous round.
+        let parents: Vec<_> = match self.round {
+            1 => Vec::new(),
+            r => self
+                .dag
+                .get(&(r - 1))
+                .map(|x| x.values().cloned().collect())
+                .unwrap_or_default(),
+        };
+        if self.round > 1 && parents.len() < self.committee.quorum_threshold() {
+            return Ok(());
+        }
+
+        // Our previous header was not certified in time and will not be: its
+        // payloads go into the new one.
+        if let Some(header) = self.header.take() {
+            debug!("Requeuing the payloads of uncertified {:?}", header);
+            self.payloads.splice(0..0, header.payloads);
+        }
+
+        let header = Header::new(
+            self.name,
+            self.round,
+            self.payloads.drain(..).collect(),
+            parents,
+            self.signature_service.clone(),
+        )
+        .await;
+        debug!("Created {:?}", header);
+        self.proposed = true;
+        self.header = Some(header.clone());
+
+        let message = MempoolMessage::Header(header.clone());
+        self.transmit(&message, None).await?;
+
+        // Vote for our own header.
+        let digest = header.digest();
+        self.aggregator.add_payload(digest.clone());
+        self.last_voted.insert(self.name, header.round);
+        let ack = PayloadAck::new(digest, self.name, self.signature_service.clone()).await;
+        self.handle_ack(ack).await
+    }
+
+    async fn handle_header(&mut self, header: Header) -> MempoolResult<()> {
+        debug!("Processing {:?}", header);
+
+        // Ensure we did not vote for this author at this round yet.
+        if header.round <= *self.last_voted.get(&header.author).unwrap_or(&0)
+            || header.round + GC_DEPTH <= self.committed_round
+        {
+            return Ok(());
+        }
+
+        // Ensure we know all parents and store all payloads; if not, retry later.
+        let parents_known = header.round == 1
+            || match self.dag.get(&(header.round - 1)) {
+                Some(vertices) => {
+                    let known: HashSet<_> = vertices.values().collect();
+                    header.parents.iter().all(|x| known.contains(x))
+                }
+                None => false,
+            };
+        let mut missing = Vec::new();
+        for x in &header.payloads {
+            if self.store.read(x.to_vec()).await?.is_none() {
+                missing.push(x.clone());
+            }
+        }
+        if !parents_known || !missing.is_empty() {
+            debug!("Processing of {:?} suspended", header);
+            if !missing.is_empty() {
+                let message = MempoolMessage::PayloadRequest(missing, self.name);
+                self.transmit(&message, Some(&header.author)).await?;
+            }
+            self.pending_headers.insert(header.author, header);
+            return Ok(());
+        }
+
+        // Vote for the header.
+        self.pending_headers.remove(&header.author);
+        self.last_voted.insert(header.author, header.round);
+        let ack = PayloadAck::new(header.digest(), self.name, self.signature_service.clone()).await;
+        let message = MempoolMessage::PayloadAck(ack);
+        self.transmit(&message, Some(&header.author)).await
+    }
+
+    async fn handle_ack(&mut self, ack: PayloadAck) -> MempoolResult<()> {
+        debug!("Processing {:?}", ack);
+
+        // Add the vote to our aggregator and see if we certified our header.
+        if let Some(certificate) = self.aggregator.add_ack(ack)? {
+            debug!("Assembled {:?}", certificate);
+            let header = match self.header.take() {
+                Some(header) if header.digest() == certificate.digest => header,
+                _ => return Ok(()),
+            };
+            let vertex = Vertex {
+                header,
+                certificate,
+            };
+            let message = MempoolMessage::Vertex(vertex.clone());
+            self.transmit(&message, None).await?;
+            self.process_vertex(vertex).await?;
+        }
+        Ok(())
+    }
+
+    async fn handle_vertex(&mut self, vertex: Vertex) -> MempoolResult<()> {
+        debug!("Processing {:?}", vertex);
+
+        self.process_vertex(vertex).await
+    }
+
+    async fn process_vertex(&mut self, vertex: Vertex) -> MempoolResult<()> {
+        let digest = vertex.digest();
+        let round = vertex.header.round;
+        let author = vertex.header.author;
+        if self.vertices.contains_key(&digest)
+            || self.committed.contains_key(&digest)
+            || round + GC_DEPTH <= self.committed_round
+        {
+            return Ok(());
+        }
+
+        // A vertex is as urgent as the most urgent of its payloads.
+        let mut priority = 0;
+        if self.parameters.queue_policy == QueuePolicy::Priority {
+            for x in &vertex.header.payloads {
+                if let Some(bytes) = self.store.read(x.to_vec()).await? {
+                    let payload: Payload = bincode::deserialize(&bytes)?;
+                    priority = priority.max(payload.priority());
+                }
+            }
+        }
+
+        // Add the vertex to the DAG; it can now be ordered by consensus.
+        self.store_value(digest.to_vec(), &vertex).await;
+        self.dag
+            .entry(round)
+            .or_default()
+            .insert(author, digest.clone());
+        self.vertices.insert(digest.clone(), vertex);
+        self.opt_queue.insert(digest.clone(), author, priority);
+        self.pes_queue.insert(digest, author, priority);
+
+        // Move to the next round once a quorum of the current round is certified;
+        // a late vertex may complete several rounds at once.
+        while self.dag.get(&self.round).map_or(0, |x| x.len()) >= self.committee.quorum_threshold()
+        {
+            self.round += 1;
+            self.proposed = false;
+            debug!("DAG moved to round {}", self.round);
+        }
+        Ok(())
+    }
+
+    async fn retry_pending_headers(&mut self) -> MempoolResult<()> {
+        let headers: Vec<_> = self.pending_headers.drain().map(|(_, x)| x).collect();
+        for header in headers {
+            self.handle_header(header).await?;
+        }
+        Ok(())
+    }
+
+    async fn get_payload(&mut self, max: usize, tag: u8) -> MempoolResult<Vec<Digest>> {
+        let queue = match tag {
+            OPT => &mut self.opt_queue,
+            PES => &mut self.pes_queue,
+            _ => return Ok(Vec::new()),
+        };
+        if queue.is_empty() {
+            // Seal the pending transactions so they make it into our next header.
+            for payload in self.payload_maker.make().await {
+                self.process_own_payload(payload).await?;
+            }
+            return Ok(Vec::new());
+        }
+        let digest_len = Digest::default().size();
+        Ok(queue.pop(max / digest_len))
+    }
+
+    // Hand the fresh transactions of a committed payload to the node.
+    async fn output(&mut self, transactions: Vec<Transaction>) {
+        if transactions.is_empty() {
+            return;
+        }
+        if let Err(e) = self.commit_channel.send(transactions).await {
+            warn!(
+                "Failed to send transactions through the commit channel: {}",
+                e
+            );
+        }
+    }
+
+    async fn verify_payload(&mut self, block: Box<Block>, tag: u8) -> MempoolResult<bool> {
+        self.synchronizer.verify_payload(*block, tag).await
+    }
+
+    // The vertices in the causal history of a committed vertex that are not
+    // committed yet, oldest round first. Every node commits them in this order.
+    fn uncommitted_history(&self, digest: &Digest) -> Vec<Digest> {
+        let mut history = Vec::new();
+        let mut visited = HashSet::new();
+        let mut stack = vec![digest.clone()];
+        while let Some(x) = stack.pop() {
+            if self.committed.contains_key(&x) || !visited.insert(x.clone()) {
+                continue;
+            }
+            if let Some(vertex) = self.vertices.get(&x) {
+                stack.extend(vertex.header.parents.iter().cloned());
+                history.push((vertex.header.round, vertex.header.author, x));
+            }
+        }
+        history.sort_by_key(|(round, author, x)| (*round, *author, x.to_vec()));
+        history.into_iter().map(|(_, _, x)| x).collect()
+    }
+
+    async fn cleanup(&mut self, digests: Vec<Digest>, round: SeqNumber) -> MempoolResult<()> {
+        self.synchronizer.cleanup(round).await;
+
+        // Commit the leaders along with their causal history.
+        let mut history = Vec::new();
+        for x in &digests {
+            for y in self.uncommitted_history(x) {
+                let round = self.vertices.get(&y).map_or(0, |v| v.header.round);
+                self.committed.insert(y.clone(), round);
+                history.push(y);
+            }
+        }
+        for x in &history {
+            self.opt_queue.remove(x);
+            self.pes_queue.remove(x);
+            let payloads = match self.vertices.get(x) {
+                Some(vertex) => {
+                    self.committed_round = self.committed_round.max(vertex.header.round);
+                    vertex.header.payloads.clone()
+                }
+                None => Vec::new(),
+            };
+
+            // Remember the committed transactions to drop their replays, and skip
+            // the replays that were committed anyway.
+            for digest in &payloads {
+                self.quotas.commit(digest);
+                if let Some(bytes) = self.store.read(digest.to_vec()).await? {
+                    let payload: Payload = bincode::deserialize(&bytes)?;
+                    let fresh: Vec<Transaction> = self
+                        .replay_filter
+                        .commit(&payload.transactions)
+                        .into_iter()
+                        .cloned()
+                        .collect();
+                    if fresh.len() < payload.transactions.len() {
+                        debug!(
+                            "Skipping {} replayed transactions of committed payload {}",
+                            payload.transactions.len() - fresh.len(),
+                            digest
+                        );
+                    }
+                    self.output(fresh).await;
+                }
+            }
+
+            // The payload maker adapts to the commit latency of our payloads.
+            self.payload_maker.committed(payloads).await;
+        }
+
+        // Garbage collect the rounds of the DAG far behind the committed ones.
+        if self.committed_round > GC_DEPTH {
+            let gc_round = self.committed_round - GC_DEPTH;
+            self.dag.retain(|r, _| *r > gc_round);
+            self.vertices.retain(|_, x| x.header.round > gc_round);
+            self.committed.retain(|_, r| *r > gc_round);
+            self.aggregator.cleanup(&history);
+        }
+
+        // Evict the payloads that were never committed, unless still referenced.
+        for x in self.quotas.cleanup(round) {
+            if x.delete && !self.is_referenced(&x.digest) {
+                self.store.delete(x.digest.to_vec()).await;
+            }
+        }
+        self.quotas.report();
+        Ok(())
+    }
+
+    pub async fn run(&mut self) {
+        let log = |result: Result<&(), &MempoolError>| match result {
+            Ok(()) => (),
+            Err(MempoolError::StoreError(e)) => error!("{}", e),
+            Err(MempoolError::SerializationError(e)) => error!("Store corrupted. {}", e),
+            Err(e) => warn!("{}", e),
+        };
+
+        let mut timer = interval(Duration::from_millis(
+            self.parameters.max_header_delay.max(1),
+        ));
+        loop {
+            let result = tokio::select! {
+                Some(message) = self.core_channel.recv() => {
+                    if let Err(e) = message.verify(&self.committee, &self.parameters.verification) {
+                        log(Err(&e));
+                        continue;
+                    }
+                    match message {
+                        MempoolMessage::Payload(payload) => self.handle_others_payload(payload).await,
+                        MempoolMessage::PayloadRequest(digests, sender) => self.handle_request(digests, sender).await,
+                        MempoolMessage::PayloadAck(ack) => self.handle_ack(ack).await,
+                        MempoolMessage::Header(header) => self.handle_header(header).await,
+                        MempoolMessage::Vertex(vertex) => self.handle_vertex(vertex).await,
+                        MempoolMessage::Certificate(..) => Ok(()),
+                    }
+                },
+                Some(payload) = self.payload_channel.recv() => self.process_own_payload(payload).await,
+                Some(message) = self.consensus_channel.recv() => {
+                    match message {
+                        ConsensusMempoolMessage::Get(max, sender, tag) => {
+                            let result = self.get_payload(max, tag).await;
+                            log(result.as_ref().map(|_| &()));
+                            let _ = sender.send(result.unwrap_or_default());
+                        },
+                        ConsensusMempoolMessage::Verify(block, sender, tag) => {
+                            let result = self.verify_payload(block, tag).await;
+                            log(result.as_ref().map(|_| &()));
+                            let status = match result {
+                                Ok(true) => PayloadStatus::Accept,
+                                Ok(false) => PayloadStatus::Wait,
+                                Err(_) => PayloadStatus::Reject,
+                            };
+                            let _ = sender.send(status);
+                        },
+                        ConsensusMempoolMessage::Cleanup(digests, round) => {
+                            let result = self.cleanup(digests, round).await;
+                            log(result.as_ref());
+                        },
+                    }
+                    Ok(())
+                },
+                _ = timer.tick() => {
+                    match self.retry_pending_headers().await {
+                        Ok(()) => self.try_propose().await,
+                        Err(e) => Err(e),
+                    }
+                },
+                else => break,
+            };
+            log(result.as_ref());
+        }
+    }
+}
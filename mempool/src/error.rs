@@ -1,4 +1,5 @@
-use crypto::{CryptoError, PublicKey};
+use crate::messages::Round;
+use crypto::{CryptoError, Digest, PublicKey};
 use store::StoreError;
 use thiserror::Error;
 
@@ -42,4 +43,25 @@ pub enum MempoolError {
 
     #[error("Node {0:?} is not in the committee")]
     NotInCommittee(PublicKey),
+
+    #[error("Received more than one ack from {0:?}")]
+    AuthorityReuseinCertificate(PublicKey),
+
+    #[error("Received certificate without a quorum")]
+    CertificateRequiresQuorum,
+
+    #[error("Received header of round {0} without a quorum of parents")]
+    HeaderRequiresQuorum(Round),
+
+    #[error("Malformed vertex {0}")]
+    MalformedVertex(Digest),
+
+    #[error("Rate limit exceeded by {0}: dropping payload")]
+    AuthorRateLimited(PublicKey),
+
+    #[error("Storage quota exceeded by {0}: dropping payload")]
+    AuthorQuotaExceeded(PublicKey),
+
+    #[error("Channel to {0} is closed")]
+    ChannelClosed(&'static str),
 }
@@ -1,23 +1,35 @@
 use crate::messages::Transaction;
+use crate::replay::ReplayFilter;
 use futures::stream::StreamExt as _;
 use log::{debug, warn};
 use std::net::SocketAddr;
 use tokio::net::{TcpListener, TcpStream};
 use tokio::sync::mpsc::Sender;
+use tokio::task::JoinSet;
 use tokio_util::codec::{Framed, LengthDelimitedCodec};
 
 pub struct Front {
     address: SocketAddr,
-    deliver: Sender<Transaction>,
+    deliver: Vec<Sender<Transaction>>, // one per ingestion worker
+    replay_filter: ReplayFilter,
 }
 
 impl Front {
-    pub fn new(address: SocketAddr, deliver: Sender<Transaction>) -> Self {
-        Self { address, deliver }
+    pub fn new(
+        address: SocketAddr,
+        deliver: Vec<Sender<Transaction>>,
+        replay_filter: ReplayFilter,
+    ) -> Self {
+        Self {
+            address,
+            deliver,
+            replay_filter,
+        }
     }
 
     // For each incoming request, we spawn a new worker responsible to receive
-    // messages and replay them through the provided deliver channel.
+    // messages and replay them through one of the deliver channels, taken in
+    // turn so that the ingestion workers share the clients.
     pub async fn run(&self) {
         //监听前端地址
         let listener = TcpListener::bind(&self.address)
@@ -25,26 +37,52 @@ impl Front {
             .expect("Failed to bind to TCP port");
 
         debug!("Listening for client transactions on {}", self.address);
+        // Dropping this future closes the listener and all client connections.
+        let mut workers = JoinSet::new();
+        let mut next = 0;
         loop {
-            let (socket, peer) = match listener.accept().await {
-                Ok(value) => value,
-                Err(e) => {
-                    warn!("Failed to connect with client: {}", e);
-                    continue;
-                }
-            };
-            debug!("Connection established with client {}", peer);
-            Self::spawn_worker(socket, peer, self.deliver.clone()).await;
+            tokio::select! {
+                result = listener.accept() => {
+                    let (socket, peer) = match result {
+                        Ok(value) => value,
+                        Err(e) => {
+                            warn!("Failed to connect with client: {}", e);
+                            continue;
+                        }
+                    };
+                    debug!("Connection established with client {}", peer);
+                    let replay_filter = self.replay_filter.clone();
+                    let deliver = self.deliver[next % self.deliver.len()].clone();
+                    next += 1;
+                    Self::spawn_worker(&mut workers, socket, peer, deliver, replay_filter);
+                },
+                Some(_) = workers.join_next() => (),
+            }
         }
     }
 
-    async fn spawn_worker(socket: TcpStream, peer: SocketAddr, deliver: Sender<Transaction>) {
-        tokio::spawn(async move {
+    fn spawn_worker(
+        workers: &mut JoinSet<()>,
+        socket: TcpStream,
+        peer: SocketAddr,
+        deliver: Sender<Transaction>,
+        replay_filter: ReplayFilter,
+    ) {
+        workers.spawn(async move {
             let mut transport = Framed::new(socket, LengthDelimitedCodec::new());
             while let Some(frame) = transport.next().await {
                 match frame {
                     //接收客户端发送过来的消息 存入client——sender
-                    Ok(x) => deliver.send(x.to_vec()).await.expect("Core channel closed"),
+                    // 丢弃已经提交过的交易
+                    Ok(x) if replay_filter.is_committed(&x.to_vec()) => {
+                        debug!("Dropping replayed transaction from client {}", peer)
+                    }
+                    Ok(x) => {
+                        if deliver.send(x.to_vec()).await.is_err() {
+                            debug!("Mempool is gone, closing connection with client {}", peer);
+                            return;
+                        }
+                    }
                     Err(e) => {
                         warn!("Failed to receive client transaction: {}", e);
                         return;
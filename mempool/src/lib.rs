@@ -1,18 +1,23 @@
 #[macro_use]
 mod error;
+mod aggregator;
 mod config;
 mod core;
+mod dag;
 mod front;
 mod mempool;
 mod messages;
 mod payload;
+mod queue;
+mod quota;
+mod replay;
 mod synchronizer;
 
 #[cfg(test)]
 #[path = "tests/common.rs"]
 mod common;
 
-pub use crate::config::{Committee, Parameters};
+pub use crate::config::{Committee, Parameters, QueuePolicy};
 pub use crate::error::MempoolError;
 pub use crate::mempool::Mempool;
-pub use crate::messages::Payload;
+pub use crate::messages::{Certificate, Payload, Transaction};
@@ -1,13 +1,16 @@
 use crate::config::{Committee, Parameters};
 use crate::core::Core;
+use crate::dag::Primary;
 use crate::error::MempoolResult;
 use crate::front::Front;
+use crate::messages::Transaction;
 use crate::payload::PayloadMaker;
+use crate::replay::ReplayFilter;
 use crate::synchronizer::Synchronizer;
 use consensus::{ConsensusMempoolMessage, ConsensusMessage};
 use crypto::{PublicKey, SignatureService};
 use log::info;
-use network::{NetReceiver, NetSender};
+use network::{ChannelId, Mux, Shutdown};
 use store::Store;
 use tokio::sync::mpsc::{channel, Receiver, Sender};
 
@@ -15,8 +18,13 @@ use tokio::sync::mpsc::{channel, Receiver, Sender};
 #[path = "tests/mempool_tests.rs"]
 pub mod mempool_tests;
 
+// The channel of the mempool on the connections between nodes. Payloads are
+// large, they go after the consensus messages.
+pub const MEMPOOL_CHANNEL: ChannelId = 2;
+
 pub struct Mempool;
 
+#[allow(clippy::too_many_arguments)]
 impl Mempool {
     pub fn run(
         name: PublicKey,
@@ -27,6 +35,9 @@ impl Mempool {
         consensus_channel: Sender<ConsensusMessage>,
         consensus_channel_smvba: Sender<ConsensusMessage>,
         consensus_mempool_channel: Receiver<ConsensusMempoolMessage>,
+        commit_channel: Sender<Vec<Transaction>>, // fresh transactions of committed payloads
+        mux: &mut Mux,                            // the connections of the node, run by the caller
+        shutdown: Shutdown,                       // stops the front and the core
     ) -> MempoolResult<()> {
         info!(
             "Mempool queue capacity set to {} payloads",
@@ -37,13 +48,29 @@ impl Mempool {
             parameters.max_payload_size
         );
         info!(
-            "Mempool min block delay set to {} ms",
-            parameters.min_block_delay
+            "Mempool max payload delay set to {} ms",
+            parameters.max_payload_delay
+        );
+        info!(
+            "Mempool target commit latency set to {} ms",
+            parameters.target_commit_latency
+        );
+        info!(
+            "Mempool ingestion workers set to {}",
+            parameters.ingestion_workers
+        );
+        info!(
+            "Mempool max frame size set to {} B",
+            parameters.max_frame_size
         );
 
         let (tx_network, rx_network) = channel(10000);
         let (tx_core, rx_core) = channel(10000);
-        let (tx_client, rx_client) = channel(10000);
+        let (tx_payload, rx_payload) = channel(10000);
+        // Each ingestion worker gets its share of the client connections.
+        let (tx_clients, rx_clients): (Vec<_>, Vec<_>) = (0..parameters.ingestion_workers.max(1))
+            .map(|_| channel(10000))
+            .unzip();
 
         // Run the front end that receives client transactions.
         let address = committee.front_address(&name).map(|mut x| {
@@ -51,27 +78,19 @@ impl Mempool {
             x
         })?;
 
+        // Committed transactions are dropped at the front and when sealing our
+        // payloads, and skipped when committed again.
+        let replay_filter = ReplayFilter::new(parameters.replay_retention);
+
         //启动前端服务 用于客户端提交tx
-        let front = Front::new(address, tx_client);
-        tokio::spawn(async move {
+        let front = Front::new(address, tx_clients, replay_filter.clone());
+        shutdown.spawn(async move {
             front.run().await;
         });
 
-        // Run the mempool network sender and receiver.
-        let address = committee.mempool_address(&name).map(|mut x| {
-            x.set_ip("0.0.0.0".parse().unwrap());
-            x
-        })?;
-        //启动 mempool   用节点之间进行tx的通信
-        let network_receiver = NetReceiver::new(address, tx_core.clone());
-        tokio::spawn(async move {
-            network_receiver.run().await;
-        });
-
-        let mut network_sender = NetSender::new(rx_network);
-        tokio::spawn(async move {
-            network_sender.run().await;
-        });
+        // Receive and send our messages on the connections of the node.
+        mux.receiver(MEMPOOL_CHANNEL, tx_core, parameters.max_frame_size);
+        shutdown.spawn(mux.sender(MEMPOOL_CHANNEL).forward(rx_network));
 
         // Build and run the synchronizer.
         //用于发送消息
@@ -88,26 +107,59 @@ impl Mempool {
         // Build and run the payload maker.
         let payload_maker = PayloadMaker::new(
             name,
-            signature_service,
+            signature_service.clone(),
             parameters.max_payload_size,
-            parameters.min_block_delay,
-            rx_client,
-            tx_core,
+            parameters.max_payload_delay,
+            parameters.target_commit_latency,
+            replay_filter.clone(),
+            rx_clients,
+            tx_payload,
         );
 
+        // Run the DAG primary instead of the core if enabled.
+        if parameters.dag {
+            info!(
+                "Mempool DAG enabled with max header delay {} ms",
+                parameters.max_header_delay
+            );
+            let mut primary = Primary::new(
+                name,
+                committee,
+                parameters,
+                store,
+                signature_service,
+                synchronizer,
+                payload_maker,
+                replay_filter,
+                /* core_channel */ rx_core,
+                /* payload_channel */ rx_payload,
+                consensus_mempool_channel,
+                /* network_channel */ tx_network,
+                commit_channel,
+            );
+            shutdown.spawn(async move {
+                primary.run().await;
+            });
+            return Ok(());
+        }
+
         // Run the core.
         let mut core = Core::new(
             name,
             committee,
             parameters,
             store,
+            signature_service,
             synchronizer,
             payload_maker,
+            replay_filter,
             /* core_channel */ rx_core,
+            /* payload_channel */ rx_payload,
             consensus_mempool_channel,
             /* network_channel */ tx_network,
+            commit_channel,
         );
-        tokio::spawn(async move {
+        shutdown.spawn(async move {
             core.run().await;
         });
 
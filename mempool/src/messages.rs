@@ -1,12 +1,26 @@
+use crate::config::Committee;
+use crate::error::{MempoolError, MempoolResult};
 use crypto::{Digest, Hash, PublicKey, Signature, SignatureService};
 use ed25519_dalek::Digest as _;
 use ed25519_dalek::Sha512;
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::convert::TryInto;
 use std::fmt;
 
 pub type Transaction = Vec<u8>;
 
+// Clients may declare the priority of a transaction in the byte following its
+// tag and id (see the benchmark client); it is 0 otherwise.
+pub const PRIORITY_OFFSET: usize = 9;
+
+pub fn priority(transaction: &Transaction) -> u8 {
+    transaction
+        .get(PRIORITY_OFFSET)
+        .copied()
+        .unwrap_or_default()
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct Payload {
     pub transactions: Vec<Transaction>,
@@ -35,6 +49,27 @@ impl Payload {
     pub fn size(&self) -> usize {
         self.transactions.iter().map(|x| x.len()).sum()
     }
+
+    // The highest priority declared by the transactions of the payload.
+    pub fn priority(&self) -> u8 {
+        self.transactions
+            .iter()
+            .map(priority)
+            .max()
+            .unwrap_or_default()
+    }
+
+    pub fn verify(&self, committee: &Committee) -> MempoolResult<()> {
+        // Ensure the author of the payload is in the committee.
+        ensure!(
+            committee.exists(&self.author),
+            MempoolError::UnknownAuthority(self.author)
+        );
+
+        // Verify that the payload is correctly signed.
+        self.signature.verify(&self.digest(), &self.author)?;
+        Ok(())
+    }
 }
 
 impl Hash for Payload {
@@ -53,3 +88,218 @@ impl fmt::Debug for Payload {
         write!(f, "Payload({}, {})", self.digest(), self.size())
     }
 }
+
+// Signed echo of a payload: the author of the ack stores the payload.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct PayloadAck {
+    pub digest: Digest, // digest of the acknowledged payload
+    pub author: PublicKey,
+    pub signature: Signature,
+}
+
+impl PayloadAck {
+    pub async fn new(
+        digest: Digest,
+        author: PublicKey,
+        mut signature_service: SignatureService,
+    ) -> Self {
+        let ack = Self {
+            digest,
+            author,
+            signature: Signature::default(),
+        };
+        let signature = signature_service.request_signature(ack.digest()).await;
+        Self { signature, ..ack }
+    }
+
+    pub fn verify(&self, committee: &Committee) -> MempoolResult<()> {
+        // Ensure the authority is in the committee.
+        ensure!(
+            committee.exists(&self.author),
+            MempoolError::UnknownAuthority(self.author)
+        );
+
+        // Check the signature.
+        self.signature.verify(&self.digest(), &self.author)?;
+        Ok(())
+    }
+}
+
+impl Hash for PayloadAck {
+    fn digest(&self) -> Digest {
+        //与Certificate对应
+        let mut hasher = Sha512::new();
+        hasher.update(&self.digest);
+        Digest(hasher.finalize().as_slice()[..32].try_into().unwrap())
+    }
+}
+
+impl fmt::Debug for PayloadAck {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "PayloadAck({}, {})", self.digest, self.author)
+    }
+}
+
+// Availability certificate: 2f+1 nodes acknowledged they store the payload,
+// so at least f+1 honest nodes can serve it to the synchronizer.
+#[derive(Clone, Deserialize, Serialize, Default)]
+pub struct Certificate {
+    pub digest: Digest,
+    pub votes: Vec<(PublicKey, Signature)>,
+}
+
+impl Certificate {
+    pub fn verify(&self, committee: &Committee) -> MempoolResult<()> {
+        // Ensure the certificate has a quorum.
+        let mut used = HashSet::new();
+        for (name, _) in self.votes.iter() {
+            ensure!(
+                used.insert(*name),
+                MempoolError::AuthorityReuseinCertificate(*name)
+            );
+            ensure!(
+                committee.exists(name),
+                MempoolError::UnknownAuthority(*name)
+            );
+        }
+        ensure!(
+            used.len() >= committee.quorum_threshold(),
+            MempoolError::CertificateRequiresQuorum
+        );
+
+        // Check the signatures.
+        Signature::verify_batch(&self.digest(), &self.votes).map_err(MempoolError::from)
+    }
+}
+
+impl Hash for Certificate {
+    fn digest(&self) -> Digest {
+        //与PayloadAck对应
+        let mut hasher = Sha512::new();
+        hasher.update(&self.digest);
+        Digest(hasher.finalize().as_slice()[..32].try_into().unwrap())
+    }
+}
+
+impl fmt::Debug for Certificate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "Certificate({}, {})", self.digest, self.votes.len())
+    }
+}
+
+pub type Round = u64;
+
+// DAG vertex proposal: the payloads sealed by our workers since our last
+// header, linked to 2f+1 certified vertices of the previous round.
+#[derive(Clone, Deserialize, Serialize, Default)]
+pub struct Header {
+    pub author: PublicKey,
+    pub round: Round,
+    pub payloads: Vec<Digest>,
+    pub parents: Vec<Digest>,
+    pub signature: Signature,
+}
+
+impl Header {
+    pub async fn new(
+        author: PublicKey,
+        round: Round,
+        payloads: Vec<Digest>,
+        parents: Vec<Digest>,
+        mut signature_service: SignatureService,
+    ) -> Self {
+        let header = Self {
+            author,
+            round,
+            payloads,
+            parents,
+            signature: Signature::default(),
+        };
+        let signature = signature_service.request_signature(header.digest()).await;
+        Self {
+            signature,
+            ..header
+        }
+    }
+
+    pub fn verify(&self, committee: &Committee) -> MempoolResult<()> {
+        // Ensure the authority is in the committee.
+        ensure!(
+            committee.exists(&self.author),
+            MempoolError::UnknownAuthority(self.author)
+        );
+
+        // Ensure the header links to a quorum of the previous round.
+        let parents: HashSet<_> = self.parents.iter().collect();
+        ensure!(
+            self.round == 1 || parents.len() >= committee.quorum_threshold(),
+            MempoolError::HeaderRequiresQuorum(self.round)
+        );
+
+        // Check the signature.
+        self.signature.verify(&self.digest(), &self.author)?;
+        Ok(())
+    }
+}
+
+impl Hash for Header {
+    fn digest(&self) -> Digest {
+        let mut hasher = Sha512::new();
+        hasher.update(self.author.0);
+        hasher.update(self.round.to_le_bytes());
+        // The lengths tell where the payloads end and the parents start.
+        hasher.update((self.payloads.len() as u64).to_le_bytes());
+        for x in &self.payloads {
+            hasher.update(x);
+        }
+        hasher.update((self.parents.len() as u64).to_le_bytes());
+        for x in &self.parents {
+            hasher.update(x);
+        }
+        Digest(hasher.finalize().as_slice()[..32].try_into().unwrap())
+    }
+}
+
+impl fmt::Debug for Header {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(
+            f,
+            "Header({}, author {}, round {}, payloads {}, parents {})",
+            self.digest(),
+            self.author,
+            self.round,
+            self.payloads.len(),
+            self.parents.len()
+        )
+    }
+}
+
+// Certified DAG vertex; consensus orders the digests of these vertices.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Vertex {
+    pub header: Header,
+    pub certificate: Certificate,
+}
+
+impl Vertex {
+    pub fn verify(&self, committee: &Committee) -> MempoolResult<()> {
+        ensure!(
+            self.certificate.digest == self.header.digest(),
+            MempoolError::MalformedVertex(self.certificate.digest.clone())
+        );
+        self.header.verify(committee)?;
+        self.certificate.verify(committee)
+    }
+}
+
+impl Hash for Vertex {
+    fn digest(&self) -> Digest {
+        self.header.digest()
+    }
+}
+
+impl fmt::Debug for Vertex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "Vertex({:?})", self.header)
+    }
+}
@@ -1,48 +1,113 @@
-use crate::core::MempoolMessage;
 use crate::messages::{Payload, Transaction};
-use crypto::{PublicKey, SignatureService};
+use crate::replay::ReplayFilter;
+use crypto::{Digest, Hash as _, PublicKey, SignatureService};
+use futures::future::join_all;
+use log::{debug, warn};
+use std::collections::HashMap;
 use tokio::sync::mpsc::{channel, Receiver, Sender};
 use tokio::sync::oneshot;
-use tokio::time::{sleep, Duration};
+use tokio::time::{sleep, Duration, Instant};
+
+#[cfg(test)]
+#[path = "tests/payload_tests.rs"]
+pub mod payload_tests;
+
+// Number of sealed payloads whose commit we wait for to measure the latency.
+const MAX_SEALED: usize = 1_000;
+
+// Adapts the size of our payloads to the time they take to commit: smaller
+// payloads when they commit later than the target, larger ones otherwise.
+struct BatchSize {
+    current: usize,
+    min: usize,
+    max: usize,
+    target: Option<Duration>,  // fixed size if not set
+    latency: Option<Duration>, // moving average of the commit latency
+}
+
+impl BatchSize {
+    fn new(max: usize, target: u64) -> Self {
+        Self {
+            current: max,
+            min: (max / 16).max(1),
+            max,
+            target: (target > 0).then(|| Duration::from_millis(target)),
+            latency: None,
+        }
+    }
+
+    fn observe(&mut self, latency: Duration) {
+        let target = match self.target {
+            Some(target) => target,
+            None => return,
+        };
+        let average = match self.latency {
+            Some(average) => (average * 7 + latency) / 8,
+            None => latency,
+        };
+        self.latency = Some(average);
+
+        // Back off quickly, grow slowly.
+        self.current = match average > target {
+            true => (self.current * 3 / 4).max(self.min),
+            false => (self.current + self.min).min(self.max),
+        };
+        debug!(
+            "Commit latency {} ms, payload size set to {} B",
+            average.as_millis(),
+            self.current
+        );
+    }
+}
 
 struct Runner {
     transactions: Vec<Transaction>,
     size: usize,
-    max_size: usize,
-    min_block_delay: u64,
+    batch_size: BatchSize,
+    max_delay: Option<Duration>, // seal on size only if not set
+    sealed: HashMap<Digest, Instant>,
+    replay_filter: ReplayFilter,
     name: PublicKey,
     signature_service: SignatureService,
     client_channel: Receiver<Transaction>,
-    core_channel: Sender<MempoolMessage>,
+    core_channel: Sender<Payload>,
     request_channel: Receiver<oneshot::Sender<Payload>>,
+    commit_channel: Receiver<Vec<Digest>>,
 }
 
 impl Runner {
+    #[allow(clippy::too_many_arguments)]
     fn new(
         name: PublicKey,
         signature_service: SignatureService,
         max_size: usize,
-        min_block_delay: u64,
+        max_delay: u64,
+        target_latency: u64,
+        replay_filter: ReplayFilter,
         client_channel: Receiver<Transaction>,
-        core_channel: Sender<MempoolMessage>,
+        core_channel: Sender<Payload>,
         request_channel: Receiver<oneshot::Sender<Payload>>,
+        commit_channel: Receiver<Vec<Digest>>,
     ) -> Self {
         Self {
-            transactions: Vec::with_capacity(max_size),
+            transactions: Vec::new(),
             size: 0,
-            max_size,
-            min_block_delay,
+            batch_size: BatchSize::new(max_size, target_latency),
+            max_delay: (max_delay > 0).then(|| Duration::from_millis(max_delay)),
+            sealed: HashMap::new(),
+            replay_filter,
             name,
             signature_service,
             client_channel,
             core_channel,
             request_channel,
+            commit_channel,
         }
     }
 
     async fn add(&mut self, tx: Transaction) -> Option<Payload> {
         let length = tx.len();
-        let ret = match self.size + length > self.max_size {
+        let ret = match self.size > 0 && self.size + length > self.batch_size.current {
             //如果Vec满了就生成一个payload
             true => Some(self.make().await),
             false => None,
@@ -54,81 +119,166 @@ impl Runner {
     }
 
     async fn make(&mut self) -> Payload {
-        let transactions = self.transactions.drain(..).collect();
+        // Transactions committed since they reached us are not sealed again.
+        let replay_filter = &self.replay_filter;
+        let transactions = self
+            .transactions
+            .drain(..)
+            .filter(|x| !replay_filter.is_committed(x))
+            .collect();
 
         // Cleanup state.
         self.size = 0;
 
-        // Make a payload.
-        Payload::new(transactions, self.name, self.signature_service.clone()).await
+        // Make a payload, and remember when to measure its commit latency.
+        let payload = Payload::new(transactions, self.name, self.signature_service.clone()).await;
+        if payload.size() > 0 && self.batch_size.target.is_some() {
+            if self.sealed.len() >= MAX_SEALED {
+                // Payloads that never commit are forgotten, oldest first.
+                if let Some(oldest) = self
+                    .sealed
+                    .iter()
+                    .min_by_key(|(_, x)| **x)
+                    .map(|(x, _)| x.clone())
+                {
+                    self.sealed.remove(&oldest);
+                }
+            }
+            self.sealed.insert(payload.digest(), Instant::now());
+        }
+        payload
+    }
+
+    fn committed(&mut self, digests: Vec<Digest>) {
+        for digest in digests {
+            if let Some(sealed) = self.sealed.remove(&digest) {
+                self.batch_size.observe(sealed.elapsed());
+            }
+        }
     }
 
     async fn run(&mut self) {
+        // Seals the pending transactions once the oldest one waited `max_delay`.
+        let timer = sleep(Duration::ZERO);
+        tokio::pin!(timer);
+
         loop {
-            tokio::select! {
+            let payload = tokio::select! {
                 Some(transaction) = self.client_channel.recv() => {
-                    if let Some(payload) = self.add(transaction).await {
-                        let message = MempoolMessage::OwnPayload(payload);
-                        if let Err(e) = self.core_channel.send(message).await {
-                            panic!("Failed to send payload to the core: {}", e);
+                    let payload = self.add(transaction).await;
+                    if self.transactions.len() == 1 {
+                        if let Some(delay) = self.max_delay {
+                            timer.as_mut().reset(Instant::now() + delay);
                         }
-
-                        // Wait for the minimum block delay.
-                        sleep(Duration::from_millis(self.min_block_delay)).await;
                     }
+                    payload
+                },
+                () = &mut timer, if self.max_delay.is_some() && self.size > 0 => {
+                    Some(self.make().await)
                 },
                 Some(sender) = self.request_channel.recv() => {
                     let _ = sender.send(self.make().await);
+                    None
+                },
+                Some(digests) = self.commit_channel.recv() => {
+                    self.committed(digests);
+                    None
                 },
                 else => break,
+            };
+
+            if let Some(payload) = payload {
+                // Waiting for the core slows down the clients.
+                if self.core_channel.send(payload).await.is_err() {
+                    warn!("Mempool core is gone, stopping the payload maker");
+                    break;
+                }
             }
         }
     }
 }
 
-pub struct PayloadMaker {
+// The channels to one of the runners.
+struct Worker {
     request_channel: Sender<oneshot::Sender<Payload>>,
+    commit_channel: Sender<Vec<Digest>>,
+}
+
+// Builds and signs our payloads on one runner per client channel, in parallel:
+// each runner signs on its own fork of the signature service.
+pub struct PayloadMaker {
+    workers: Vec<Worker>,
 }
 
 impl PayloadMaker {
+    // Payloads are sealed once they reach the batch size (at most `max_size`),
+    // once their oldest transaction waited `max_delay` ms (unless 0), or when the
+    // consensus asks for one. With a `target_latency` (ms, unless 0) the batch
+    // size follows the commit latency of our payloads.
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         name: PublicKey,
         signature_service: SignatureService,
         max_size: usize,
-        min_block_delay: u64,
-        client_channel: Receiver<Transaction>,
-        core_channel: Sender<MempoolMessage>,
+        max_delay: u64,
+        target_latency: u64,
+        replay_filter: ReplayFilter,
+        client_channels: Vec<Receiver<Transaction>>,
+        core_channel: Sender<Payload>,
     ) -> Self {
-        let (tx_request, rx_request) = channel(10000);
-        tokio::spawn(async move {
-            Runner::new(
-                name,
-                signature_service,
-                max_size,
-                min_block_delay,
-                client_channel,
-                core_channel,
-                rx_request,
-            )
-            .run()
-            .await;
-        });
-        Self {
-            request_channel: tx_request,
-        }
+        let workers = client_channels
+            .into_iter()
+            .map(|client_channel| {
+                let (tx_request, rx_request) = channel(10000);
+                let (tx_commit, rx_commit) = channel(10000);
+                let mut runner = Runner::new(
+                    name,
+                    signature_service.fork(),
+                    max_size,
+                    max_delay,
+                    target_latency,
+                    replay_filter.clone(),
+                    client_channel,
+                    core_channel.clone(),
+                    rx_request,
+                    rx_commit,
+                );
+                tokio::spawn(async move {
+                    runner.run().await;
+                });
+                Worker {
+                    request_channel: tx_request,
+                    commit_channel: tx_commit,
+                }
+            })
+            .collect();
+        Self { workers }
     }
 
-    pub async fn make(&mut self) -> Option<Payload> {
-        let (sender, receiver) = oneshot::channel();
-        if let Err(e) = self.request_channel.send(sender).await {
-            panic!("Failed to request payload from the inner runner: {}", e);
-        }
-        let payload = receiver
+    // Seal the pending transactions of every runner.
+    pub async fn make(&mut self) -> Vec<Payload> {
+        let requests = self.workers.iter().map(|worker| async move {
+            let (sender, receiver) = oneshot::channel();
+            if worker.request_channel.send(sender).await.is_err() {
+                warn!("Payload maker is gone");
+                return None;
+            }
+            receiver.await.ok()
+        });
+        join_all(requests)
             .await
-            .expect("Failed to receive payload from the inner runner");
-        match payload.size() {
-            0 => None,
-            _ => Some(payload),
+            .into_iter()
+            .flatten()
+            .filter(|x| x.size() > 0)
+            .collect()
+    }
+
+    // Report the committed payloads, ours among them.
+    pub async fn committed(&mut self, digests: Vec<Digest>) {
+        for worker in &self.workers {
+            if worker.commit_channel.send(digests.clone()).await.is_err() {
+                warn!("Payload maker is gone");
+            }
         }
     }
 }
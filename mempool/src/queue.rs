@@ -0,0 +1,133 @@
+use crate::config::QueuePolicy;
+use crypto::{Digest, PublicKey};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+#[cfg(test)]
+#[path = "tests/queue_tests.rs"]
+pub mod queue_tests;
+
+// A bucket of the queue: the payload author under `Fair`, the declared priority
+// under `Priority`, and a single lane under `Fifo`.
+type Lane = (u8, PublicKey);
+
+// Ordered queue of payload digests waiting to be proposed. Digests are served in
+// arrival order per lane; lanes are served round robin (`Fifo`, `Fair`) or by
+// decreasing priority (`Priority`), so an old payload cannot starve behind a
+// busy author. Removal is amortized O(1): removed digests are dropped from
+// `members` and their stale entries are compacted once they outnumber the live
+// ones. A removed digest inserted again loses its stale entry first, so that a
+// digest has at most one entry.
+pub struct PayloadQueue {
+    policy: QueuePolicy,
+    buckets: HashMap<Lane, VecDeque<Digest>>,
+    lanes: VecDeque<Lane>, // round robin order of the non-empty buckets
+    members: HashSet<Digest>,
+    stale: HashSet<Digest>, // removed digests still held by the buckets
+}
+
+impl PayloadQueue {
+    pub fn new(policy: QueuePolicy, capacity: usize) -> Self {
+        Self {
+            policy,
+            buckets: HashMap::new(),
+            lanes: VecDeque::new(),
+            members: HashSet::with_capacity(capacity),
+            stale: HashSet::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    pub fn insert(&mut self, digest: Digest, author: PublicKey, priority: u8) -> bool {
+        if !self.members.insert(digest.clone()) {
+            return false;
+        }
+        if self.stale.remove(&digest) {
+            self.purge(&digest);
+        }
+        let lane = match self.policy {
+            QueuePolicy::Fifo => (0, PublicKey::default()),
+            QueuePolicy::Fair => (0, author),
+            QueuePolicy::Priority => (priority, PublicKey::default()),
+        };
+        let bucket = self.buckets.entry(lane).or_default();
+        if bucket.is_empty() {
+            self.lanes.push_back(lane);
+        }
+        bucket.push_back(digest);
+        true
+    }
+
+    pub fn remove(&mut self, digest: &Digest) -> bool {
+        if !self.members.remove(digest) {
+            return false;
+        }
+        self.stale.insert(digest.clone());
+        if self.stale.len() > self.members.len() {
+            self.compact();
+        }
+        true
+    }
+
+    // Drop the stale entries of the buckets, and the buckets left empty.
+    fn compact(&mut self) {
+        let members = &self.members;
+        self.buckets.retain(|_, bucket| {
+            bucket.retain(|x| members.contains(x));
+            !bucket.is_empty()
+        });
+        let buckets = &self.buckets;
+        self.lanes.retain(|x| buckets.contains_key(x));
+        self.stale.clear();
+    }
+
+    // Drop the stale entry of a digest.
+    fn purge(&mut self, digest: &Digest) {
+        self.buckets.retain(|_, bucket| {
+            bucket.retain(|x| x != digest);
+            !bucket.is_empty()
+        });
+        let buckets = &self.buckets;
+        self.lanes.retain(|x| buckets.contains_key(x));
+    }
+
+    // Take up to `max` digests according to the queue policy.
+    pub fn pop(&mut self, max: usize) -> Vec<Digest> {
+        let mut digests = Vec::new();
+        while digests.len() < max {
+            let next = match self.policy {
+                QueuePolicy::Priority => self
+                    .lanes
+                    .iter()
+                    .enumerate()
+                    .max_by_key(|(_, (priority, _))| *priority)
+                    .map(|(i, _)| i),
+                _ => (!self.lanes.is_empty()).then_some(0),
+            };
+            let lane = match next.and_then(|i| self.lanes.remove(i)) {
+                Some(lane) => lane,
+                None => break,
+            };
+            let bucket = self.buckets.get_mut(&lane).unwrap();
+            while let Some(digest) = bucket.pop_front() {
+                if self.members.remove(&digest) {
+                    digests.push(digest);
+                    break;
+                }
+                self.stale.remove(&digest);
+            }
+            if bucket.is_empty() {
+                self.buckets.remove(&lane);
+            } else {
+                self.lanes.push_back(lane);
+            }
+        }
+        digests
+    }
+}
@@ -0,0 +1,191 @@
+use crate::error::{MempoolError, MempoolResult};
+use consensus::SeqNumber;
+use crypto::{Digest, PublicKey};
+use log::info;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use tokio::time::{Duration, Instant};
+
+#[cfg(test)]
+#[path = "tests/quota_tests.rs"]
+pub mod quota_tests;
+
+// Limits how many payloads each authority can make us store: a rate limit per
+// second, a storage quota of uncommitted payloads, and garbage collection of the
+// payloads that are still not committed `gc_depth` rounds after we stored them.
+// Blocks may reference certified payloads, so those stay in the store for another
+// `gc_depth` rounds after they leave the quota of their author.
+pub struct Quotas {
+    max_payloads: usize,
+    max_rate: u64,
+    gc_depth: SeqNumber,
+    round: SeqNumber,
+    authors: HashMap<PublicKey, Usage>,
+    payloads: HashMap<Digest, Stored>,
+    rounds: BTreeMap<SeqNumber, Vec<Digest>>, // payloads by round they were stored at
+    rejections: HashMap<PublicKey, u64>,
+    updated: HashSet<PublicKey>, // authors with rejections since the last report
+}
+
+struct Stored {
+    author: PublicKey,
+    round: SeqNumber, // the round we stored (or stopped counting) the payload at
+    certified: bool,
+    counted: bool, // whether it counts against the quota of its author
+}
+
+// A payload that was never committed and no longer counts against the quota of
+// its author. Certified payloads are only deleted on their second eviction.
+pub struct Evicted {
+    pub digest: Digest,
+    pub delete: bool,
+}
+
+struct Usage {
+    stored: usize,
+    window: Instant,
+    received: u64,
+}
+
+impl Usage {
+    fn new() -> Self {
+        Self {
+            stored: 0,
+            window: Instant::now(),
+            received: 0,
+        }
+    }
+}
+
+impl Quotas {
+    pub fn new(max_payloads: usize, max_rate: u64, gc_depth: SeqNumber) -> Self {
+        Self {
+            max_payloads,
+            max_rate,
+            gc_depth,
+            round: 0,
+            authors: HashMap::new(),
+            payloads: HashMap::new(),
+            rounds: BTreeMap::new(),
+            rejections: HashMap::new(),
+            updated: HashSet::new(),
+        }
+    }
+
+    // Ensure the author may make us store one more payload.
+    pub fn check(&mut self, author: &PublicKey) -> MempoolResult<()> {
+        let usage = self.authors.entry(*author).or_insert_with(Usage::new);
+        if usage.window.elapsed() >= Duration::from_secs(1) {
+            usage.window = Instant::now();
+            usage.received = 0;
+        }
+        usage.received += 1;
+
+        let result = if self.max_rate != 0 && usage.received > self.max_rate {
+            Err(MempoolError::AuthorRateLimited(*author))
+        } else if self.max_payloads != 0 && usage.stored >= self.max_payloads {
+            Err(MempoolError::AuthorQuotaExceeded(*author))
+        } else {
+            Ok(())
+        };
+        if result.is_err() {
+            *self.rejections.entry(*author).or_insert(0) += 1;
+            self.updated.insert(*author);
+        }
+        result
+    }
+
+    pub fn add(&mut self, digest: Digest, author: PublicKey) {
+        if self.payloads.contains_key(&digest) {
+            return;
+        }
+        // Payloads stored without a check (e.g. referenced by a block) count too.
+        self.authors.entry(author).or_insert_with(Usage::new).stored += 1;
+        self.rounds
+            .entry(self.round)
+            .or_default()
+            .push(digest.clone());
+        let stored = Stored {
+            author,
+            round: self.round,
+            certified: false,
+            counted: true,
+        };
+        self.payloads.insert(digest, stored);
+    }
+
+    pub fn certify(&mut self, digest: &Digest) {
+        if let Some(stored) = self.payloads.get_mut(digest) {
+            stored.certified = true;
+        }
+    }
+
+    // Committed payloads do not count against the quota of their author anymore.
+    pub fn commit(&mut self, digest: &Digest) {
+        if let Some(stored) = self.payloads.remove(digest) {
+            self.release(&stored);
+            if let Some(digests) = self.rounds.get_mut(&stored.round) {
+                digests.retain(|x| x != digest);
+            }
+        }
+    }
+
+    // Returns the payloads that were never committed and should be evicted.
+    pub fn cleanup(&mut self, round: SeqNumber) -> Vec<Evicted> {
+        self.round = self.round.max(round);
+        if self.gc_depth == 0 {
+            return Vec::new();
+        }
+        let mut expired = Vec::new();
+        while let Some((&r, _)) = self.rounds.iter().next() {
+            if r + self.gc_depth >= self.round {
+                break;
+            }
+            let digests = self.rounds.remove(&r).unwrap();
+            for digest in digests {
+                if let Some(stored) = self.payloads.remove(&digest) {
+                    self.release(&stored);
+                    let delete = !(stored.certified && stored.counted);
+                    if !delete {
+                        // Keep it until its second eviction.
+                        self.rounds
+                            .entry(self.round)
+                            .or_default()
+                            .push(digest.clone());
+                        let stored = Stored {
+                            round: self.round,
+                            counted: false,
+                            ..stored
+                        };
+                        self.payloads.insert(digest.clone(), stored);
+                    }
+                    expired.push(Evicted { digest, delete });
+                }
+            }
+        }
+        expired
+    }
+
+    fn release(&mut self, stored: &Stored) {
+        if !stored.counted {
+            return;
+        }
+        if let Some(usage) = self.authors.get_mut(&stored.author) {
+            usage.stored = usage.stored.saturating_sub(1);
+        }
+    }
+
+    pub fn rejections(&self, author: &PublicKey) -> u64 {
+        *self.rejections.get(author).unwrap_or(&0)
+    }
+
+    pub fn report(&mut self) {
+        let authors: Vec<_> = self.updated.drain().collect();
+        for author in authors {
+            info!(
+                "Rejected {} payloads from {} so far",
+                self.rejections(&author),
+                author
+            );
+        }
+    }
+}
@@ -0,0 +1,73 @@
+use crate::messages::Transaction;
+use crypto::Digest;
+use ed25519_dalek::Digest as _;
+use ed25519_dalek::Sha512;
+use std::collections::{HashSet, VecDeque};
+use std::convert::TryInto;
+use std::sync::{Arc, Mutex};
+
+#[cfg(test)]
+#[path = "tests/replay_tests.rs"]
+pub mod replay_tests;
+
+pub fn transaction_digest(tx: &Transaction) -> Digest {
+    Digest(Sha512::digest(tx).as_slice()[..32].try_into().unwrap())
+}
+
+// Bounded index of the most recently committed transactions, shared by the
+// front (client ingestion), the payload maker (sealing) and the core (commits).
+// Payloads of other nodes are never refused for it: blocks may reference them.
+#[derive(Clone)]
+pub struct ReplayFilter {
+    inner: Arc<Mutex<Index>>,
+}
+
+struct Index {
+    retention: usize,
+    committed: HashSet<Digest>,
+    order: VecDeque<Digest>, // eviction order, oldest first
+}
+
+impl ReplayFilter {
+    pub fn new(retention: usize) -> Self {
+        let index = Index {
+            retention,
+            committed: HashSet::with_capacity(retention),
+            order: VecDeque::with_capacity(retention),
+        };
+        Self {
+            inner: Arc::new(Mutex::new(index)),
+        }
+    }
+
+    pub fn is_committed(&self, tx: &Transaction) -> bool {
+        let index = self.inner.lock().unwrap();
+        index.committed.contains(&transaction_digest(tx))
+    }
+
+    // Record committed transactions and return those committed for the first
+    // time; replays (already in the index) are to be skipped at execution.
+    pub fn commit<'a, I>(&self, transactions: I) -> Vec<&'a Transaction>
+    where
+        I: IntoIterator<Item = &'a Transaction>,
+    {
+        let mut index = self.inner.lock().unwrap();
+        if index.retention == 0 {
+            return transactions.into_iter().collect();
+        }
+        let mut fresh = Vec::new();
+        for tx in transactions {
+            let digest = transaction_digest(tx);
+            if !index.committed.insert(digest.clone()) {
+                continue;
+            }
+            fresh.push(tx);
+            index.order.push_back(digest);
+            if index.order.len() > index.retention {
+                let evicted = index.order.pop_front().unwrap();
+                index.committed.remove(&evicted);
+            }
+        }
+        fresh
+    }
+}
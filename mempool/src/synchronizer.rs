@@ -9,8 +9,8 @@ use crypto::{Digest, PublicKey};
 use futures::future::try_join_all;
 use futures::stream::futures_unordered::FuturesUnordered;
 use futures::stream::StreamExt as _;
-use log::{debug, error};
-use network::NetMessage;
+use log::{debug, error, warn};
+use network::{push, DropCounter, NetMessage};
 use std::collections::{HashMap, HashSet};
 use std::time::{SystemTime, UNIX_EPOCH};
 use store::Store;
@@ -20,6 +20,10 @@ use tokio::time::{sleep, Duration, Instant};
 #[path = "tests/synchronizer_tests.rs"]
 pub mod synchronizer_tests;
 
+// Sync requests are best effort: the synchronizer asks again for the payloads it
+// still misses. The other messages wait for room, see `MempoolMessage::drop_policy`.
+static NETWORK_DROPS: DropCounter = DropCounter::new("mempool messages");
+
 enum SynchronizerMessage {
     Sync(HashSet<Digest>, Block, u8),
     Clean(SeqNumber),
@@ -28,6 +32,7 @@ enum SynchronizerMessage {
 pub struct Synchronizer {
     inner_channel: Sender<SynchronizerMessage>,
     store: Store,
+    referenced: HashMap<Digest, SeqNumber>, // payloads of the blocks we verified, by block height
 }
 
 impl Synchronizer {
@@ -52,8 +57,10 @@ impl Synchronizer {
             tokio::pin!(timer);
             loop {
                 tokio::select! {
-                    Some(message) = rx_inner.recv() => match message {
-                        SynchronizerMessage::Sync(mut missing, block,tag) => {//等待缺失的payload
+                    // Stop along with the core.
+                    message = rx_inner.recv() => match message {
+                        None => break,
+                        Some(SynchronizerMessage::Sync(mut missing, block,tag)) => {//等待缺失的payload
                             // TODO [issue #7]: A bad node may make us run out of memory by sending many blocks
                             // with different round numbers or different payloads.
 
@@ -92,10 +99,10 @@ impl Synchronizer {
                                     &network_channel
                                 )
                                 .await
-                                .expect("Failed to send payload sync request");
+                                .unwrap_or_else(|e| warn!("{}", e));
                             }
                         },
-                        SynchronizerMessage::Clean(mut round) => {//将小于等于 round 轮的请求都清除
+                        Some(SynchronizerMessage::Clean(mut round)) => {//将小于等于 round 轮的请求都清除
                             for (r, handler) in pending.values() {
                                 if r <= &round {
                                     let _ = handler.send(()).await;
@@ -113,21 +120,19 @@ impl Synchronizer {
                                 for x in &block.payload {//将已经收到的payload去除
                                     let _ = requests.remove(x);
                                 }
-                                if tag == OPT{
+                                let result = if tag == OPT{
                                     let message = ConsensusMessage::HsLoopBack(block);
-                                    if let Err(e) = consensus_channel.send(message).await {
-                                        panic!("Failed to send message to consensus: {}", e);
-                                    }
+                                    consensus_channel.send(message).await
                                 }else if tag == PES{
                                     let message = ConsensusMessage::ParLoopBack(block);
-                                    if let Err(e) = consensus_channel_smvba.send(message).await {
-                                        panic!("Failed to send message to consensus: {}", e);
-                                    }
+                                    consensus_channel_smvba.send(message).await
                                 }else{
                                     let message = ConsensusMessage::FBLoopBack(block);
-                                    if let Err(e) = consensus_channel_smvba.send(message).await {
-                                        panic!("Failed to send message to consensus: {}", e);
-                                    }
+                                    consensus_channel_smvba.send(message).await
+                                };
+                                if result.is_err() {
+                                    warn!("Consensus is gone, stopping the synchronizer");
+                                    break;
                                 }
 
                             },
@@ -156,7 +161,7 @@ impl Synchronizer {
                                 &network_channel
                             )
                             .await
-                            .expect("Failed to send payload sync request");
+                            .unwrap_or_else(|e| warn!("{}", e));
                         }
                         timer.as_mut().reset(Instant::now() + Duration::from_millis(5000));
                     },
@@ -167,6 +172,7 @@ impl Synchronizer {
         Self {
             inner_channel: tx_inner,
             store,
+            referenced: HashMap::new(),
         }
     }
 
@@ -199,22 +205,31 @@ impl Synchronizer {
         let addresses = if let Some(to) = to {
             //如果没有指定发送地址，则广播给出自己以外的所有人
             debug!("Sending {:?} to {}", message, to);
-            vec![committee.mempool_address(to)?]
+            vec![committee.address(to)?]
         } else {
             debug!("Broadcasting {:?}", message);
             committee.broadcast_addresses(&from)
         };
         let bytes = bincode::serialize(message).expect("Failed to serialize core message");
-        let message = NetMessage(Bytes::from(bytes), addresses);
-        if let Err(e) = network_channel.send(message).await {
-            panic!("Failed to send block through network channel: {}", e);
-        }
-        Ok(())
+        let net_message = NetMessage(Bytes::from(bytes), addresses);
+        push(
+            network_channel,
+            net_message,
+            message.drop_policy(),
+            &NETWORK_DROPS,
+        )
+        .await
+        .map_err(|_| MempoolError::ChannelClosed("network"))
     }
 
     pub async fn verify_payload(&mut self, block: Block, tag: u8) -> MempoolResult<bool> {
         let mut missing = HashSet::new();
         for digest in &block.payload {
+            let height = self
+                .referenced
+                .entry(digest.clone())
+                .or_insert(block.height);
+            *height = (*height).max(block.height);
             if self.store.read(digest.to_vec()).await?.is_none() {
                 debug!("Requesting sync for payload {}", digest);
                 missing.insert(digest.clone());
@@ -226,17 +241,25 @@ impl Synchronizer {
             return Ok(true);
         }
         let message = SynchronizerMessage::Sync(missing, block, tag);
-        if let Err(e) = self.inner_channel.send(message).await {
-            panic!("Failed to send message to synchronizer core: {}", e);
-        }
+        self.inner_channel
+            .send(message)
+            .await
+            .map_err(|_| MempoolError::ChannelClosed("synchronizer"))?;
         Ok(false)
     }
 
+    // Whether a block we did not commit or clean up yet references the payload,
+    // including the payloads we requested: they must be stored.
+    pub fn is_referenced(&self, digest: &Digest) -> bool {
+        self.referenced.contains_key(digest)
+    }
+
     pub async fn cleanup(&mut self, round: SeqNumber) {
+        self.referenced.retain(|_, height| *height > round);
         let message = SynchronizerMessage::Clean(round);
         debug!("cleanup round {}", round);
-        if let Err(e) = self.inner_channel.send(message).await {
-            panic!("Failed to send message to synchronizer core: {}", e);
+        if self.inner_channel.send(message).await.is_err() {
+            warn!("Synchronizer is gone, skipping cleanup");
         }
     }
 }
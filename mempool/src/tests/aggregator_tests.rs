@@ -0,0 +1,48 @@
+use super::*;
+use crate::common::{ack, committee, keys, payload};
+use crypto::Hash as _;
+
+#[test]
+fn ignore_unknown_payload() {
+    let mut aggregator = Aggregator::new(committee());
+    let (name, secret) = keys().pop().unwrap();
+    let result = aggregator.add_ack(ack(name, &secret));
+    assert!(result.is_ok());
+    assert!(result.unwrap().is_none());
+}
+
+#[test]
+fn make_certificate() {
+    let mut aggregator = Aggregator::new(committee());
+    aggregator.add_payload(payload().digest());
+    let mut keys = keys();
+
+    // Add 2f+1 acks to the aggregator and ensure it returns a valid certificate.
+    let (name, secret) = keys.pop().unwrap();
+    let result = aggregator.add_ack(ack(name, &secret));
+    assert!(result.is_ok());
+    assert!(result.unwrap().is_none());
+
+    let (name, secret) = keys.pop().unwrap();
+    let result = aggregator.add_ack(ack(name, &secret));
+    assert!(result.is_ok());
+    assert!(result.unwrap().is_none());
+
+    let (name, secret) = keys.pop().unwrap();
+    match aggregator.add_ack(ack(name, &secret)) {
+        Ok(Some(certificate)) => assert!(certificate.verify(&committee()).is_ok()),
+        _ => assert!(false),
+    }
+}
+
+#[test]
+fn ack_authority_reuse() {
+    let mut aggregator = Aggregator::new(committee());
+    aggregator.add_payload(payload().digest());
+    let (name, secret) = keys().pop().unwrap();
+    assert!(aggregator.add_ack(ack(name, &secret)).is_ok());
+    match aggregator.add_ack(ack(name, &secret)) {
+        Err(MempoolError::AuthorityReuseinCertificate(author)) => assert_eq!(author, name),
+        _ => assert!(false),
+    }
+}
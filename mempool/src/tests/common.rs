@@ -1,8 +1,8 @@
 use crate::config::Committee;
-use crate::messages::Payload;
+use crate::messages::{Header, Payload, PayloadAck};
 use consensus::{Block, QC};
 use crypto::Hash as _;
-use crypto::{generate_keypair, PublicKey, SecretKey, Signature};
+use crypto::{generate_keypair, Domain, PublicKey, SecretKey, Signature};
 use rand::rngs::StdRng;
 use rand::SeedableRng as _;
 
@@ -35,8 +35,8 @@ impl Committee {
             authority.front_address.set_port(base_port + port);
         }
         for authority in self.authorities.values_mut() {
-            let port = authority.mempool_address.port();
-            authority.mempool_address.set_port(base_port + port);
+            let port = authority.address.port();
+            authority.address.set_port(base_port + port);
         }
     }
 }
@@ -56,6 +56,33 @@ pub fn payload() -> Payload {
     }
 }
 
+// Fixture.
+pub fn ack(author: PublicKey, secret: &SecretKey) -> PayloadAck {
+    let ack = PayloadAck {
+        digest: payload().digest(),
+        author,
+        signature: Signature::default(),
+    };
+    let signature = Signature::new(&ack.digest(), secret);
+    PayloadAck { signature, ..ack }
+}
+
+// Fixture.
+pub fn header() -> Header {
+    let (author, secret) = keys().remove(0);
+    let header = Header {
+        author,
+        round: 1,
+        payloads: vec![payload().digest()],
+        ..Header::default()
+    };
+    let signature = Signature::new(&header.digest(), &secret);
+    Header {
+        signature,
+        ..header
+    }
+}
+
 // Fixture.
 pub fn block() -> Block {
     let (author, secret) = keys().pop().unwrap();
@@ -69,6 +96,6 @@ pub fn block() -> Block {
         signature: Signature::default(),
         tag: 0,
     };
-    let signature = Signature::new(&block.digest(), &secret);
+    let signature = Signature::new_in(Domain::Block, &block.digest(), &secret);
     Block { signature, ..block }
 }
@@ -1,7 +1,8 @@
 use super::*;
-use crate::common::{committee, keys, payload};
+use crate::common::{ack, committee, keys, payload};
 use crate::messages::Transaction;
-use crypto::SignatureService;
+use crate::replay::ReplayFilter;
+use crypto::{Signature, SignatureService};
 use std::fs;
 use std::time::Duration;
 use tokio::sync::mpsc::channel;
@@ -15,20 +16,24 @@ async fn core(
     Sender<MempoolMessage>,
     Sender<ConsensusMempoolMessage>,
     Sender<Transaction>,
+    Receiver<Vec<Transaction>>,
 ) {
     let (tx_network, rx_network) = channel(1);
     let (tx_consensus, _rx_consensus) = channel(1);
     let (tx_consensus_smvba, _rx_consensus) = channel(1);
     let (tx_core, rx_core) = channel(1);
+    let (tx_payload, rx_payload) = channel(1);
     let (tx_consensus_mempool, rx_consensus_mempool) = channel(1);
     let (tx_client, rx_client) = channel(1);
+    let (tx_commit, rx_commit) = channel(10);
 
     let (name, secret) = keys().pop().unwrap();
     let parameters = Parameters {
         queue_capacity: 1,
         sync_retry_delay: 10_000,
         max_payload_size: 1,
-        min_block_delay: 0,
+        max_payload_delay: 0,
+        ..Parameters::default()
     };
     let signature_service = SignatureService::new(secret, None);
     let _ = fs::remove_dir_all(store_path);
@@ -42,37 +47,50 @@ async fn core(
         tx_network.clone(),
         parameters.sync_retry_delay,
     );
+    let replay_filter = ReplayFilter::new(parameters.replay_retention);
     let payload_maker = PayloadMaker::new(
         name,
-        signature_service,
+        signature_service.clone(),
         parameters.max_payload_size,
-        parameters.min_block_delay,
-        rx_client,
-        tx_core.clone(),
+        parameters.max_payload_delay,
+        parameters.target_commit_latency,
+        replay_filter.clone(),
+        vec![rx_client],
+        tx_payload,
     );
     let mut core = Core::new(
         name,
         committee(),
         parameters,
         store,
+        signature_service,
         synchronizer,
         payload_maker,
+        replay_filter,
         /* core_channel */ rx_core,
+        /* payload_channel */ rx_payload,
         /* consensus_channel */ rx_consensus_mempool,
         /* network_channel */ tx_network,
+        /* commit_channel */ tx_commit,
     );
     tokio::spawn(async move {
         core.run().await;
     });
 
-    (rx_network, tx_core, tx_consensus_mempool, tx_client)
+    (
+        rx_network,
+        tx_core,
+        tx_consensus_mempool,
+        tx_client,
+        rx_commit,
+    )
 }
 
 #[tokio::test]
 async fn handle_transaction() {
     // Run the core.
     let path = ".db_test_handle_transaction";
-    let (mut rx_network, _tx_core, _tx_consensus, tx_client) = core(path).await;
+    let (mut rx_network, _tx_core, _tx_consensus, tx_client, _rx_commit) = core(path).await;
 
     // Ensure the core transmits the payload to the network.
     tx_client.send(vec![1u8]).await.unwrap();
@@ -84,7 +102,7 @@ async fn handle_transaction() {
 async fn handle_request() {
     // Run the core.
     let path = ".db_test_handle_request";
-    let (mut rx_network, tx_core, _tx_consensus, _tx_client) = core(path).await;
+    let (mut rx_network, tx_core, _tx_consensus, _tx_client, _rx_commit) = core(path).await;
 
     // Send a payload to the core.
     let message = MempoolMessage::Payload(payload());
@@ -105,16 +123,76 @@ async fn handle_request() {
 async fn get_payload() {
     // Run the core.
     let path = ".db_test_get_payload";
-    let (_rx_network, _tx_core, tx_consensus, tx_client) = core(path).await;
+    let (mut rx_network, tx_core, tx_consensus, tx_client, _rx_commit) = core(path).await;
+    tokio::spawn(async move { while rx_network.recv().await.is_some() {} });
 
     // Send enough transactions to generate a payload.
     tx_client.send(vec![1u8]).await.unwrap();
     tx_client.send(vec![1u8]).await.unwrap();
+    sleep(Duration::from_millis(50)).await;
+
+    // Ack the payload from 2 other nodes to certify it.
+    for (name, secret) in keys().iter().take(2) {
+        let message = MempoolMessage::PayloadAck(ack(*name, secret));
+        tx_core.send(message).await.unwrap();
+    }
+    sleep(Duration::from_millis(50)).await;
 
     // Get the next payload.
     let (sender, receiver) = oneshot::channel();
-    let message = ConsensusMempoolMessage::Get(64, sender, OPT );
+    let message = ConsensusMempoolMessage::Get(64, sender, OPT);
     tx_consensus.send(message).await.unwrap();
     let result = receiver.await.unwrap();
     assert_eq!(result, vec![payload().digest()]);
 }
+
+#[tokio::test]
+async fn get_uncertified_payload() {
+    // Run the core.
+    let path = ".db_test_get_uncertified_payload";
+    let (mut rx_network, _tx_core, tx_consensus, tx_client, _rx_commit) = core(path).await;
+    tokio::spawn(async move { while rx_network.recv().await.is_some() {} });
+
+    // Send enough transactions to generate a payload.
+    tx_client.send(vec![1u8]).await.unwrap();
+    tx_client.send(vec![1u8]).await.unwrap();
+    sleep(Duration::from_millis(50)).await;
+
+    // Ensure the payload is not proposed before it is certified.
+    let (sender, receiver) = oneshot::channel();
+    let message = ConsensusMempoolMessage::Get(64, sender, OPT);
+    tx_consensus.send(message).await.unwrap();
+    assert!(receiver.await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn commit_transaction_once() {
+    // Run the core.
+    let path = ".db_test_commit_transaction_once";
+    let (mut rx_network, tx_core, tx_consensus, _tx_client, mut rx_commit) = core(path).await;
+    tokio::spawn(async move { while rx_network.recv().await.is_some() {} });
+
+    // Two authors send payloads with the same transaction.
+    let mut digests = Vec::new();
+    for (author, secret) in keys().iter().take(2) {
+        let payload = Payload {
+            author: *author,
+            ..payload()
+        };
+        let signature = Signature::new(&payload.digest(), secret);
+        digests.push(payload.digest());
+        let message = MempoolMessage::Payload(Payload {
+            signature,
+            ..payload
+        });
+        tx_core.send(message).await.unwrap();
+    }
+    sleep(Duration::from_millis(50)).await;
+
+    // Commit both payloads: the transaction comes out once.
+    let message = ConsensusMempoolMessage::Cleanup(digests, 1);
+    tx_consensus.send(message).await.unwrap();
+    assert_eq!(rx_commit.recv().await.unwrap(), vec![vec![1u8]]);
+    sleep(Duration::from_millis(50)).await;
+    assert!(rx_commit.try_recv().is_err());
+}
@@ -0,0 +1,253 @@
+use super::*;
+use crate::common::{committee, header, keys, payload};
+use crate::messages::Certificate;
+use crate::messages::Transaction;
+use crate::replay::ReplayFilter;
+use crypto::{SecretKey, Signature};
+use std::fs;
+use std::time::Duration;
+use tokio::sync::mpsc::channel;
+use tokio::time::{sleep, timeout};
+
+async fn primary(
+    store_path: &str,
+) -> (
+    Receiver<NetMessage>,
+    Sender<MempoolMessage>,
+    Sender<ConsensusMempoolMessage>,
+    Sender<Transaction>,
+    Receiver<Vec<Transaction>>,
+) {
+    let (tx_network, rx_network) = channel(10);
+    let (tx_consensus, _rx_consensus) = channel(1);
+    let (tx_consensus_smvba, _rx_consensus) = channel(1);
+    let (tx_core, rx_core) = channel(1);
+    let (tx_payload, rx_payload) = channel(1);
+    let (tx_consensus_mempool, rx_consensus_mempool) = channel(1);
+    let (tx_client, rx_client) = channel(1);
+    let (tx_commit, rx_commit) = channel(10);
+
+    let (name, secret) = keys().pop().unwrap();
+    let parameters = Parameters {
+        queue_capacity: 1,
+        max_payload_size: 1,
+        max_payload_delay: 0,
+        dag: true,
+        max_header_delay: 50,
+        ..Parameters::default()
+    };
+    let signature_service = SignatureService::new(secret, None);
+    let _ = fs::remove_dir_all(store_path);
+    let store = Store::new(store_path).unwrap();
+    let synchronizer = Synchronizer::new(
+        tx_consensus,
+        tx_consensus_smvba,
+        store.clone(),
+        name,
+        committee(),
+        tx_network.clone(),
+        parameters.sync_retry_delay,
+    );
+    let replay_filter = ReplayFilter::new(parameters.replay_retention);
+    let payload_maker = PayloadMaker::new(
+        name,
+        signature_service.clone(),
+        parameters.max_payload_size,
+        parameters.max_payload_delay,
+        parameters.target_commit_latency,
+        replay_filter.clone(),
+        vec![rx_client],
+        tx_payload,
+    );
+    let mut primary = Primary::new(
+        name,
+        committee(),
+        parameters,
+        store,
+        signature_service,
+        synchronizer,
+        payload_maker,
+        replay_filter,
+        /* core_channel */ rx_core,
+        /* payload_channel */ rx_payload,
+        /* consensus_channel */ rx_consensus_mempool,
+        /* network_channel */ tx_network,
+        /* commit_channel */ tx_commit,
+    );
+    tokio::spawn(async move {
+        primary.run().await;
+    });
+
+    (
+        rx_network,
+        tx_core,
+        tx_consensus_mempool,
+        tx_client,
+        rx_commit,
+    )
+}
+
+// A vertex certified by the whole committee.
+fn vertex(
+    (author, secret): &(PublicKey, SecretKey),
+    round: Round,
+    payloads: Vec<Digest>,
+    parents: Vec<Digest>,
+) -> Vertex {
+    let header = Header {
+        author: *author,
+        round,
+        payloads,
+        parents,
+        signature: Signature::default(),
+    };
+    let signature = Signature::new(&header.digest(), secret);
+    let header = Header {
+        signature,
+        ..header
+    };
+    let votes = keys()
+        .iter()
+        .map(|(name, secret)| {
+            let ack = PayloadAck {
+                digest: header.digest(),
+                author: *name,
+                signature: Signature::default(),
+            };
+            (*name, Signature::new(&ack.digest(), secret))
+        })
+        .collect();
+    let certificate = Certificate {
+        digest: header.digest(),
+        votes,
+    };
+    Vertex {
+        header,
+        certificate,
+    }
+}
+
+#[tokio::test]
+async fn broadcast_header() {
+    // Run the primary.
+    let path = ".db_test_broadcast_header";
+    let (mut rx_network, _tx_core, _tx_consensus, _tx_client, _rx_commit) = primary(path).await;
+
+    // Ensure the primary broadcasts its first header after the header delay.
+    let NetMessage(bytes, addresses) = rx_network.recv().await.unwrap();
+    match bincode::deserialize(&bytes) {
+        Ok(MempoolMessage::Header(header)) => {
+            assert_eq!(header.round, 1);
+            assert_eq!(addresses.len(), committee().size() - 1);
+        }
+        _ => assert!(false),
+    }
+}
+
+#[tokio::test]
+async fn vote_header() {
+    // Run the primary.
+    let path = ".db_test_vote_header";
+    let (mut rx_network, tx_core, _tx_consensus, _tx_client, _rx_commit) = primary(path).await;
+
+    // Send a header without its payload: the primary should not vote yet.
+    let message = MempoolMessage::Header(header());
+    tx_core.send(message).await.unwrap();
+    sleep(Duration::from_millis(20)).await;
+
+    // Send the missing payload.
+    let message = MempoolMessage::Payload(payload());
+    tx_core.send(message).await.unwrap();
+
+    // Ensure the primary eventually votes for the header.
+    let vote = timeout(Duration::from_millis(500), async {
+        while let Some(NetMessage(bytes, _)) = rx_network.recv().await {
+            if let Ok(MempoolMessage::PayloadAck(ack)) = bincode::deserialize(&bytes) {
+                return Some(ack);
+            }
+        }
+        None
+    })
+    .await;
+    match vote {
+        Ok(Some(ack)) => assert_eq!(ack.digest, header().digest()),
+        _ => assert!(false),
+    }
+}
+
+#[test]
+fn header_digest_separates_payloads_and_parents() {
+    let (a, b) = (payload().digest(), header().digest());
+    let split = |payloads: Vec<Digest>, parents: Vec<Digest>| Header {
+        payloads,
+        parents,
+        ..header()
+    };
+    assert_ne!(
+        split(vec![a.clone()], vec![b.clone()]).digest(),
+        split(vec![a, b], vec![]).digest()
+    );
+}
+
+#[tokio::test]
+async fn commit_causal_history() {
+    // Run the primary.
+    let path = ".db_test_commit_causal_history";
+    let (mut rx_network, tx_core, tx_consensus, _tx_client, mut rx_commit) = primary(path).await;
+
+    // Three other nodes certify a payload each at round 1, and link to them at
+    // round 2.
+    let authors: Vec<_> = keys().into_iter().take(3).collect();
+    let mut first = Vec::new();
+    for (i, (author, secret)) in authors.iter().enumerate() {
+        let payload = Payload {
+            transactions: vec![vec![i as u8]],
+            author: *author,
+            signature: Signature::default(),
+        };
+        let signature = Signature::new(&payload.digest(), secret);
+        let payload = Payload {
+            signature,
+            ..payload
+        };
+        first.push(vertex(&authors[i], 1, vec![payload.digest()], Vec::new()));
+        let message = MempoolMessage::Payload(payload);
+        tx_core.send(message).await.unwrap();
+    }
+    let parents: Vec<_> = first.iter().map(|x| x.digest()).collect();
+    let second: Vec<_> = authors
+        .iter()
+        .map(|x| vertex(x, 2, Vec::new(), parents.clone()))
+        .collect();
+
+    // Deliver round 2 first: the last vertex of round 1 completes both rounds.
+    for x in second.iter().chain(first.iter()) {
+        let message = MempoolMessage::Vertex(x.clone());
+        tx_core.send(message).await.unwrap();
+    }
+    let header = timeout(Duration::from_millis(500), async {
+        while let Some(NetMessage(bytes, _)) = rx_network.recv().await {
+            match bincode::deserialize(&bytes) {
+                Ok(MempoolMessage::Header(header)) if header.round > 1 => return Some(header),
+                _ => continue,
+            }
+        }
+        None
+    })
+    .await;
+    match header {
+        Ok(Some(header)) => assert_eq!(header.round, 3),
+        _ => assert!(false),
+    }
+    tokio::spawn(async move { while rx_network.recv().await.is_some() {} });
+
+    // Commit a vertex of round 2: the round 1 vertices come out with it, in the
+    // order of their authors.
+    let message = ConsensusMempoolMessage::Cleanup(vec![second[0].digest()], 1);
+    tx_consensus.send(message).await.unwrap();
+    let mut expected: Vec<_> = authors.iter().enumerate().collect();
+    expected.sort_by_key(|(_, (author, _))| *author);
+    for (i, _) in expected {
+        assert_eq!(rx_commit.recv().await.unwrap(), vec![vec![i as u8]]);
+    }
+}
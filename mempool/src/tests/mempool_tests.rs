@@ -6,6 +6,7 @@ use consensus::{Block, PayloadStatus, OPT};
 use crypto::Hash as _;
 use futures::future::try_join_all;
 use futures::sink::SinkExt as _;
+use network::{Authenticator, Mux, ShutdownHandle};
 use std::fs;
 use std::time::Duration;
 use tokio::net::TcpStream;
@@ -28,7 +29,8 @@ async fn end_to_end() {
                 queue_capacity: 1,
                 sync_retry_delay: 10_000,
                 max_payload_size: 1,
-                min_block_delay: 0,
+                max_payload_delay: 0,
+                ..Parameters::default()
             };
             let signature_service = SignatureService::new(secret, None);
             let store_path = format!(".db_test_end_to_end_{}", i);
@@ -37,8 +39,15 @@ async fn end_to_end() {
             let (tx_consensus, _rx_consensus) = channel(1);
             let (tx_consensus_smvba, _rx_consensus) = channel(1);
             let (tx_consensus_mempool, rx_consensus_mempool) = channel(1);
+            let authenticator = Authenticator::new(
+                name,
+                signature_service.clone(),
+                committee.authorities.values().map(|x| (x.name, x.address)),
+            );
+            let mut mux = Mux::new(committee.address(&name).unwrap(), authenticator);
 
             tokio::spawn(async move {
+                let shutdown = ShutdownHandle::new().subscribe();
                 Mempool::run(
                     name,
                     committee,
@@ -48,9 +57,14 @@ async fn end_to_end() {
                     tx_consensus,
                     tx_consensus_smvba,
                     rx_consensus_mempool,
+                    /* commit_channel */ channel(1).0,
+                    &mut mux,
+                    shutdown.clone(),
                 )
                 .unwrap();
-                sleep(Duration::from_millis(100)).await;
+                shutdown.spawn(mux.run());
+                // Leave time for the peers to authenticate each other.
+                sleep(Duration::from_millis(500)).await;
 
                 let payload = vec![payload().digest()];
                 let block = Block { payload, ..block() };
@@ -0,0 +1,191 @@
+use super::*;
+use crate::common::{committee, keys};
+use crypto::{Identity, SecretKey, SignRequest, SignResponse, Signer, SignerResult};
+use tokio::time::timeout;
+
+fn payload_maker(
+    max_size: usize,
+    max_delay: u64,
+) -> (PayloadMaker, Sender<Transaction>, Receiver<Payload>) {
+    let (name, secret) = keys().pop().unwrap();
+    let (tx_client, rx_client) = channel(10);
+    let (tx_core, rx_core) = channel(10);
+    let payload_maker = PayloadMaker::new(
+        name,
+        SignatureService::new(secret, None),
+        max_size,
+        max_delay,
+        /* target_latency */ 0,
+        ReplayFilter::new(10),
+        vec![rx_client],
+        tx_core,
+    );
+    (payload_maker, tx_client, rx_core)
+}
+
+#[tokio::test]
+async fn seal_on_size() {
+    let (_payload_maker, tx_client, mut rx_core) = payload_maker(2, 0);
+
+    // Payloads are sealed back to back, without waiting.
+    for _ in 0..5 {
+        tx_client.send(vec![1u8, 2u8]).await.unwrap();
+    }
+    for _ in 0..4 {
+        match timeout(Duration::from_millis(100), rx_core.recv()).await {
+            Ok(Some(payload)) => assert_eq!(payload.size(), 2),
+            _ => assert!(false),
+        }
+    }
+}
+
+#[tokio::test]
+async fn seal_on_max_delay() {
+    let (_payload_maker, tx_client, mut rx_core) = payload_maker(1_000, 50);
+    tx_client.send(vec![1u8]).await.unwrap();
+    match timeout(Duration::from_millis(500), rx_core.recv()).await {
+        Ok(Some(payload)) => {
+            assert_eq!(payload.transactions, vec![vec![1u8]])
+        }
+        _ => assert!(false),
+    }
+}
+
+#[test]
+fn adapt_to_commit_latency() {
+    let mut batch_size = BatchSize::new(1_600, 100);
+    assert_eq!(batch_size.current, 1_600);
+
+    // Late commits shrink the payloads, down to the minimum.
+    batch_size.observe(Duration::from_millis(400));
+    assert_eq!(batch_size.current, 1_200);
+    for _ in 0..50 {
+        batch_size.observe(Duration::from_millis(400));
+    }
+    assert_eq!(batch_size.current, 100);
+
+    // Timely commits grow them back, up to the maximum.
+    for _ in 0..100 {
+        batch_size.observe(Duration::from_millis(10));
+    }
+    assert_eq!(batch_size.current, 1_600);
+
+    // The size is fixed without a target latency.
+    let mut batch_size = BatchSize::new(1_600, 0);
+    batch_size.observe(Duration::from_millis(400));
+    assert_eq!(batch_size.current, 1_600);
+}
+
+#[tokio::test]
+async fn make_on_every_worker() {
+    let (name, secret) = keys().pop().unwrap();
+    let (tx_client_0, rx_client_0) = channel(10);
+    let (tx_client_1, rx_client_1) = channel(10);
+    let (_tx_client_2, rx_client_2) = channel(10);
+    let (tx_core, _rx_core) = channel(10);
+    let mut payload_maker = PayloadMaker::new(
+        name,
+        SignatureService::new(secret, None),
+        1_000,
+        0,
+        0,
+        ReplayFilter::new(10),
+        vec![rx_client_0, rx_client_1, rx_client_2],
+        tx_core,
+    );
+    tx_client_0.send(vec![0u8]).await.unwrap();
+    tx_client_1.send(vec![1u8]).await.unwrap();
+    sleep(Duration::from_millis(50)).await;
+
+    // Each worker seals its own payload; idle workers have nothing to seal.
+    let mut transactions: Vec<_> = payload_maker
+        .make()
+        .await
+        .into_iter()
+        .flat_map(|x| x.transactions)
+        .collect();
+    transactions.sort();
+    assert_eq!(transactions, vec![vec![0u8], vec![1u8]]);
+    assert!(payload_maker.make().await.is_empty());
+}
+
+#[tokio::test]
+async fn skip_committed_transactions() {
+    let (name, secret) = keys().pop().unwrap();
+    let (tx_client, rx_client) = channel(10);
+    let (tx_core, _rx_core) = channel(10);
+    let replay_filter = ReplayFilter::new(10);
+    let mut payload_maker = PayloadMaker::new(
+        name,
+        SignatureService::new(secret, None),
+        1_000,
+        0,
+        0,
+        replay_filter.clone(),
+        vec![rx_client],
+        tx_core,
+    );
+    tx_client.send(vec![1u8]).await.unwrap();
+    tx_client.send(vec![2u8]).await.unwrap();
+    sleep(Duration::from_millis(50)).await;
+
+    // Transactions committed while they waited are not sealed again.
+    replay_filter.commit(&[vec![1u8]]);
+    let payloads = payload_maker.make().await;
+    assert_eq!(payloads.len(), 1);
+    assert_eq!(payloads[0].transactions, vec![vec![2u8]]);
+}
+
+// Holds the key in memory, but never answers.
+struct StuckSigner(SecretKey);
+
+impl Signer for StuckSigner {
+    fn sign(&mut self, _request: SignRequest) -> SignerResult<SignResponse> {
+        loop {
+            std::thread::park();
+        }
+    }
+
+    fn message_key(&self) -> Option<SecretKey> {
+        Some(self.0.clone())
+    }
+}
+
+#[tokio::test]
+async fn sign_on_each_worker() {
+    let (name, secret) = keys().pop().unwrap();
+    let identity = Identity {
+        name,
+        pkset: None,
+        quorum_pkset: None,
+        share: None,
+    };
+    let signature_service = SignatureService::new_with_signer(StuckSigner(secret), &identity);
+
+    // The shared signer is busy, the runners sign on their own.
+    let mut busy = signature_service.clone();
+    tokio::spawn(async move { busy.request_signature(Digest::default()).await });
+    let (tx_clients, rx_clients): (Vec<_>, Vec<_>) = (0..4).map(|_| channel(10)).unzip();
+    let (tx_core, mut rx_core) = channel(10);
+    let _payload_maker = PayloadMaker::new(
+        name,
+        signature_service,
+        1_000,
+        10,
+        0,
+        ReplayFilter::new(10),
+        rx_clients,
+        tx_core,
+    );
+    for (i, tx_client) in tx_clients.iter().enumerate() {
+        tx_client.send(vec![i as u8]).await.unwrap();
+    }
+    for _ in 0..4 {
+        match timeout(Duration::from_millis(500), rx_core.recv()).await {
+            Ok(Some(payload)) => {
+                assert!(payload.verify(&committee()).is_ok())
+            }
+            _ => assert!(false),
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use super::*;
+use crate::common::keys;
+use crypto::Digest;
+
+fn digest(i: u8) -> Digest {
+    Digest([i; 32])
+}
+
+#[test]
+fn fifo_order() {
+    let mut queue = PayloadQueue::new(QueuePolicy::Fifo, 10);
+    let (author, _) = keys().pop().unwrap();
+    for i in 0..5 {
+        assert!(queue.insert(digest(i), author, 0));
+    }
+    assert!(!queue.insert(digest(0), author, 0));
+
+    // Removed digests are skipped and the others come out in arrival order.
+    assert!(queue.remove(&digest(1)));
+    assert_eq!(queue.len(), 4);
+    assert_eq!(queue.pop(2), vec![digest(0), digest(2)]);
+    assert_eq!(queue.pop(10), vec![digest(3), digest(4)]);
+    assert!(queue.is_empty());
+}
+
+#[test]
+fn fair_order() {
+    let mut queue = PayloadQueue::new(QueuePolicy::Fair, 10);
+    let mut keys = keys();
+    let (a, _) = keys.pop().unwrap();
+    let (b, _) = keys.pop().unwrap();
+    queue.insert(digest(0), a, 0);
+    queue.insert(digest(1), a, 0);
+    queue.insert(digest(2), a, 0);
+    queue.insert(digest(3), b, 0);
+
+    // A busy author cannot starve the others.
+    assert_eq!(queue.pop(2), vec![digest(0), digest(3)]);
+    assert_eq!(queue.pop(10), vec![digest(1), digest(2)]);
+}
+
+#[test]
+fn priority_order() {
+    let mut queue = PayloadQueue::new(QueuePolicy::Priority, 10);
+    let (author, _) = keys().pop().unwrap();
+    queue.insert(digest(0), author, 0);
+    queue.insert(digest(1), author, 2);
+    queue.insert(digest(2), author, 1);
+    queue.insert(digest(3), author, 2);
+
+    // Higher priorities go first, in arrival order within a priority.
+    assert_eq!(queue.pop(3), vec![digest(1), digest(3), digest(2)]);
+    assert_eq!(queue.pop(10), vec![digest(0)]);
+}
+
+#[test]
+fn compact_on_remove() {
+    let mut queue = PayloadQueue::new(QueuePolicy::Fair, 10);
+    let (author, _) = keys().pop().unwrap();
+    for i in 0..100 {
+        queue.insert(digest(i), author, 0);
+        queue.remove(&digest(i));
+    }
+
+    // Removed digests do not pile up in the buckets.
+    assert!(queue.is_empty());
+    assert!(queue.buckets.values().map(|x| x.len()).sum::<usize>() <= 1);
+    assert!(queue.lanes.len() <= 1);
+}
+
+#[test]
+fn reinsert_removed_digest() {
+    let mut queue = PayloadQueue::new(QueuePolicy::Fifo, 10);
+    let (author, _) = keys().pop().unwrap();
+    queue.insert(digest(0), author, 0);
+    queue.insert(digest(1), author, 0);
+    queue.insert(digest(2), author, 0);
+    queue.remove(&digest(0));
+    queue.insert(digest(0), author, 0);
+    queue.remove(&digest(1));
+    queue.remove(&digest(2));
+
+    // The digest inserted again comes out once, at its new place.
+    assert_eq!(queue.pop(10), vec![digest(0)]);
+    assert!(queue.is_empty());
+}
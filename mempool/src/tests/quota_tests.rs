@@ -0,0 +1,99 @@
+use super::*;
+use crate::common::keys;
+
+fn digest(i: u8) -> Digest {
+    Digest([i; 32])
+}
+
+#[test]
+fn storage_quota() {
+    let mut quotas = Quotas::new(2, 0, 0);
+    let (author, _) = keys().pop().unwrap();
+    for i in 0..2 {
+        assert!(quotas.check(&author).is_ok());
+        quotas.add(digest(i), author);
+    }
+
+    // The quota is full until one of the payloads is committed.
+    match quotas.check(&author) {
+        Err(MempoolError::AuthorQuotaExceeded(x)) => assert_eq!(x, author),
+        _ => assert!(false),
+    }
+    assert_eq!(quotas.rejections(&author), 1);
+    quotas.commit(&digest(0));
+    assert!(quotas.check(&author).is_ok());
+}
+
+#[test]
+fn rate_limit() {
+    let mut quotas = Quotas::new(0, 2, 0);
+    let (author, _) = keys().pop().unwrap();
+    assert!(quotas.check(&author).is_ok());
+    assert!(quotas.check(&author).is_ok());
+    match quotas.check(&author) {
+        Err(MempoolError::AuthorRateLimited(x)) => assert_eq!(x, author),
+        _ => assert!(false),
+    }
+
+    // Other authorities are not affected.
+    let (other, _) = keys().remove(0);
+    assert!(quotas.check(&other).is_ok());
+}
+
+#[test]
+fn evict_uncommitted_payloads() {
+    let mut quotas = Quotas::new(0, 0, 10);
+    let (author, _) = keys().pop().unwrap();
+    quotas.check(&author).unwrap();
+    quotas.add(digest(0), author);
+    quotas.check(&author).unwrap();
+    quotas.add(digest(1), author);
+    quotas.commit(&digest(1));
+
+    // Only the payload that was never committed is evicted, after the gc depth.
+    assert!(quotas.cleanup(10).is_empty());
+    let evicted = quotas.cleanup(11);
+    assert_eq!(evicted.len(), 1);
+    assert_eq!(evicted[0].digest, digest(0));
+    assert!(evicted[0].delete);
+    assert!(quotas.cleanup(12).is_empty());
+}
+
+#[test]
+fn evict_certified_payloads() {
+    let mut quotas = Quotas::new(1, 0, 10);
+    let (author, _) = keys().pop().unwrap();
+    quotas.check(&author).unwrap();
+    quotas.add(digest(0), author);
+    quotas.certify(&digest(0));
+
+    // Certified payloads leave the quota of their author, but are flagged so
+    // that we keep storing them.
+    let evicted = quotas.cleanup(11);
+    assert_eq!(evicted.len(), 1);
+    assert!(!evicted[0].delete);
+    assert!(quotas.check(&author).is_ok());
+
+    // Until they are still not committed another `gc_depth` rounds later.
+    assert!(quotas.cleanup(21).is_empty());
+    let evicted = quotas.cleanup(22);
+    assert_eq!(evicted.len(), 1);
+    assert!(evicted[0].delete);
+    assert!(quotas.cleanup(40).is_empty());
+}
+
+#[test]
+fn count_unchecked_payloads() {
+    let mut quotas = Quotas::new(1, 0, 10);
+    let (author, _) = keys().pop().unwrap();
+
+    // A payload stored without a check (e.g. referenced by a block) counts
+    // against the quota of its author all the same.
+    quotas.add(digest(0), author);
+    assert!(quotas.check(&author).is_err());
+    quotas.commit(&digest(0));
+    assert!(quotas.check(&author).is_ok());
+    quotas.add(digest(1), author);
+    assert_eq!(quotas.cleanup(11).len(), 1);
+    assert!(quotas.check(&author).is_ok());
+}
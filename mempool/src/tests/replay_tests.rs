@@ -0,0 +1,35 @@
+use super::*;
+
+#[test]
+fn drop_committed_transactions() {
+    let filter = ReplayFilter::new(10);
+    let tx = vec![1u8, 2, 3];
+    assert!(!filter.is_committed(&tx));
+
+    // Handles share the same index.
+    filter.clone().commit(&[tx.clone()]);
+    assert!(filter.is_committed(&tx));
+    assert!(!filter.is_committed(&vec![4u8]));
+}
+
+#[test]
+fn evict_oldest_transactions() {
+    let filter = ReplayFilter::new(2);
+    let transactions: Vec<_> = (0..3u8).map(|i| vec![i]).collect();
+    filter.commit(&transactions);
+
+    // Only the most recent transactions are retained.
+    assert!(!filter.is_committed(&transactions[0]));
+    assert!(filter.is_committed(&transactions[1]));
+    assert!(filter.is_committed(&transactions[2]));
+}
+
+#[test]
+fn skip_replays_on_commit() {
+    let filter = ReplayFilter::new(10);
+    let (a, b) = (vec![1u8], vec![2u8]);
+    assert_eq!(filter.commit(&[a.clone(), a.clone()]), vec![&a]);
+
+    // Only the transactions committed for the first time are returned.
+    assert_eq!(filter.commit(&[a.clone(), b.clone()]), vec![&b]);
+}
@@ -76,7 +76,7 @@ async fn verify_wait() {
                 }
                 _ => assert!(false),
             }
-            let address = committee().mempool_address(&author);
+            let address = committee().address(&author);
             assert_eq!(recipient, vec![address.unwrap()]);
         }
         _ => assert!(false),
@@ -89,4 +89,8 @@ async fn verify_wait() {
         Some(ConsensusMessage::HsLoopBack(b)) => assert_eq!(b.digest(), digest),
         _ => assert!(false),
     }
+    // The payloads must be stored until the block is cleaned up.
+    assert!(payload.iter().all(|x| synchronizer.is_referenced(x)));
+    synchronizer.cleanup(1).await;
+    assert!(!synchronizer.is_referenced(&payload[0]));
 }
@@ -0,0 +1,75 @@
+use crate::{NetworkError, NetworkResult};
+use log::warn;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::mpsc::Sender;
+
+#[cfg(test)]
+#[path = "tests/backpressure_tests.rs"]
+pub mod backpressure_tests;
+
+// Drops are logged once every this many messages.
+const LOG_INTERVAL: u64 = 1_000;
+
+// What happens to a message sent to a full queue.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DropPolicy {
+    // Wait for room: the producer slows down to the pace of the consumer. For
+    // the messages the protocol cannot lose.
+    Block,
+    // Drop the message: for the messages the protocol recovers from losing
+    // (through timeouts, retransmissions or sync requests).
+    DropNewest,
+}
+
+// Counts the messages of one class dropped under backpressure.
+pub struct DropCounter {
+    name: &'static str,
+    count: AtomicU64,
+}
+
+impl DropCounter {
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            count: AtomicU64::new(0),
+        }
+    }
+
+    pub fn record(&self) {
+        let count = self.count.fetch_add(1, Ordering::Relaxed) + 1;
+        if count == 1 || count.is_multiple_of(LOG_INTERVAL) {
+            warn!("Queue of {} is full: dropped {} messages", self.name, count);
+        }
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+// Send a message to a bounded queue according to the policy of its class. Fails
+// only if the consumer is gone.
+pub async fn push<T>(
+    queue: &Sender<T>,
+    message: T,
+    policy: DropPolicy,
+    drops: &DropCounter,
+) -> NetworkResult<()> {
+    let closed = || NetworkError::QueueClosed(drops.name());
+    match policy {
+        DropPolicy::Block => queue.send(message).await.map_err(|_| closed()),
+        DropPolicy::DropNewest => match queue.try_send(message) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => {
+                drops.record();
+                Ok(())
+            }
+            Err(TrySendError::Closed(_)) => Err(closed()),
+        },
+    }
+}
@@ -0,0 +1,58 @@
+use crate::{NetworkError, NetworkResult};
+use crypto::PublicKey;
+use log::warn;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// A peer sending this many malformed frames within the window gets banned.
+const MAX_MALFORMED_FRAMES: usize = 3;
+const OFFENCE_WINDOW: Duration = Duration::from_secs(60);
+const BAN_DURATION: Duration = Duration::from_secs(600);
+
+struct Offences {
+    count: usize,
+    since: Instant,
+    banned_until: Option<Instant>,
+}
+
+// The peers that sent malformed frames to a receiver. Banned peers may still
+// complete the handshake, but their connections are then dropped.
+#[derive(Clone, Default)]
+pub struct BanList {
+    peers: Arc<Mutex<HashMap<PublicKey, Offences>>>,
+}
+
+impl BanList {
+    pub fn check(&self, peer: &PublicKey) -> NetworkResult<()> {
+        let mut peers = self.peers.lock().unwrap();
+        match peers.get(peer).and_then(|x| x.banned_until) {
+            Some(until) if until > Instant::now() => Err(NetworkError::PeerBanned(*peer)),
+            Some(_) => {
+                // The ban expired, start over.
+                peers.remove(peer);
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    pub fn report(&self, peer: &PublicKey) {
+        let now = Instant::now();
+        let mut peers = self.peers.lock().unwrap();
+        let offences = peers.entry(*peer).or_insert(Offences {
+            count: 0,
+            since: now,
+            banned_until: None,
+        });
+        if now.duration_since(offences.since) > OFFENCE_WINDOW {
+            offences.count = 0;
+            offences.since = now;
+        }
+        offences.count += 1;
+        if offences.count >= MAX_MALFORMED_FRAMES && offences.banned_until.is_none() {
+            warn!("Banning {} for {}s", peer, BAN_DURATION.as_secs());
+            offences.banned_until = Some(now + BAN_DURATION);
+        }
+    }
+}
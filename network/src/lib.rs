@@ -1,15 +1,19 @@
+use bincode::Options as _;
 use bytes::Bytes;
-use futures::sink::SinkExt as _;
-use futures::stream::StreamExt as _;
-use log::{debug, info, warn};
-use serde::de::DeserializeOwned;
-use std::collections::HashMap;
-use std::fmt::Debug;
+use crypto::{CryptoError, PublicKey};
 use std::net::SocketAddr;
 use thiserror::Error;
-use tokio::net::{TcpListener, TcpStream};
-use tokio::sync::mpsc::{channel, Receiver, Sender};
-use tokio_util::codec::{Framed, LengthDelimitedCodec};
+
+pub use crate::backpressure::{push, DropCounter, DropPolicy};
+pub use crate::mux::{CancelHandler, ChannelId, Mux, MuxSender};
+pub use crate::noise::{Authenticator, SecureTransport, DEFAULT_MAX_FRAME_SIZE};
+pub use crate::shutdown::{Shutdown, ShutdownHandle};
+
+mod backpressure;
+mod bans;
+mod mux;
+mod noise;
+mod shutdown;
 
 #[cfg(test)]
 #[path = "tests/network_tests.rs"]
@@ -22,134 +26,75 @@ pub enum NetworkError {
 
     #[error("Serialization error: {0}")]
     SerializationError(#[from] Box<bincode::ErrorKind>),
-}
 
-// 消息数据 + addr(ip:port)
-pub struct NetMessage(pub Bytes, pub Vec<SocketAddr>);
+    #[error("Noise error: {0}")]
+    NoiseError(#[from] snow::Error),
 
-pub struct NetSender {
-    transmit: Receiver<NetMessage>, //先将要发送的消息缓存在channel中
-}
+    #[error("Invalid handshake signature: {0}")]
+    InvalidSignature(#[from] CryptoError),
 
-impl NetSender {
-    pub fn new(transmit: Receiver<NetMessage>) -> Self {
-        Self { transmit }
-    }
+    #[error("Malformed handshake")]
+    InvalidHandshake,
 
-    // We keep alive one TCP connection per peer, each of which is handled
-    // by a separate thread (called worker). We communicate with our workers
-    // with a dedicated channel kept by the HashMap called `senders`. If the
-    // a connection die, we make a new one.
-    pub async fn run(&mut self) {
-        let mut senders = HashMap::<_, Sender<_>>::new();
-        while let Some(NetMessage(bytes, addresses)) = self.transmit.recv().await {
-            for address in addresses {
-                let spawn = match senders.get(&address) {
-                    //如果connect存在 直接写入channel
-                    Some(tx) => tx.send(bytes.clone()).await.is_err(),
-                    None => true,
-                };
-                if spawn {
-                    //如果不存在则创建一个新的连接
-                    let tx = Self::spawn_worker(address).await;
-                    if let Ok(()) = tx.send(bytes.clone()).await {
-                        senders.insert(address, tx);
-                    }
-                }
-            }
-        }
-    }
+    #[error("Handshake timed out")]
+    HandshakeTimeout,
 
-    async fn spawn_worker(address: SocketAddr) -> Sender<Bytes> {
-        //新建立一个连接
-        // Each worker handle a TCP connection with on address.
-        let (tx, mut rx) = channel(10000);
-        tokio::spawn(async move {
-            let stream = match TcpStream::connect(address).await {
-                Ok(stream) => {
-                    info!("Outgoing connection established with {}", address);
-                    stream
-                }
-                Err(e) => {
-                    warn!("Failed to connect to {}: {}", address, e);
-                    return;
-                }
-            };
-            let mut transport = Framed::new(stream, LengthDelimitedCodec::new());
-            while let Some(message) = rx.recv().await {
-                //如果有消息就发送给对方
-                match transport.send(message).await {
-                    Ok(_) => debug!("Successfully sent message to {}", address),
-                    Err(e) => {
-                        warn!("Failed to send message to {}: {}", address, e);
-                        return;
-                    }
-                }
-            }
-        });
-        tx
-    }
-}
+    #[error("Peer {0} is not a member of the committee")]
+    UnknownPeer(PublicKey),
 
-/*
-1. 监听
-2. 将收到的消息存入sender channel
-3. 外部通过 receiver 接收消息
- */
-pub struct NetReceiver<Message> {
-    address: SocketAddr, //监听地址
-    deliver: Sender<Message>,
-}
+    #[error("Expected peer {0} but {1} answered")]
+    UnexpectedPeer(PublicKey, PublicKey),
 
-impl<Message: 'static + Send + DeserializeOwned + Debug> NetReceiver<Message> {
-    pub fn new(address: SocketAddr, deliver: Sender<Message>) -> Self {
-        Self { address, deliver }
-    }
+    #[error("No member of the committee listens on {0}")]
+    UnknownAddress(SocketAddr),
+
+    #[error("Received an empty frame")]
+    InvalidFrame,
 
-    // For each incoming request, we spawn a new worker responsible to receive
-    // messages and replay them through the provided deliver channel.
-    pub async fn run(&self) {
-        let listener = TcpListener::bind(&self.address)
-            .await
-            .expect("Failed to bind to TCP port");
-
-        info!("Listening on {}", self.address);
-        loop {
-            let (socket, peer) = match listener.accept().await {
-                //接收一个新建立的连接
-                Ok(value) => value,
-                Err(e) => {
-                    warn!("{}", NetworkError::from(e));
-                    continue;
-                }
-            };
-            info!("Incoming connection established with {}", peer);
-            Self::spawn_worker(socket, peer, self.deliver.clone()).await;
+    #[error("Received a message of {0} bytes, above the limit of {1} bytes")]
+    OversizedMessage(usize, usize),
+
+    #[error("Connection closed")]
+    ConnectionClosed,
+
+    #[error("Received an acknowledgement for no message")]
+    UnexpectedAck,
+
+    #[error("Queue of {0} is closed")]
+    QueueClosed(&'static str),
+
+    #[error("Malformed message from {0}: {1}")]
+    MalformedMessage(PublicKey, Box<bincode::ErrorKind>),
+
+    #[error("Peer {0} is banned for sending malformed frames")]
+    PeerBanned(PublicKey),
+}
+
+impl NetworkError {
+    // Whether the peer sent something it should not have, rather than the
+    // connection failing.
+    fn is_malformed(&self) -> bool {
+        match self {
+            NetworkError::MalformedMessage(..)
+            | NetworkError::NoiseError(_)
+            | NetworkError::InvalidFrame
+            | NetworkError::OversizedMessage(..) => true,
+            // Raised by the codec on frames above the limit.
+            NetworkError::NetworkError(e) => e.kind() == std::io::ErrorKind::InvalidData,
+            _ => false,
         }
     }
+}
 
-    async fn spawn_worker(socket: TcpStream, peer: SocketAddr, deliver: Sender<Message>) {
-        tokio::spawn(async move {
-            let mut transport = Framed::new(socket, LengthDelimitedCodec::new());
-            while let Some(frame) = transport.next().await {
-                match frame
-                    .map_err(NetworkError::from)
-                    .and_then(|x| bincode::deserialize(&x).map_err(NetworkError::from))
-                {
-                    Ok(message) => {
-                        debug!("Received {:?}", message);
-                        deliver
-                            .send(message)
-                            .await
-                            .expect("Failed to deliver message");
-                    }
-                    Err(e) => {
-                        warn!("{}", e);
-                        return;
-                    }
-                }
-            }
-            warn!("Connection closed by peer {}", peer);
-        });
-    }
+pub type NetworkResult<T> = Result<T, NetworkError>;
+
+// 限制反序列化的内存分配, 防止对方伪造超大的长度字段
+fn decoder(limit: usize) -> impl bincode::Options + Copy {
+    bincode::DefaultOptions::new()
+        .with_limit(limit as u64)
+        .with_fixint_encoding()
+        .allow_trailing_bytes()
 }
+
+// 消息数据 + addr(ip:port)
+pub struct NetMessage(pub Bytes, pub Vec<SocketAddr>);
@@ -0,0 +1,470 @@
+use crate::backpressure::{push, DropCounter, DropPolicy};
+use crate::bans::BanList;
+use crate::noise::{Authenticator, SecureTransport};
+use crate::{decoder, NetMessage, NetworkError, NetworkResult};
+use bincode::Options as _;
+use bytes::{BufMut as _, Bytes, BytesMut};
+use crypto::PublicKey;
+use futures::future::poll_fn;
+use log::{debug, info, warn};
+use serde::de::DeserializeOwned;
+use std::cmp::{max, min};
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Debug;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::task::Poll;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::{channel, Receiver, Sender};
+use tokio::sync::oneshot;
+use tokio::task::JoinSet;
+use tokio::time::{sleep, Duration};
+
+#[cfg(test)]
+#[path = "tests/mux_tests.rs"]
+pub mod mux_tests;
+
+// Logical channels sharing the connections between two nodes. When frames of
+// several channels are waiting, those of the lowest channel go first.
+pub type ChannelId = u8;
+pub const MAX_CHANNELS: usize = 4;
+
+// Frames waiting for a peer, per channel.
+const CHANNEL_QUEUE_CAPACITY: usize = 1_000;
+
+// Frames sent and not acknowledged yet, or waiting for a reconnection, per peer.
+const MAX_PENDING_FRAMES: usize = 10_000;
+
+// Received frames waiting to be decoded, per channel.
+const DELIVERY_QUEUE_CAPACITY: usize = 1_000;
+
+// Delays between two attempts to reach a peer, in ms.
+const MIN_RETRY_DELAY: u64 = 50;
+const MAX_RETRY_DELAY: u64 = 10_000;
+
+static CHANNEL_DROPS: DropCounter = DropCounter::new("multiplexed frames");
+
+// Resolves with the acknowledgement of the peer. Dropping it cancels the message:
+// it is not retransmitted anymore.
+pub type CancelHandler = oneshot::Receiver<Bytes>;
+
+// Where to deliver the frames of each channel before decoding, and the largest
+// message the channel takes.
+type Sinks = Vec<Option<(Sender<(PublicKey, Bytes)>, usize)>>;
+
+struct Outgoing {
+    data: Bytes,
+    // Only set for reliable frames, see `MuxSender::send_reliable`.
+    cancel_handler: Option<oneshot::Sender<Bytes>>,
+}
+
+// The channels of one node: a single listener for all the peers, and a single
+// outgoing connection to each of them.
+pub struct Mux {
+    address: SocketAddr,
+    authenticator: Authenticator,
+    max_frame_size: usize,
+    bans: BanList,
+    sinks: Sinks,
+    connections: Connections,
+}
+
+impl Mux {
+    pub fn new(address: SocketAddr, authenticator: Authenticator) -> Self {
+        Self {
+            address,
+            authenticator: authenticator.clone(),
+            max_frame_size: 0,
+            bans: BanList::default(),
+            sinks: vec![None; MAX_CHANNELS],
+            connections: Connections::new(authenticator),
+        }
+    }
+
+    // Deliver the messages received on a channel. Messages larger than
+    // `max_size` bytes are refused, and count against their sender.
+    pub fn receiver<M>(&mut self, channel: ChannelId, deliver: Sender<M>, max_size: usize)
+    where
+        M: 'static + Send + DeserializeOwned + Debug,
+    {
+        let (tx, mut rx) = tokio::sync::mpsc::channel(DELIVERY_QUEUE_CAPACITY);
+        self.sinks[channel as usize] = Some((tx, max_size));
+        self.max_frame_size = max(self.max_frame_size, max_size + 1);
+
+        let bans = self.bans.clone();
+        tokio::spawn(async move {
+            while let Some((peer, bytes)) = rx.recv().await {
+                match decoder(max_size).deserialize::<M>(&bytes) {
+                    Ok(message) => {
+                        debug!("Received {:?}", message);
+                        if deliver.send(message).await.is_err() {
+                            return;
+                        }
+                    }
+                    Err(e) => {
+                        bans.report(&peer);
+                        warn!("{}", NetworkError::MalformedMessage(peer, e));
+                    }
+                }
+            }
+        });
+    }
+
+    pub fn sender(&self, channel: ChannelId) -> MuxSender {
+        MuxSender {
+            channel,
+            connections: self.connections.clone(),
+        }
+    }
+
+    // Accept the connections of the peers. Dropping this future closes the
+    // listener and all incoming connections.
+    pub async fn run(self) {
+        let listener = TcpListener::bind(&self.address)
+            .await
+            .expect("Failed to bind to TCP port");
+
+        info!("Listening on {}", self.address);
+        let sinks = Arc::new(self.sinks);
+        let mut workers = JoinSet::new();
+        loop {
+            tokio::select! {
+                result = listener.accept() => {
+                    let (socket, peer) = match result {
+                        Ok(value) => value,
+                        Err(e) => {
+                            warn!("{}", NetworkError::from(e));
+                            continue;
+                        }
+                    };
+                    let worker = Self::receive(
+                        socket,
+                        peer,
+                        self.authenticator.clone(),
+                        sinks.clone(),
+                        self.max_frame_size,
+                        self.bans.clone(),
+                    );
+                    workers.spawn(worker);
+                },
+                Some(_) = workers.join_next() => (),
+            }
+        }
+    }
+
+    async fn receive(
+        socket: TcpStream,
+        peer: SocketAddr,
+        authenticator: Authenticator,
+        sinks: Arc<Sinks>,
+        max_frame_size: usize,
+        bans: BanList,
+    ) {
+        let (mut transport, name) = match authenticator.accept(socket).await {
+            Ok((transport, name)) => (transport, name),
+            Err(e) => {
+                warn!("Rejected connection from {}: {}", peer, e);
+                return;
+            }
+        };
+        info!("Incoming connection established with {} ({})", peer, name);
+        transport.set_max_frame_size(max_frame_size);
+
+        while let Some(frame) = transport.recv().await {
+            // Banned peers are cut off, even in the middle of a connection.
+            let result = bans.check(&name).and(frame).and_then(|frame| {
+                let (sink, max_size) = frame
+                    .first()
+                    .and_then(|channel| sinks.get(*channel as usize))
+                    .and_then(|x| x.as_ref())
+                    .ok_or(NetworkError::InvalidFrame)?;
+                // The connection takes the limit of the largest channel.
+                let data = frame.slice(1..);
+                if data.len() > *max_size {
+                    return Err(NetworkError::OversizedMessage(data.len(), *max_size));
+                }
+                Ok((sink, data))
+            });
+            match result {
+                Ok((sink, data)) => {
+                    // Waiting here pushes back on the peer through TCP.
+                    if sink.send((name, data)).await.is_err() {
+                        warn!("Stopped delivering messages from {}", peer);
+                        return;
+                    }
+                    if let Err(e) = transport.send(Bytes::from("Ack")).await {
+                        warn!("Failed to acknowledge message to {}: {}", peer, e);
+                        return;
+                    }
+                }
+                Err(e) => {
+                    if e.is_malformed() {
+                        bans.report(&name);
+                    }
+                    warn!("{}", e);
+                    return;
+                }
+            }
+        }
+        warn!("Connection closed by peer {}", peer);
+    }
+}
+
+// Sends the messages of one channel.
+#[derive(Clone)]
+pub struct MuxSender {
+    channel: ChannelId,
+    connections: Connections,
+}
+
+impl MuxSender {
+    // Best effort: the message is dropped if the peer lags or is unreachable.
+    pub async fn send(&self, address: SocketAddr, data: Bytes) {
+        let message = Outgoing {
+            data,
+            cancel_handler: None,
+        };
+        let queue = self.connections.queue(address, self.channel);
+        if let Err(e) = push(&queue, message, DropPolicy::DropNewest, &CHANNEL_DROPS).await {
+            warn!("{}", e);
+        }
+    }
+
+    pub async fn broadcast(&self, addresses: Vec<SocketAddr>, data: Bytes) {
+        for address in addresses {
+            self.send(address, data.clone()).await;
+        }
+    }
+
+    // The message is retransmitted until the peer acknowledges it, or until its
+    // handler is dropped.
+    pub async fn send_reliable(&self, address: SocketAddr, data: Bytes) -> CancelHandler {
+        let (sender, receiver) = oneshot::channel();
+        let message = Outgoing {
+            data,
+            cancel_handler: Some(sender),
+        };
+        let queue = self.connections.queue(address, self.channel);
+        if queue.send(message).await.is_err() {
+            warn!("Connection to {} is gone", address);
+        }
+        receiver
+    }
+
+    pub async fn broadcast_reliable(
+        &self,
+        addresses: Vec<SocketAddr>,
+        data: Bytes,
+    ) -> Vec<CancelHandler> {
+        let mut handlers = Vec::with_capacity(addresses.len());
+        for address in addresses {
+            handlers.push(self.send_reliable(address, data.clone()).await);
+        }
+        handlers
+    }
+
+    // Send the messages of a `NetMessage` channel, best effort.
+    pub async fn forward(self, mut transmit: Receiver<NetMessage>) {
+        while let Some(NetMessage(bytes, addresses)) = transmit.recv().await {
+            self.broadcast(addresses, bytes).await;
+        }
+    }
+}
+
+// The outgoing connections, shared by all channels.
+#[derive(Clone)]
+struct Connections {
+    authenticator: Authenticator,
+    peers: Arc<Mutex<HashMap<SocketAddr, Vec<Sender<Outgoing>>>>>,
+}
+
+impl Connections {
+    fn new(authenticator: Authenticator) -> Self {
+        Self {
+            authenticator,
+            peers: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    // The queue of a channel to a peer. Only the members of the committee get a
+    // connection: the queue of another address is closed from the start.
+    fn queue(&self, address: SocketAddr, channel: ChannelId) -> Sender<Outgoing> {
+        if !self.authenticator.knows(&address) {
+            warn!("{}", NetworkError::UnknownAddress(address));
+            return tokio::sync::mpsc::channel(1).0;
+        }
+        let mut peers = self.peers.lock().unwrap();
+        if peers.get(&address).is_none_or(|x| x[0].is_closed()) {
+            // Forget the connections that ended before making a new one.
+            peers.retain(|_, x| !x[0].is_closed());
+            let queues = Connection::spawn(address, self.authenticator.clone());
+            peers.insert(address, queues);
+        }
+        peers[&address][channel as usize].clone()
+    }
+}
+
+// A connection to a peer. It lives as long as the senders and reconnects with
+// backoff; reliable frames survive reconnections, the others do not.
+struct Connection {
+    address: SocketAddr,
+    authenticator: Authenticator,
+    queues: Vec<Receiver<Outgoing>>,
+    // The frames not acknowledged yet, in the order they were sent.
+    pending: VecDeque<(Bytes, Option<oneshot::Sender<Bytes>>)>,
+}
+
+impl Connection {
+    fn spawn(address: SocketAddr, authenticator: Authenticator) -> Vec<Sender<Outgoing>> {
+        let (senders, queues) = (0..MAX_CHANNELS)
+            .map(|_| channel(CHANNEL_QUEUE_CAPACITY))
+            .unzip();
+        tokio::spawn(async move {
+            Self {
+                address,
+                authenticator,
+                queues,
+                pending: VecDeque::new(),
+            }
+            .run()
+            .await;
+        });
+        senders
+    }
+
+    async fn run(&mut self) {
+        let mut delay = MIN_RETRY_DELAY;
+        loop {
+            match self.connect().await {
+                Ok(mut transport) => {
+                    delay = MIN_RETRY_DELAY;
+                    match self.keep_alive(&mut transport).await {
+                        NetworkError::QueueClosed(_) => return,
+                        e => warn!("Connection to {} failed: {}", self.address, e),
+                    }
+                }
+                Err(e) => warn!("Failed to connect to {}: {}", self.address, e),
+            }
+            // Keep the reliable frames while waiting to reconnect, and drop the others.
+            self.prune();
+            let timer = sleep(Duration::from_millis(delay));
+            tokio::pin!(timer);
+            loop {
+                tokio::select! {
+                    () = &mut timer => break,
+                    message = Self::next(&mut self.queues) => match message {
+                        Some((channel, Outgoing { data, cancel_handler: Some(handler) })) => {
+                            self.buffer(frame(channel, &data), handler);
+                        },
+                        Some(_) => CHANNEL_DROPS.record(),
+                        None => return,
+                    },
+                }
+            }
+            delay = min(2 * delay, MAX_RETRY_DELAY);
+        }
+    }
+
+    async fn connect(&self) -> NetworkResult<SecureTransport> {
+        let (transport, peer) = self.authenticator.connect(self.address).await?;
+        info!(
+            "Outgoing connection established with {} ({})",
+            self.address, peer
+        );
+        Ok(transport)
+    }
+
+    // Forget the frames whose handler was dropped, and the best effort ones.
+    fn prune(&mut self) {
+        self.pending
+            .retain(|(_, handler)| handler.as_ref().is_some_and(|x| !x.is_closed()));
+    }
+
+    // Keep a reliable frame until the next connection. Once the buffer is full
+    // the oldest frame gives way: dropping its handler tells the sender.
+    fn buffer(&mut self, frame: Bytes, handler: oneshot::Sender<Bytes>) {
+        if self.pending.len() >= MAX_PENDING_FRAMES {
+            self.prune();
+        }
+        if self.pending.len() >= MAX_PENDING_FRAMES {
+            self.pending.pop_front();
+            CHANNEL_DROPS.record();
+        }
+        self.pending.push_back((frame, Some(handler)));
+    }
+
+    async fn keep_alive(&mut self, transport: &mut SecureTransport) -> NetworkError {
+        // Retransmit the reliable frames the previous connection left unacknowledged,
+        // except those cancelled in the meantime.
+        self.prune();
+        for (frame, _) in &self.pending {
+            if let Err(e) = transport.send(frame.clone()).await {
+                return e;
+            }
+        }
+
+        loop {
+            tokio::select! {
+                // The peer acknowledges the frames in the order it receives them.
+                response = transport.recv() => match response {
+                    Some(Ok(ack)) => match self.pending.pop_front() {
+                        Some((_, handler)) => {
+                            if let Some(handler) = handler {
+                                let _ = handler.send(ack);
+                            }
+                        }
+                        None => return NetworkError::UnexpectedAck,
+                    },
+                    Some(Err(e)) => return e,
+                    None => return NetworkError::ConnectionClosed,
+                },
+                // The acknowledgements must catch up before more frames go out.
+                message = Self::next(&mut self.queues), if self.pending.len() < MAX_PENDING_FRAMES => {
+                    let (channel, message) = match message {
+                        Some(x) => x,
+                        None => return NetworkError::QueueClosed("multiplexed connection"),
+                    };
+                    if message.cancel_handler.as_ref().is_some_and(|x| x.is_closed()) {
+                        continue;
+                    }
+                    let frame = frame(channel, &message.data);
+                    self.pending.push_back((frame.clone(), message.cancel_handler));
+                    if let Err(e) = transport.send(frame).await {
+                        return e;
+                    }
+                    debug!("Sent frame on channel {} to {}", channel, self.address);
+                },
+            }
+        }
+    }
+
+    // The next frame to send, taken from the lowest channel that has one. None
+    // once the senders of all channels are gone.
+    async fn next(queues: &mut [Receiver<Outgoing>]) -> Option<(ChannelId, Outgoing)> {
+        poll_fn(|cx| {
+            let mut closed = 0;
+            for (channel, queue) in queues.iter_mut().enumerate() {
+                match queue.poll_recv(cx) {
+                    Poll::Ready(Some(message)) => {
+                        return Poll::Ready(Some((channel as ChannelId, message)))
+                    }
+                    Poll::Ready(None) => closed += 1,
+                    Poll::Pending => (),
+                }
+            }
+            match closed == queues.len() {
+                true => Poll::Ready(None),
+                false => Poll::Pending,
+            }
+        })
+        .await
+    }
+}
+
+// A frame starts with the channel of its message.
+fn frame(channel: ChannelId, data: &[u8]) -> Bytes {
+    let mut frame = BytesMut::with_capacity(data.len() + 1);
+    frame.put_u8(channel);
+    frame.extend_from_slice(data);
+    frame.freeze()
+}
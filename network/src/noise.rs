@@ -0,0 +1,235 @@
+use crate::{NetworkError, NetworkResult};
+use bytes::{Bytes, BytesMut};
+use crypto::{Digest, PublicKey, Signature, SignatureService};
+use ed25519_dalek::Digest as _;
+use ed25519_dalek::Sha512;
+use futures::sink::SinkExt as _;
+use futures::stream::StreamExt as _;
+use std::cmp::max;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryInto;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+use tokio_util::codec::{Framed, LengthDelimitedCodec};
+
+const NOISE_PARAMS: &str = "Noise_NN_25519_ChaChaPoly_BLAKE2s";
+const PROLOGUE: &[u8] = b"hotstuff transport v1";
+
+// Largest noise message, and the size of the tag it carries.
+const MAX_NOISE_MESSAGE: usize = 65535;
+const TAG_SIZE: usize = 16;
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Largest frame during the handshake, whose messages are all small.
+const MAX_HANDSHAKE_FRAME: usize = 1024;
+
+// Largest frame once authenticated, unless the transport is given another limit.
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 8 * 1024 * 1024;
+
+// Our identity and the authorities we accept to talk to, along with the
+// address each of them listens on.
+#[derive(Clone)]
+pub struct Authenticator {
+    name: PublicKey,
+    signature_service: SignatureService,
+    members: Arc<HashSet<PublicKey>>,
+    addresses: Arc<HashMap<SocketAddr, PublicKey>>,
+}
+
+impl Authenticator {
+    pub fn new<I>(name: PublicKey, signature_service: SignatureService, members: I) -> Self
+    where
+        I: IntoIterator<Item = (PublicKey, SocketAddr)>,
+    {
+        let addresses: HashMap<_, _> = members
+            .into_iter()
+            .map(|(name, address)| (address, name))
+            .collect();
+        Self {
+            name,
+            signature_service,
+            members: Arc::new(addresses.values().cloned().collect()),
+            addresses: Arc::new(addresses),
+        }
+    }
+
+    // Whether a member of the committee listens on this address.
+    pub fn knows(&self, address: &SocketAddr) -> bool {
+        self.addresses.contains_key(address)
+    }
+
+    // Run the handshake on an incoming connection and return the encrypted
+    // transport along with the authenticated identity of the peer.
+    pub async fn accept(&self, stream: TcpStream) -> NetworkResult<(SecureTransport, PublicKey)> {
+        timeout(HANDSHAKE_TIMEOUT, self.handshake(stream, None))
+            .await
+            .map_err(|_| NetworkError::HandshakeTimeout)?
+    }
+
+    // Connect to the member listening on this address. The peer must prove it
+    // holds the key of that member, not just any key of the committee.
+    pub async fn connect(
+        &self,
+        address: SocketAddr,
+    ) -> NetworkResult<(SecureTransport, PublicKey)> {
+        let expected = *self
+            .addresses
+            .get(&address)
+            .ok_or(NetworkError::UnknownAddress(address))?;
+        let stream = TcpStream::connect(address).await?;
+        timeout(HANDSHAKE_TIMEOUT, self.handshake(stream, Some(expected)))
+            .await
+            .map_err(|_| NetworkError::HandshakeTimeout)?
+    }
+
+    // The initiator knows whom it dials, the responder learns it from the peer.
+    async fn handshake(
+        &self,
+        stream: TcpStream,
+        expected: Option<PublicKey>,
+    ) -> NetworkResult<(SecureTransport, PublicKey)> {
+        let initiator = expected.is_some();
+        let codec = LengthDelimitedCodec::builder()
+            .max_frame_length(MAX_HANDSHAKE_FRAME)
+            .new_codec();
+        let mut framed = Framed::new(stream, codec);
+        let mut buffer = vec![0u8; MAX_NOISE_MESSAGE];
+
+        // The noise handshake (-> e, <- e ee) sets up the session keys.
+        let builder = snow::Builder::new(NOISE_PARAMS.parse().unwrap()).prologue(PROLOGUE);
+        let mut noise = match initiator {
+            true => builder.build_initiator()?,
+            false => builder.build_responder()?,
+        };
+        if initiator {
+            let length = noise.write_message(&[], &mut buffer)?;
+            framed
+                .send(Bytes::copy_from_slice(&buffer[..length]))
+                .await?;
+            let frame = framed
+                .next()
+                .await
+                .ok_or(NetworkError::ConnectionClosed)??;
+            noise.read_message(&frame, &mut buffer)?;
+        } else {
+            let frame = framed
+                .next()
+                .await
+                .ok_or(NetworkError::ConnectionClosed)??;
+            noise.read_message(&frame, &mut buffer)?;
+            let length = noise.write_message(&[], &mut buffer)?;
+            framed
+                .send(Bytes::copy_from_slice(&buffer[..length]))
+                .await?;
+        }
+        let hash = noise.get_handshake_hash().to_vec();
+        let mut transport = SecureTransport {
+            framed,
+            noise: noise.into_transport_mode()?,
+            buffer,
+        };
+
+        // Both sides then sign the session with their authority key: 32 bytes of
+        // public key followed by 64 bytes of signature.
+        let signature = self
+            .signature_service
+            .clone()
+            .request_signature(session_digest(&hash, initiator))
+            .await;
+        let mut auth = Vec::with_capacity(96);
+        auth.extend_from_slice(&self.name.0);
+        auth.extend_from_slice(&signature.to_bytes());
+        transport.send(Bytes::from(auth)).await?;
+
+        // 在反序列化任何消息之前先验证对方的身份
+        let auth = transport
+            .recv()
+            .await
+            .ok_or(NetworkError::ConnectionClosed)??;
+        if auth.len() != 96 {
+            return Err(NetworkError::InvalidHandshake);
+        }
+        let peer = PublicKey(auth[..32].try_into().unwrap());
+        if !self.members.contains(&peer) {
+            return Err(NetworkError::UnknownPeer(peer));
+        }
+        if let Some(expected) = expected.filter(|x| *x != peer) {
+            return Err(NetworkError::UnexpectedPeer(expected, peer));
+        }
+        let signature = Signature::from_bytes(auth[32..].try_into().unwrap());
+        signature.verify(&session_digest(&hash, !initiator), &peer)?;
+        transport.set_max_frame_size(DEFAULT_MAX_FRAME_SIZE);
+        Ok((transport, peer))
+    }
+}
+
+// The digest signed by each side, bound to the session and to its role in it.
+fn session_digest(hash: &[u8], initiator: bool) -> Digest {
+    let mut hasher = Sha512::new();
+    hasher.update(PROLOGUE);
+    hasher.update(if initiator {
+        b"initiator"
+    } else {
+        b"responder"
+    });
+    hasher.update(hash);
+    Digest(hasher.finalize().as_slice()[..32].try_into().unwrap())
+}
+
+// A connection whose frames are encrypted with the session keys.
+pub struct SecureTransport {
+    framed: Framed<TcpStream, LengthDelimitedCodec>,
+    noise: snow::TransportState,
+    buffer: Vec<u8>,
+}
+
+impl SecureTransport {
+    // Messages larger than `size` bytes are refused in both directions. The
+    // codec sees them encrypted, with a tag for each chunk.
+    pub fn set_max_frame_size(&mut self, size: usize) {
+        let chunks = max(1, size.div_ceil(MAX_NOISE_MESSAGE - TAG_SIZE));
+        let length = size.saturating_add(chunks * TAG_SIZE);
+        self.framed.codec_mut().set_max_frame_length(length);
+    }
+
+    pub async fn send(&mut self, message: Bytes) -> NetworkResult<()> {
+        // Messages larger than a noise message are encrypted in chunks, all full
+        // but the last one.
+        let chunk_size = MAX_NOISE_MESSAGE - TAG_SIZE;
+        let chunks = match message.is_empty() {
+            true => vec![&message[..]],
+            false => message.chunks(chunk_size).collect(),
+        };
+        let mut frame = BytesMut::with_capacity(message.len() + chunks.len() * TAG_SIZE);
+        for chunk in chunks {
+            let length = self.noise.write_message(chunk, &mut self.buffer)?;
+            frame.extend_from_slice(&self.buffer[..length]);
+        }
+        self.framed.send(frame.freeze()).await?;
+        Ok(())
+    }
+
+    pub async fn recv(&mut self) -> Option<NetworkResult<Bytes>> {
+        let frame = match self.framed.next().await? {
+            Ok(frame) => frame,
+            Err(e) => return Some(Err(e.into())),
+        };
+        Some(self.decrypt(&frame))
+    }
+
+    fn decrypt(&mut self, frame: &[u8]) -> NetworkResult<Bytes> {
+        if frame.is_empty() {
+            return Err(NetworkError::InvalidFrame);
+        }
+        let mut message = BytesMut::with_capacity(frame.len());
+        for chunk in frame.chunks(MAX_NOISE_MESSAGE) {
+            let length = self.noise.read_message(chunk, &mut self.buffer)?;
+            message.extend_from_slice(&self.buffer[..length]);
+        }
+        Ok(message.freeze())
+    }
+}
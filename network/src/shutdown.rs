@@ -0,0 +1,81 @@
+use futures::future::pending;
+use std::future::Future;
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+
+#[cfg(test)]
+#[path = "tests/shutdown_tests.rs"]
+pub mod shutdown_tests;
+
+// Signals the tasks of a node to stop, then waits for them to finish.
+pub struct ShutdownHandle {
+    signal: watch::Sender<bool>,
+    alive: mpsc::Sender<()>,
+    done: mpsc::Receiver<()>,
+}
+
+impl ShutdownHandle {
+    pub fn new() -> Self {
+        let (signal, _) = watch::channel(false);
+        let (alive, done) = mpsc::channel(1);
+        Self {
+            signal,
+            alive,
+            done,
+        }
+    }
+
+    pub fn subscribe(&self) -> Shutdown {
+        Shutdown {
+            signal: self.signal.subscribe(),
+            _alive: self.alive.clone(),
+        }
+    }
+
+    pub async fn shutdown(mut self) {
+        let _ = self.signal.send(true);
+        // Every task holds a copy of `alive`: the channel closes once they all ended.
+        drop(self.alive);
+        let _ = self.done.recv().await;
+    }
+}
+
+impl Default for ShutdownHandle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Held by the tasks to stop on shutdown. A node never shuts down if its handle
+// is dropped without signalling.
+#[derive(Clone)]
+pub struct Shutdown {
+    signal: watch::Receiver<bool>,
+    _alive: mpsc::Sender<()>,
+}
+
+impl Shutdown {
+    // Resolves once the shutdown is signalled.
+    pub async fn wait(&mut self) {
+        while !*self.signal.borrow_and_update() {
+            if self.signal.changed().await.is_err() {
+                pending::<()>().await;
+            }
+        }
+    }
+
+    // Run a task until it completes or the node shuts down; the task is dropped
+    // at its next await point in the latter case.
+    pub fn spawn<F>(&self, task: F) -> JoinHandle<()>
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let mut shutdown = self.clone();
+        tokio::spawn(async move {
+            tokio::select! {
+                () = task => (),
+                () = shutdown.wait() => (),
+            }
+        })
+    }
+}
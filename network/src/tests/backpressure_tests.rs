@@ -0,0 +1,49 @@
+use super::*;
+use tokio::sync::mpsc::channel;
+use tokio::time::{timeout, Duration};
+
+#[tokio::test]
+async fn drop_newest_when_full() {
+    static DROPS: DropCounter = DropCounter::new("test");
+    let (tx, mut rx) = channel(2);
+    for i in 0..5u8 {
+        assert!(push(&tx, i, DropPolicy::DropNewest, &DROPS).await.is_ok());
+    }
+    assert_eq!(DROPS.count(), 3);
+
+    // The queue kept the oldest messages.
+    assert_eq!(rx.recv().await, Some(0));
+    assert_eq!(rx.recv().await, Some(1));
+    assert!(rx.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn block_waits_for_room() {
+    static DROPS: DropCounter = DropCounter::new("test");
+    let (tx, mut rx) = channel(1);
+    push(&tx, 0u8, DropPolicy::Block, &DROPS).await.unwrap();
+
+    // The second message waits until the consumer makes room.
+    let blocked = timeout(
+        Duration::from_millis(50),
+        push(&tx, 1u8, DropPolicy::Block, &DROPS),
+    );
+    assert!(blocked.await.is_err());
+    assert_eq!(rx.recv().await, Some(0));
+    push(&tx, 1u8, DropPolicy::Block, &DROPS).await.unwrap();
+    assert_eq!(rx.recv().await, Some(1));
+    assert_eq!(DROPS.count(), 0);
+}
+
+#[tokio::test]
+async fn closed_queue() {
+    static DROPS: DropCounter = DropCounter::new("test");
+    let (tx, rx) = channel(1);
+    drop(rx);
+    for policy in [DropPolicy::Block, DropPolicy::DropNewest] {
+        match push(&tx, 0u8, policy, &DROPS).await {
+            Err(NetworkError::QueueClosed(name)) => assert_eq!(name, "test"),
+            _ => assert!(false),
+        }
+    }
+}
@@ -0,0 +1,304 @@
+use super::*;
+use crate::network_tests::{address, authenticator};
+use crate::DEFAULT_MAX_FRAME_SIZE;
+use tokio::sync::oneshot::error::TryRecvError;
+
+// Run the first key, delivering the messages it receives on channel 0.
+fn node<M>(base: u16, members: usize, max_size: usize) -> Receiver<M>
+where
+    M: 'static + Send + DeserializeOwned + Debug,
+{
+    let mut mux = Mux::new(address(0, base), authenticator(0, members, base));
+    let (tx, rx) = channel(1);
+    mux.receiver(0, tx, max_size);
+    tokio::spawn(mux.run());
+    rx
+}
+
+// Connect to the first key as the i-th key.
+async fn connect(base: u16, i: usize) -> SecureTransport {
+    let authenticator = authenticator(i, 4, base);
+    let (transport, _) = authenticator.connect(address(0, base)).await.unwrap();
+    transport
+}
+
+#[tokio::test]
+async fn demultiplex_channels() {
+    // Run a node with two channels carrying different messages.
+    let target = address(1, 5900);
+    let mut mux = Mux::new(target, authenticator(1, 4, 5900));
+    let (tx_text, mut rx_text) = channel::<String>(1);
+    let (tx_number, mut rx_number) = channel::<u64>(1);
+    mux.receiver(0, tx_text, DEFAULT_MAX_FRAME_SIZE);
+    mux.receiver(1, tx_number, DEFAULT_MAX_FRAME_SIZE);
+    tokio::spawn(mux.run());
+    sleep(Duration::from_millis(50)).await;
+
+    // Both channels share the same connection.
+    let peer = Mux::new(address(0, 5900), authenticator(0, 4, 5900));
+    let text = Bytes::from(bincode::serialize("Ok").unwrap());
+    let number = Bytes::from(bincode::serialize(&7u64).unwrap());
+    peer.sender(1).send(target, number).await;
+    let handler = peer.sender(0).send_reliable(target, text).await;
+    assert!(handler.await.is_ok());
+    assert_eq!(rx_text.recv().await.unwrap(), "Ok");
+    assert_eq!(rx_number.recv().await.unwrap(), 7);
+}
+
+#[tokio::test]
+async fn retransmit_after_invalid_channel() {
+    let target = address(1, 5910);
+    let mut mux = Mux::new(target, authenticator(1, 4, 5910));
+    let (tx, mut rx) = channel::<String>(1);
+    mux.receiver(0, tx, DEFAULT_MAX_FRAME_SIZE);
+    tokio::spawn(mux.run());
+    sleep(Duration::from_millis(50)).await;
+
+    // The node drops the connection on a frame for a channel it does not know,
+    // the reliable message that follows goes through a new connection.
+    let peer = Mux::new(address(0, 5910), authenticator(0, 4, 5910));
+    let message = Bytes::from(bincode::serialize("Ok").unwrap());
+    peer.sender(2).send(target, message.clone()).await;
+    sleep(Duration::from_millis(100)).await;
+    let handler = peer.sender(0).send_reliable(target, message).await;
+    assert!(handler.await.is_ok());
+    assert_eq!(rx.recv().await.unwrap(), "Ok");
+}
+
+#[tokio::test]
+async fn receive() {
+    let base = 5200;
+    let mut rx = node::<String>(base, 4, DEFAULT_MAX_FRAME_SIZE);
+    sleep(Duration::from_millis(50)).await;
+
+    // Send a message.
+    let message = "Ok";
+    let bytes = bincode::serialize(message).unwrap();
+    let mut transport = connect(base, 1).await;
+    transport.send(frame(0, &bytes)).await.unwrap();
+
+    // Ensure the message gets passed to the channel.
+    match rx.recv().await {
+        Some(value) => assert_eq!(value, message),
+        _ => assert!(false),
+    }
+}
+
+#[tokio::test]
+async fn large_message() {
+    let base = 5300;
+    let mut rx = node::<Vec<u8>>(base, 4, DEFAULT_MAX_FRAME_SIZE);
+    sleep(Duration::from_millis(50)).await;
+
+    // Messages larger than a noise message are sent in chunks.
+    let message = vec![7u8; 200_000];
+    let bytes = bincode::serialize(&message).unwrap();
+    let mut transport = connect(base, 1).await;
+    transport.send(frame(0, &bytes)).await.unwrap();
+
+    match rx.recv().await {
+        Some(value) => assert_eq!(value, message),
+        _ => assert!(false),
+    }
+}
+
+#[tokio::test]
+async fn reject_unknown_peer() {
+    // The node only accepts the first 3 keys.
+    let base = 5400;
+    let _rx = node::<String>(base, 3, DEFAULT_MAX_FRAME_SIZE);
+    sleep(Duration::from_millis(50)).await;
+
+    // The handshake with the fourth key fails.
+    let mut transport = connect(base, 3).await;
+    let _ = transport.send(frame(0, b"Ok")).await;
+    assert!(transport.recv().await.is_none_or(|x| x.is_err()));
+}
+
+#[tokio::test]
+async fn reject_large_frame() {
+    let base = 5700;
+    let mut rx = node::<Vec<u8>>(base, 4, 1_000);
+    sleep(Duration::from_millis(50)).await;
+
+    // The node drops the connection instead of reading the frame.
+    let message = vec![7u8; 2_000];
+    let bytes = bincode::serialize(&message).unwrap();
+    let mut transport = connect(base, 1).await;
+    transport.send(frame(0, &bytes)).await.unwrap();
+    assert!(transport.recv().await.is_none_or(|x| x.is_err()));
+    assert!(rx.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn ban_after_malformed_frames() {
+    let base = 5800;
+    let mut rx = node::<String>(base, 4, DEFAULT_MAX_FRAME_SIZE);
+    sleep(Duration::from_millis(50)).await;
+
+    // A string claiming to be 1 GB long, three times over.
+    for _ in 0..3 {
+        let mut transport = connect(base, 1).await;
+        let bytes = (1u64 << 30).to_le_bytes();
+        transport.send(frame(0, &bytes)).await.unwrap();
+        let _ = transport.recv().await;
+    }
+    sleep(Duration::from_millis(50)).await;
+
+    // The peer is now banned, even its valid messages are dropped.
+    let mut transport = connect(base, 1).await;
+    let bytes = bincode::serialize("Ok").unwrap();
+    let _ = transport.send(frame(0, &bytes)).await;
+    assert!(transport.recv().await.is_none_or(|x| x.is_err()));
+    assert!(rx.try_recv().is_err());
+
+    // Other peers are still welcome.
+    let mut transport = connect(base, 2).await;
+    transport.send(frame(0, &bytes)).await.unwrap();
+    assert_eq!(rx.recv().await, Some("Ok".to_string()));
+}
+
+#[tokio::test]
+async fn retransmit_after_reconnect() {
+    // Send a message to a peer that is not up yet.
+    let peer = Mux::new(address(1, 5600), authenticator(1, 4, 5600));
+    let message = "Ok";
+    let bytes = Bytes::from(bincode::serialize(message).unwrap());
+    let handler = peer.sender(0).send_reliable(address(0, 5600), bytes).await;
+    sleep(Duration::from_millis(100)).await;
+
+    // The message gets through once the peer boots.
+    let mut rx = node::<String>(5600, 4, DEFAULT_MAX_FRAME_SIZE);
+    assert!(handler.await.is_ok());
+    assert_eq!(rx.recv().await.unwrap(), message);
+}
+
+#[tokio::test]
+async fn drop_oldest_pending_frames() {
+    // Nobody listens at the address of the peer.
+    let peer = Mux::new(address(0, 5620), authenticator(0, 4, 5620));
+    let sender = peer.sender(0);
+    let mut handlers = Vec::new();
+    for i in 0..MAX_PENDING_FRAMES + 1 {
+        let bytes = Bytes::from(i.to_le_bytes().to_vec());
+        handlers.push(sender.send_reliable(address(1, 5620), bytes).await);
+    }
+    sleep(Duration::from_millis(100)).await;
+
+    // Only the oldest frame gave way.
+    assert!(matches!(handlers[0].try_recv(), Err(TryRecvError::Closed)));
+    assert!(handlers[1..]
+        .iter_mut()
+        .all(|x| matches!(x.try_recv(), Err(TryRecvError::Empty))));
+}
+
+#[tokio::test]
+async fn prune_cancelled_frames() {
+    let peer = Mux::new(address(0, 5630), authenticator(0, 4, 5630));
+    let sender = peer.sender(0);
+    let mut handlers = Vec::new();
+    for i in 0..MAX_PENDING_FRAMES {
+        let bytes = Bytes::from(i.to_le_bytes().to_vec());
+        handlers.push(sender.send_reliable(address(1, 5630), bytes).await);
+    }
+
+    // A cancelled frame makes room for a new one.
+    handlers.remove(0);
+    let bytes = Bytes::from("Ok");
+    handlers.push(sender.send_reliable(address(1, 5630), bytes).await);
+    sleep(Duration::from_millis(100)).await;
+    assert!(handlers
+        .iter_mut()
+        .all(|x| matches!(x.try_recv(), Err(TryRecvError::Empty))));
+}
+
+#[tokio::test]
+async fn accept_message_at_limit() {
+    // The message spans several noise messages, each with its own tag.
+    let base = 5640;
+    let mut rx = node::<Vec<u8>>(base, 4, 200_000);
+    sleep(Duration::from_millis(50)).await;
+
+    // A vector is serialized after its 8 bytes of length.
+    let message = vec![7u8; 200_000 - 8];
+    let bytes = bincode::serialize(&message).unwrap();
+    let mut transport = connect(base, 1).await;
+    transport.send(frame(0, &bytes)).await.unwrap();
+    assert_eq!(rx.recv().await, Some(message));
+}
+
+#[tokio::test]
+async fn reject_large_message_per_channel() {
+    let base = 5650;
+    let mut mux = Mux::new(address(0, base), authenticator(0, 4, base));
+    let (tx_small, mut rx_small) = channel::<Vec<u8>>(1);
+    let (tx_large, _rx_large) = channel::<Vec<u8>>(1);
+    mux.receiver(0, tx_small, 1_000);
+    mux.receiver(1, tx_large, DEFAULT_MAX_FRAME_SIZE);
+    tokio::spawn(mux.run());
+    sleep(Duration::from_millis(50)).await;
+
+    // The connection takes large messages, but not on the small channel.
+    let message = vec![7u8; 2_000];
+    let bytes = bincode::serialize(&message).unwrap();
+    let mut transport = connect(base, 1).await;
+    transport.send(frame(0, &bytes)).await.unwrap();
+    assert!(transport.recv().await.is_none_or(|x| x.is_err()));
+    assert!(rx_small.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn send_and_acknowledge() {
+    let mut rx = node::<String>(5660, 4, DEFAULT_MAX_FRAME_SIZE);
+    sleep(Duration::from_millis(50)).await;
+
+    // Send a message and wait for the acknowledgement.
+    let peer = Mux::new(address(1, 5660), authenticator(1, 4, 5660));
+    let message = "Ok";
+    let bytes = Bytes::from(bincode::serialize(message).unwrap());
+    let handler = peer.sender(0).send_reliable(address(0, 5660), bytes).await;
+    assert_eq!(handler.await.unwrap(), Bytes::from("Ack"));
+    assert_eq!(rx.recv().await.unwrap(), message);
+}
+
+#[tokio::test]
+async fn retransmit_unacknowledged_frame() {
+    let base = 5670;
+    let listener = TcpListener::bind(address(1, base)).await.unwrap();
+    let peer = Mux::new(address(0, base), authenticator(0, 4, base));
+    let bytes = Bytes::from(bincode::serialize("Ok").unwrap());
+    let handler = peer.sender(0).send_reliable(address(1, base), bytes).await;
+
+    // The first connection breaks before the frame is acknowledged.
+    let (socket, _) = listener.accept().await.unwrap();
+    let (mut transport, _) = authenticator(1, 4, base).accept(socket).await.unwrap();
+    let first = transport.recv().await.unwrap().unwrap();
+    drop(transport);
+
+    // The frame comes again on the next connection, which acknowledges it.
+    let (socket, _) = listener.accept().await.unwrap();
+    let (mut transport, _) = authenticator(1, 4, base).accept(socket).await.unwrap();
+    let second = transport.recv().await.unwrap().unwrap();
+    assert_eq!(first, second);
+    transport.send(Bytes::from("Ack")).await.unwrap();
+    assert_eq!(handler.await.unwrap(), Bytes::from("Ack"));
+}
+
+#[tokio::test]
+async fn connect_to_members_only() {
+    // The node only knows the first 3 keys.
+    let base = 5680;
+    let peer = Mux::new(address(0, base), authenticator(0, 3, base));
+    let sender = peer.sender(0);
+    sender.send(address(3, base), Bytes::from("Ok")).await;
+    assert!(peer.connections.peers.lock().unwrap().is_empty());
+
+    // Reliable messages to others are cancelled at once.
+    let handler = sender
+        .send_reliable(address(3, base), Bytes::from("Ok"))
+        .await;
+    assert!(handler.await.is_err());
+
+    sender.send(address(1, base), Bytes::from("Ok")).await;
+    assert_eq!(peer.connections.peers.lock().unwrap().len(), 1);
+}
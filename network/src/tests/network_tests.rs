@@ -1,15 +1,42 @@
 use super::*;
+use crypto::{generate_keypair, SecretKey, SignatureService};
 use futures::future::try_join_all;
-use std::time::Duration;
+use rand::rngs::StdRng;
+use rand::SeedableRng as _;
+use tokio::net::TcpListener;
+use tokio::sync::mpsc::channel;
 use tokio::task::JoinHandle;
-use tokio::time::sleep;
+use tokio::time::{sleep, Duration};
 
-pub fn listener(address: SocketAddr) -> JoinHandle<()> {
+pub fn keys() -> Vec<(PublicKey, SecretKey)> {
+    let mut rng = StdRng::from_seed([0; 32]);
+    (0..4).map(|_| generate_keypair(&mut rng)).collect()
+}
+
+// The address of the i-th key, the keys of a test listening on consecutive ports.
+pub fn address(i: usize, base: u16) -> SocketAddr {
+    SocketAddr::from(([127, 0, 0, 1], base + i as u16))
+}
+
+// An authenticator for the i-th key, accepting the first `members` keys.
+pub fn authenticator(i: usize, members: usize, base: u16) -> Authenticator {
+    let keys = keys();
+    let (name, secret) = keys[i].clone();
+    let members = keys
+        .into_iter()
+        .enumerate()
+        .take(members)
+        .map(|(j, (name, _))| (name, address(j, base)));
+    Authenticator::new(name, SignatureService::new(secret, None), members)
+}
+
+// Run the i-th key and wait for one message.
+pub fn listener(i: usize, base: u16) -> JoinHandle<()> {
     tokio::spawn(async move {
-        let listener = TcpListener::bind(&address).await.unwrap();
+        let listener = TcpListener::bind(address(i, base)).await.unwrap();
         let (socket, _) = listener.accept().await.unwrap();
-        let mut transport = Framed::new(socket, LengthDelimitedCodec::new());
-        match transport.next().await {
+        let (mut transport, _) = authenticator(i, 4, base).accept(socket).await.unwrap();
+        match transport.recv().await {
             Some(Ok(_)) => assert!(true),
             _ => assert!(false),
         }
@@ -18,19 +45,16 @@ pub fn listener(address: SocketAddr) -> JoinHandle<()> {
 
 #[tokio::test]
 async fn send() {
-    // Make the network sender.
+    // Forward the messages of a channel to the network.
     let (tx, rx) = channel(1);
-    let mut sender = NetSender::new(rx);
-    tokio::spawn(async move {
-        sender.run().await;
-    });
+    let mux = Mux::new(address(0, 5000), authenticator(0, 4, 5000));
+    tokio::spawn(mux.sender(0).forward(rx));
 
     // Run a TCP server.
-    let address = "127.0.0.1:5000".parse::<SocketAddr>().unwrap();
-    let handle = listener(address);
+    let handle = listener(1, 5000);
 
     // Send a message.
-    let message = NetMessage(Bytes::from("Ok"), vec![address]);
+    let message = NetMessage(Bytes::from("Ok"), vec![address(1, 5000)]);
     let _ = tx.send(message).await;
 
     // Ensure the server received the message (ie. it did not panic).
@@ -39,26 +63,15 @@ async fn send() {
 
 #[tokio::test]
 async fn broadcast() {
-    // Make the network sender.
     let (tx, rx) = channel(1);
-    let mut sender = NetSender::new(rx);
-    tokio::spawn(async move {
-        sender.run().await;
-    });
+    let mux = Mux::new(address(0, 5100), authenticator(0, 4, 5100));
+    tokio::spawn(mux.sender(0).forward(rx));
 
     // Run 3 TCP servers.
-    let (handles, addresses): (Vec<_>, Vec<_>) = (0..3)
-        .map(|x| {
-            let address = format!("127.0.0.1:{}", 5100 + x)
-                .parse::<SocketAddr>()
-                .unwrap();
-            (listener(address), address)
-        })
-        .collect::<Vec<_>>()
-        .into_iter()
-        .unzip();
+    let handles: Vec<_> = (1..4).map(|i| listener(i, 5100)).collect();
 
     // Broadcast a message.
+    let addresses = (1..4).map(|i| address(i, 5100)).collect();
     let message = NetMessage(Bytes::from("Ok"), addresses);
     let _ = tx.send(message).await;
 
@@ -67,26 +80,28 @@ async fn broadcast() {
 }
 
 #[tokio::test]
-async fn receive() {
-    // Make the network receiver.
-    let address = "127.0.0.1:5200".parse::<SocketAddr>().unwrap();
-    let (tx, mut rx): (Sender<String>, _) = channel(1);
-    let receiver = NetReceiver::new(address.clone(), tx);
+async fn reject_unexpected_peer() {
+    // The second key answers at the address of the first one.
     tokio::spawn(async move {
-        receiver.run().await;
+        let listener = TcpListener::bind(address(1, 5500)).await.unwrap();
+        let (socket, _) = listener.accept().await.unwrap();
+        let _ = authenticator(2, 4, 5500).accept(socket).await;
     });
     sleep(Duration::from_millis(50)).await;
 
-    // Send a message.
-    let message = "Ok";
-    let bytes = Bytes::from(bincode::serialize(message).unwrap());
-    let stream = TcpStream::connect(address).await.unwrap();
-    let mut transport = Framed::new(stream, LengthDelimitedCodec::new());
-    transport.send(bytes.clone()).await.unwrap();
+    let (name, _) = keys()[1];
+    let (other, _) = keys()[2];
+    match authenticator(0, 4, 5500).connect(address(1, 5500)).await {
+        Err(NetworkError::UnexpectedPeer(x, y)) => assert_eq!((x, y), (name, other)),
+        _ => assert!(false),
+    }
+}
 
-    // Ensure the message gets passed to the channel.
-    match rx.recv().await {
-        Some(value) => assert_eq!(value, message),
+#[tokio::test]
+async fn reject_unknown_address() {
+    let address = "127.0.0.1:5510".parse::<SocketAddr>().unwrap();
+    match authenticator(0, 4, 5500).connect(address).await {
+        Err(NetworkError::UnknownAddress(x)) => assert_eq!(x, address),
         _ => assert!(false),
     }
 }
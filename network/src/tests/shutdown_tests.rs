@@ -0,0 +1,49 @@
+use super::*;
+use tokio::sync::oneshot;
+use tokio::time::{sleep, timeout, Duration};
+
+#[tokio::test]
+async fn stop_tasks() {
+    let handle = ShutdownHandle::new();
+    let shutdown = handle.subscribe();
+
+    // A task that never ends by itself, and notices when it is dropped.
+    let (tx, rx) = oneshot::channel::<()>();
+    shutdown.spawn(async move {
+        let _tx = tx;
+        pending::<()>().await;
+    });
+    drop(shutdown);
+
+    timeout(Duration::from_secs(1), handle.shutdown())
+        .await
+        .unwrap();
+    assert!(rx.await.is_err());
+}
+
+#[tokio::test]
+async fn wait_for_tasks() {
+    let handle = ShutdownHandle::new();
+    let mut shutdown = handle.subscribe();
+
+    // A task that takes some time to clean up after the signal.
+    let (tx, mut rx) = oneshot::channel();
+    tokio::spawn(async move {
+        shutdown.wait().await;
+        sleep(Duration::from_millis(50)).await;
+        let _ = tx.send(());
+    });
+
+    handle.shutdown().await;
+    assert!(rx.try_recv().is_ok());
+}
+
+#[tokio::test]
+async fn no_signal_without_shutdown() {
+    let handle = ShutdownHandle::new();
+    let mut shutdown = handle.subscribe();
+    drop(handle);
+    assert!(timeout(Duration::from_millis(50), shutdown.wait())
+        .await
+        .is_err());
+}
@@ -0,0 +1,101 @@
+use crate::node::NodeError;
+use consensus::Tuning;
+use log::{info, warn};
+use network::Shutdown;
+use std::fs;
+use std::os::unix::fs::{DirBuilderExt as _, PermissionsExt as _};
+use std::path::Path;
+use tokio::io::{AsyncBufReadExt as _, AsyncWriteExt as _, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::mpsc::Sender;
+
+#[cfg(test)]
+#[path = "tests/admin_tests.rs"]
+pub mod admin_tests;
+
+// The admin socket of a node, to change parameters without restarting it.
+// Requests are lines holding a JSON `Tuning`, applied by the consensus core at
+// the next height (or epoch). Each one is answered by a line holding
+// `{"Ok":null}` or `{"Err":"<reason>"}`.
+pub struct Admin;
+
+impl Admin {
+    // Bind the socket in a directory only we can enter, and move it into place
+    // once only we can use it: the umask never decides who may connect.
+    fn bind(path: &str) -> std::io::Result<UnixListener> {
+        let private = format!("{}.bind", path);
+        let _ = fs::remove_dir_all(&private);
+        fs::DirBuilder::new().mode(0o700).create(&private)?;
+        let socket = Path::new(&private).join("admin.sock");
+        let result = UnixListener::bind(&socket).and_then(|listener| {
+            fs::set_permissions(&socket, fs::Permissions::from_mode(0o600))?;
+            fs::rename(&socket, path)?;
+            Ok(listener)
+        });
+        let _ = fs::remove_dir_all(&private);
+        result
+    }
+
+    pub fn spawn(
+        path: &str,
+        tx_tuning: Sender<Tuning>,
+        shutdown: Shutdown,
+    ) -> Result<(), NodeError> {
+        let error = |e: std::io::Error| NodeError::AdminError(format!("{}: {}", path, e));
+        let _ = fs::remove_file(path);
+        let listener = Self::bind(path).map_err(error)?;
+        info!("Admin socket listening on {}", path);
+        shutdown.spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, _)) => {
+                        tokio::spawn(Self::serve(stream, tx_tuning.clone()));
+                    }
+                    Err(e) => warn!("Failed to accept admin connection: {}", e),
+                }
+            }
+        });
+        Ok(())
+    }
+
+    async fn serve(stream: UnixStream, tx_tuning: Sender<Tuning>) {
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            let result = match serde_json::from_str::<Tuning>(&line) {
+                Ok(tuning) => match tuning.check() {
+                    Ok(()) => tx_tuning
+                        .send(tuning)
+                        .await
+                        .map_err(|_| "The consensus core is not running".to_string()),
+                    Err(e) => Err(e.to_string()),
+                },
+                Err(e) => Err(format!("Malformed tuning: {}", e)),
+            };
+            let mut response = serde_json::to_string(&result).expect("Failed to serialize");
+            response.push('\n');
+            if writer.write_all(response.as_bytes()).await.is_err() {
+                break;
+            }
+        }
+    }
+
+    // Send a tuning (JSON) to the admin socket of a running node.
+    pub async fn tune(path: &str, tuning: &str) -> Result<(), NodeError> {
+        let error = |e: std::io::Error| NodeError::AdminError(format!("{}: {}", path, e));
+        let stream = UnixStream::connect(path).await.map_err(error)?;
+        let (reader, mut writer) = stream.into_split();
+        // The request must fit on a line.
+        let request = format!("{}\n", tuning.replace('\n', " "));
+        writer.write_all(request.as_bytes()).await.map_err(error)?;
+        let response = BufReader::new(reader)
+            .lines()
+            .next_line()
+            .await
+            .map_err(error)?
+            .unwrap_or_default();
+        serde_json::from_str::<Result<(), String>>(&response)
+            .map_err(|e| NodeError::AdminError(format!("Invalid response: {}", e)))?
+            .map_err(NodeError::AdminError)
+    }
+}
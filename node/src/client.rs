@@ -23,6 +23,7 @@ async fn main() -> Result<()> {
         .args_from_usage("--size=<INT> 'The size of each transaction in bytes'")
         .args_from_usage("--rate=<INT> 'The rate (txs/s) at which to send the transactions'")
         .args_from_usage("--nodes=[ADDR]... 'Network addresses that must be reachable before starting the benchmark.'")
+        .args_from_usage("--priority=[INT] 'The priority declared for the transactions (0-255, default 0)'")
         .setting(AppSettings::ArgRequiredElseHelp)
         .get_matches();
 
@@ -62,6 +63,11 @@ async fn main() -> Result<()> {
         .map(|x| x.parse::<SocketAddr>())
         .collect::<Result<Vec<_>, _>>()
         .context("Invalid socket address format")?;
+    let priority = matches
+        .value_of("priority")
+        .unwrap_or("0")
+        .parse::<u8>()
+        .context("The priority must be an integer between 0 and 255")?;
 
     info!("Node address: {}", target);
     info!("Transactions size: {} B", size);
@@ -73,6 +79,7 @@ async fn main() -> Result<()> {
         timeout,
         synctime,
         nodes,
+        priority,
     };
 
     // Wait for all nodes to be online and synchronized.
@@ -89,6 +96,7 @@ struct Client {
     timeout: u64,
     synctime: u64,
     nodes: Vec<SocketAddr>,
+    priority: u8,
 }
 
 impl Client {
@@ -102,6 +110,11 @@ impl Client {
                 "Transaction size must be at least 9 bytes",
             ));
         }
+        if self.priority > 0 && self.size < 10 {
+            return Err(anyhow::Error::msg(
+                "Transaction size must be at least 10 bytes to declare a priority",
+            ));
+        }
 
         // Connect to the mempool.
         let stream = TcpStream::connect(self.target)
@@ -131,6 +144,9 @@ impl Client {
 
                     tx.put_u8(0u8); // Sample txs start with 0.
                     tx.put_u64(counter); // This counter identifies the tx.
+                    if self.priority > 0 {
+                        tx.put_u8(self.priority);
+                    }
                     tx.resize(self.size, 0u8);
                     tx.split().freeze()
                 } else {
@@ -138,6 +154,9 @@ impl Client {
 
                     tx.put_u8(1u8); // Standard txs start with 1.
                     tx.put_u64(r); // Ensures all clients send different txs.
+                    if self.priority > 0 {
+                        tx.put_u8(self.priority);
+                    }
                     tx.resize(self.size, 0u8);
                     tx.split().freeze()
                 };
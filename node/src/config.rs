@@ -1,14 +1,24 @@
 use crate::node::NodeError;
-use consensus::{Committee as ConsensusCommittee, Parameters as ConsensusParameters};
+use consensus::{
+    Committee as ConsensusCommittee, Parameters as ConsensusParameters, Protocol, Stake,
+    VerificationPolicy,
+};
 use crypto::{generate_keypair, generate_production_keypair, PublicKey, SecretKey, SecretShare};
 use mempool::{Committee as MempoolCommittee, Parameters as MempoolParameters};
 use rand::rngs::StdRng;
 use rand::SeedableRng as _;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::fs::{self, OpenOptions};
 use std::io::BufWriter;
 use std::io::Write as _;
+use std::net::SocketAddr;
+use thiserror::Error;
+
+#[cfg(test)]
+#[path = "tests/config_tests.rs"]
+pub mod config_tests;
 
 pub trait Export: Serialize + DeserializeOwned {
     fn read(path: &str) -> Result<Self, NodeError> {
@@ -26,7 +36,11 @@ pub trait Export: Serialize + DeserializeOwned {
     fn write(&self, path: &str) -> Result<(), NodeError> {
         //将对象序列化成字节码存储在文件中
         let writer = || -> Result<(), std::io::Error> {
-            let file = OpenOptions::new().create(true).write(true).open(path)?;
+            let file = OpenOptions::new()
+                .create(true)
+                .write(true)
+                .truncate(true)
+                .open(path)?;
             let mut writer = BufWriter::new(file);
             let data = serde_json::to_string_pretty(self).unwrap();
             writer.write_all(data.as_ref())?;
@@ -44,7 +58,10 @@ pub trait Export: Serialize + DeserializeOwned {
 pub struct Parameters {
     pub consensus: ConsensusParameters, // Consensus
     pub mempool: MempoolParameters,     //MemPool 存储tx
-    pub protocol: u8,
+    #[serde(default)]
+    pub protocol: Protocol, // by name, see `consensus::Protocol`
+    #[serde(default)]
+    pub verification: VerificationPolicy, // applies to both consensus and mempool
 }
 
 impl Export for Parameters {}
@@ -74,10 +91,98 @@ impl Default for Secret {
 
 impl Export for SecretShare {}
 
+#[derive(Error, Debug)]
+pub enum CommitteeError {
+    #[error("The committee is empty")]
+    Empty,
+
+    #[error("Authority {0} appears more than once")]
+    DuplicateName(PublicKey),
+
+    #[error("Authority ids must be 0 to {0}, each used once")]
+    InvalidIds(usize),
+
+    #[error("Authority {0} has no stake")]
+    ZeroStake(PublicKey),
+
+    #[error("Address {0} is used more than once")]
+    DuplicateAddress(SocketAddr),
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct Authority {
+    pub name: PublicKey,
+    pub id: usize, // id of the node in the tss public key share set
+    pub stake: Stake,
+    pub address: SocketAddr,       // consensus, SMVBA and mempool messages
+    pub front_address: SocketAddr, // client transactions
+}
+
+// The committee file: consensus and mempool derive their views from it.
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct Committee {
-    pub consensus: ConsensusCommittee,
-    pub mempool: MempoolCommittee,
+    pub authorities: Vec<Authority>,
+    pub epoch: u128,
 }
 
 impl Export for Committee {}
+
+impl Committee {
+    // Read and validate the committee file.
+    pub fn load(path: &str) -> Result<Self, NodeError> {
+        let committee = Self::read(path)?;
+        committee.validate()?;
+        Ok(committee)
+    }
+
+    pub fn validate(&self) -> Result<(), CommitteeError> {
+        if self.authorities.is_empty() {
+            return Err(CommitteeError::Empty);
+        }
+
+        let mut names = HashSet::new();
+        let mut ids = HashSet::new();
+        let mut addresses = HashSet::new();
+        for authority in &self.authorities {
+            if !names.insert(authority.name) {
+                return Err(CommitteeError::DuplicateName(authority.name));
+            }
+            if authority.stake == 0 {
+                return Err(CommitteeError::ZeroStake(authority.name));
+            }
+            for address in [authority.address, authority.front_address] {
+                if !addresses.insert(address) {
+                    return Err(CommitteeError::DuplicateAddress(address));
+                }
+            }
+            ids.insert(authority.id);
+        }
+
+        // The tss key shares are numbered from 0.
+        let size = self.authorities.len();
+        if ids.len() != size || ids.iter().any(|x| *x >= size) {
+            return Err(CommitteeError::InvalidIds(size - 1));
+        }
+        Ok(())
+    }
+
+    pub fn consensus(&self) -> ConsensusCommittee {
+        ConsensusCommittee::new(
+            self.authorities
+                .iter()
+                .map(|x| (x.name, x.id, x.stake, x.address))
+                .collect(),
+            self.epoch,
+        )
+    }
+
+    pub fn mempool(&self) -> MempoolCommittee {
+        MempoolCommittee::new(
+            self.authorities
+                .iter()
+                .map(|x| (x.name, x.front_address, x.address))
+                .collect(),
+            self.epoch,
+        )
+    }
+}
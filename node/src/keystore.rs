@@ -0,0 +1,209 @@
+use crate::config::Export;
+use crate::node::NodeError;
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::{Aead as _, KeyInit as _};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use rand::RngCore as _;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::Read as _;
+use std::os::unix::io::FromRawFd as _;
+
+#[cfg(test)]
+#[path = "tests/keystore_tests.rs"]
+pub mod keystore_tests;
+
+// The environment variable holding the password of the key files.
+pub const PASSWORD_ENV: &str = "NODE_KEY_PASSWORD";
+
+// Argon2id parameters deriving the encryption key from the password.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct KdfParams {
+    pub m_cost: u32, // memory in KiB
+    pub t_cost: u32, // iterations
+    pub p_cost: u32, // parallelism
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        Self {
+            m_cost: Params::DEFAULT_M_COST,
+            t_cost: Params::DEFAULT_T_COST,
+            p_cost: Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+// A key file encrypted with ChaCha20-Poly1305 under a key derived from a password.
+// The plaintext is the json of the usual (unencrypted) key file.
+#[derive(Serialize, Deserialize)]
+pub struct Keystore {
+    pub kdf: KdfParams,
+    pub salt: String,       // base64
+    pub nonce: String,      // base64
+    pub ciphertext: String, // base64
+}
+
+impl Export for Keystore {}
+
+impl Keystore {
+    pub fn encrypt(plaintext: &[u8], password: &str, kdf: KdfParams) -> Result<Self, NodeError> {
+        let mut rng = rand::thread_rng();
+        let mut salt = [0u8; 16];
+        let mut nonce = [0u8; 12];
+        rng.fill_bytes(&mut salt);
+        rng.fill_bytes(&mut nonce);
+
+        let ciphertext = Self::cipher(password, &salt, &kdf)?
+            .encrypt(Nonce::from_slice(&nonce), plaintext)
+            .map_err(|_| NodeError::KeystoreError("Failed to encrypt key".to_string()))?;
+        Ok(Self {
+            kdf,
+            salt: base64::encode(salt),
+            nonce: base64::encode(nonce),
+            ciphertext: base64::encode(ciphertext),
+        })
+    }
+
+    pub fn decrypt(&self, password: &str) -> Result<Vec<u8>, NodeError> {
+        let decode = |x: &str| {
+            base64::decode(x).map_err(|e| NodeError::KeystoreError(format!("Bad encoding: {}", e)))
+        };
+        let salt = decode(&self.salt)?;
+        let nonce = decode(&self.nonce)?;
+        let ciphertext = decode(&self.ciphertext)?;
+        if nonce.len() != 12 {
+            return Err(NodeError::KeystoreError("Bad nonce length".to_string()));
+        }
+
+        Self::cipher(password, &salt, &self.kdf)?
+            .decrypt(Nonce::from_slice(&nonce), ciphertext.as_ref())
+            .map_err(|_| NodeError::KeystoreError("Wrong password or corrupted key".to_string()))
+    }
+
+    fn cipher(password: &str, salt: &[u8], kdf: &KdfParams) -> Result<ChaCha20Poly1305, NodeError> {
+        let error = |e: argon2::Error| NodeError::KeystoreError(e.to_string());
+        let params = Params::new(kdf.m_cost, kdf.t_cost, kdf.p_cost, Some(32)).map_err(error)?;
+        let mut key = [0u8; 32];
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(password.as_bytes(), salt, &mut key)
+            .map_err(error)?;
+        Ok(ChaCha20Poly1305::new(Key::from_slice(&key)))
+    }
+}
+
+// Where the password of the key files comes from.
+pub enum PasswordSource {
+    Env(String), // name of an environment variable
+    Fd(i32),     // an open file descriptor, e.g. a pipe from a secret manager
+    Prompt,      // the terminal
+}
+
+// The password of the key files, fetched once from its source when first needed.
+pub struct Password {
+    source: PasswordSource,
+    password: Option<String>,
+}
+
+impl Password {
+    pub fn new(source: PasswordSource) -> Self {
+        Self {
+            source,
+            password: None,
+        }
+    }
+
+    // Use the file descriptor if any, then the environment, then prompt.
+    pub fn from_args(fd: Option<i32>) -> Self {
+        let source = match fd {
+            Some(fd) => PasswordSource::Fd(fd),
+            None if std::env::var_os(PASSWORD_ENV).is_some() => {
+                PasswordSource::Env(PASSWORD_ENV.to_string())
+            }
+            None => PasswordSource::Prompt,
+        };
+        Self::new(source)
+    }
+
+    pub fn get(&mut self) -> Result<String, NodeError> {
+        self.fetch(false)
+    }
+
+    // Same as `get`, but asks twice when prompting for a password that is about
+    // to encrypt a new key file.
+    pub fn get_new(&mut self) -> Result<String, NodeError> {
+        self.fetch(true)
+    }
+
+    fn fetch(&mut self, confirm: bool) -> Result<String, NodeError> {
+        if let Some(password) = &self.password {
+            return Ok(password.clone());
+        }
+        let error = |e: std::io::Error| NodeError::PasswordError(e.to_string());
+        let password = match &self.source {
+            PasswordSource::Env(name) => std::env::var(name)
+                .map_err(|e| NodeError::PasswordError(format!("{}: {}", name, e)))?,
+            PasswordSource::Fd(fd) => {
+                // SAFETY: the descriptor is handed over by the caller and read only once.
+                let mut file = unsafe { File::from_raw_fd(*fd) };
+                let mut password = String::new();
+                file.read_to_string(&mut password).map_err(error)?;
+                password.trim_end_matches(&['\r', '\n'][..]).to_string()
+            }
+            PasswordSource::Prompt => {
+                let password = rpassword::prompt_password("Key file password: ").map_err(error)?;
+                if confirm
+                    && rpassword::prompt_password("Confirm password: ").map_err(error)? != password
+                {
+                    return Err(NodeError::PasswordError(
+                        "Passwords do not match".to_string(),
+                    ));
+                }
+                password
+            }
+        };
+        if password.is_empty() {
+            return Err(NodeError::PasswordError("Empty password".to_string()));
+        }
+        self.password = Some(password.clone());
+        Ok(password)
+    }
+}
+
+// Read a key file, decrypting it first if it is a keystore.
+pub fn read_key_file<T: Export>(path: &str, password: &mut Password) -> Result<T, NodeError> {
+    let error = |message: String| NodeError::ReadError {
+        file: path.to_string(),
+        message,
+    };
+    let data = fs::read(path).map_err(|e| error(e.to_string()))?;
+    match serde_json::from_slice::<Keystore>(&data) {
+        Ok(keystore) => {
+            let plaintext = keystore
+                .decrypt(&password.get()?)
+                .map_err(|e| error(e.to_string()))?;
+            serde_json::from_slice(&plaintext).map_err(|e| error(e.to_string()))
+        }
+        Err(_) => T::read(path),
+    }
+}
+
+// Write a key file, encrypted under the password if there is one.
+pub fn write_key_file<T: Export>(
+    value: &T,
+    path: &str,
+    password: Option<&str>,
+) -> Result<(), NodeError> {
+    match password {
+        Some(password) => {
+            let plaintext = serde_json::to_vec(value).unwrap();
+            Keystore::encrypt(&plaintext, password, KdfParams::default())
+                .map_err(|e| NodeError::WriteError {
+                    file: path.to_string(),
+                    message: e.to_string(),
+                })?
+                .write(path)
+        }
+        None => value.write(path),
+    }
+}
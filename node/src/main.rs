@@ -1,16 +1,22 @@
+mod admin;
 mod config;
+mod keystore;
 mod node;
+mod preflight;
 
+use crate::admin::Admin;
 use crate::config::Export as _;
-use crate::config::{Committee, Secret};
-use crate::node::Node;
-use clap::{crate_name, crate_version, App, AppSettings, SubCommand};
-use consensus::Committee as ConsensusCommittee;
+use crate::config::{Authority, Committee, Secret};
+use crate::keystore::Password;
+use crate::node::{Keys, Node, NodeError};
+use clap::{crate_name, crate_version, App, AppSettings, ArgMatches, SubCommand};
+use consensus::Protocol;
 use env_logger::Env;
 use futures::future::join_all;
-use log::error;
-use mempool::Committee as MempoolCommittee;
+use log::{error, info};
+use network::ShutdownHandle;
 use std::fs;
+use tokio::signal::unix::{signal, SignalKind};
 use tokio::task::JoinHandle;
 
 #[tokio::main]
@@ -22,25 +28,85 @@ async fn main() {
         .subcommand(
             SubCommand::with_name("keys")
                 .about("Print a fresh key pair to file")
-                .args_from_usage("--filename=<FILE> 'The file where to print the new key pair'"),
+                .args_from_usage("--filename=<FILE> 'The file where to print the new key pair'")
+                .args_from_usage("--encrypt 'Encrypt the key file with a password'")
+                .args_from_usage(
+                    "--password-fd=[FD] 'The file descriptor to read the key file password from'",
+                ),
         )
         .subcommand(
             SubCommand::with_name("threshold_keys")
                 .about("Print fresh threshold key pairs to files")
                 .args_from_usage(
                     "--filename=<FILE>... 'The files where to print the new key pairs'",
+                )
+                .args_from_usage("--encrypt 'Encrypt the key files with a password'")
+                .args_from_usage(
+                    "--password-fd=[FD] 'The file descriptor to read the key file password from'",
                 ),
         )
         .subcommand(
             SubCommand::with_name("run")
                 .about("Runs a single node")
-                .args_from_usage("--keys=<FILE> 'The file containing the node keys'")
+                .args_from_usage("--keys=[FILE] 'The file containing the node keys'")
                 .args_from_usage(
-                    "--threshold_keys=<FILE> 'The file containing the node threshold_keys'",
+                    "--threshold_keys=[FILE] 'The file containing the node threshold_keys'",
+                )
+                .args_from_usage(
+                    "--signer=[SOCKET] 'The socket of the signer, instead of key files'",
+                )
+                .args_from_usage("--committee=<FILE> 'The file containing committee information'")
+                .args_from_usage("--parameters=[FILE] 'The file containing the node parameters'")
+                .args_from_usage(
+                    "--protocol=[NAME] 'The consensus protocol, instead of the one in the parameters'",
+                )
+                .args_from_usage("--store=<PATH> 'The path where to create the data store'")
+                .args_from_usage(
+                    "--admin=[SOCKET] 'The unix socket where to accept parameter changes'",
+                )
+                .args_from_usage(
+                    "--password-fd=[FD] 'The file descriptor to read the key file password from'",
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("validate")
+                .about("Checks the configuration of a node without running it")
+                .args_from_usage("--keys=[FILE] 'The file containing the node keys'")
+                .args_from_usage(
+                    "--threshold_keys=[FILE] 'The file containing the node threshold_keys'",
+                )
+                .args_from_usage(
+                    "--signer=[SOCKET] 'The socket of the signer, instead of key files'",
                 )
                 .args_from_usage("--committee=<FILE> 'The file containing committee information'")
                 .args_from_usage("--parameters=[FILE] 'The file containing the node parameters'")
-                .args_from_usage("--store=<PATH> 'The path where to create the data store'"),
+                .args_from_usage(
+                    "--protocol=[NAME] 'The consensus protocol, instead of the one in the parameters'",
+                )
+                .args_from_usage(
+                    "--password-fd=[FD] 'The file descriptor to read the key file password from'",
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("tune")
+                .about("Changes the parameters of a running node")
+                .args_from_usage("--admin=<SOCKET> 'The admin socket of the node'")
+                .args_from_usage(
+                    "<TUNING> 'The parameters to change, in JSON (e.g. {\"min_block_delay\": 50})'",
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("signer")
+                .about("Runs a signer holding the keys of a node in a separate process")
+                .args_from_usage("--keys=<FILE> 'The file containing the node keys'")
+                .args_from_usage(
+                    "--threshold_keys=<FILE> 'The file containing the node threshold_keys'",
+                )
+                .args_from_usage("--socket=<PATH> 'The unix socket where to serve the node'")
+                .args_from_usage("--state=<FILE> 'The file recording the signed blocks'")
+                .args_from_usage(
+                    "--password-fd=[FD] 'The file descriptor to read the key file password from'",
+                ),
         )
         .subcommand(
             SubCommand::with_name("deploy")
@@ -65,50 +131,132 @@ async fn main() {
     match matches.subcommand() {
         ("keys", Some(subm)) => {
             let filename = subm.value_of("filename").unwrap();
-            if let Err(e) = Node::print_key_file(&filename) {
+            let result = new_password(subm)
+                .and_then(|password| Node::print_key_file(&filename, password.as_deref()));
+            if let Err(e) = result {
                 error!("{}", e);
             }
         }
         ("threshold_keys", Some(subm)) => {
             let filenames: Vec<&str> = subm.values_of("filename").unwrap().collect();
-            if let Err(e) = Node::print_threshold_key_file(filenames) {
+            let result = new_password(subm).and_then(|password| {
+                Node::print_threshold_key_file(filenames, password.as_deref())
+            });
+            if let Err(e) = result {
                 error!("{}", e);
             }
         }
         ("run", Some(subm)) => {
-            let key_file = subm.value_of("keys").unwrap();
-            let threshold_key_file = subm.value_of("threshold_keys").unwrap();
             let committee_file = subm.value_of("committee").unwrap();
             let parameters_file = subm.value_of("parameters");
             let store_path = subm.value_of("store").unwrap();
+            let admin = subm.value_of("admin");
+            let (keys, protocol) = match keys(subm).and_then(|x| Ok((x, protocol(subm)?))) {
+                Ok(value) => value,
+                Err(e) => {
+                    error!("{}", e);
+                    return;
+                }
+            };
             match Node::new(
                 committee_file,
-                key_file,
-                threshold_key_file,
+                keys,
                 store_path,
                 parameters_file,
+                protocol,
+                admin,
             )
             .await
             {
                 Ok(mut node) => {
-                    tokio::spawn(async move {
-                        node.analyze_block().await;
-                    })
-                    .await
-                    .expect("Failed to analyze committed blocks");
+                    tokio::select! {
+                        () = node.analyze_block() => (),
+                        () = shutdown_signal() => info!("Shutting down"),
+                    }
+                    if let Err(e) = node.shutdown.stop().await {
+                        error!("{}", e);
+                    }
                 }
                 Err(e) => error!("{}", e),
             }
         }
+        ("validate", Some(subm)) => {
+            let committee_file = subm.value_of("committee").unwrap();
+            let parameters_file = subm.value_of("parameters");
+            let result = match keys(subm).and_then(|x| Ok((x, protocol(subm)?))) {
+                Ok((keys, protocol)) => {
+                    Node::validate(committee_file, keys, parameters_file, protocol)
+                        .await
+                        .map_err(|e| e.to_string())
+                }
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(unreachable) => {
+                    for peer in &unreachable {
+                        println!("Warning: {}", peer);
+                    }
+                    println!("The configuration is valid");
+                }
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        ("tune", Some(subm)) => {
+            let admin = subm.value_of("admin").unwrap();
+            let tuning = subm.value_of("TUNING").unwrap();
+            match Admin::tune(admin, tuning).await {
+                Ok(()) => println!("Tuning scheduled"),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        ("signer", Some(subm)) => {
+            let key_file = subm.value_of("keys").unwrap().to_string();
+            let threshold_key_file = subm.value_of("threshold_keys").unwrap().to_string();
+            let socket = subm.value_of("socket").unwrap().to_string();
+            let state_file = subm.value_of("state").unwrap().to_string();
+            let result = match password_fd(subm) {
+                Ok(fd) => tokio::task::spawn_blocking(move || {
+                    let password = Password::from_args(fd);
+                    Node::run_signer(
+                        &key_file,
+                        &threshold_key_file,
+                        &socket,
+                        &state_file,
+                        password,
+                    )
+                })
+                .await
+                .expect("Failed to run signer"),
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                error!("{}", e);
+            }
+        }
         ("deploy", Some(subm)) => {
             let nodes = subm.value_of("nodes").unwrap();
             match nodes.parse::<usize>() {
-                Ok(nodes) if nodes > 0 => match deploy_testbed(nodes) {
-                    Ok(handles) => {
-                        let _ = join_all(handles).await;
+                Ok(nodes) if nodes > 0 => {
+                    let testbed = ShutdownHandle::new();
+                    match deploy_testbed(nodes, &testbed) {
+                        Ok(handles) => {
+                            tokio::select! {
+                                _ = join_all(handles) => (),
+                                () = shutdown_signal() => {
+                                    info!("Shutting down the testbed");
+                                    testbed.shutdown().await;
+                                },
+                            }
+                        }
+                        Err(e) => error!("Failed to deploy testbed: {}", e),
                     }
-                    Err(e) => error!("Failed to deploy testbed: {}", e),
-                },
+                }
                 _ => error!("The number of nodes must be a positive integer"),
             }
         }
@@ -116,43 +264,80 @@ async fn main() {
     }
 }
 
-fn deploy_testbed(nodes: usize) -> Result<Vec<JoinHandle<()>>, Box<dyn std::error::Error>> {
+// Where to get the keys from: key files or a signer.
+fn keys<'a>(subm: &'a ArgMatches) -> Result<Keys<'a>, String> {
+    match (
+        subm.value_of("signer"),
+        subm.value_of("keys"),
+        subm.value_of("threshold_keys"),
+    ) {
+        (Some(socket), None, None) => Ok(Keys::Signer(socket)),
+        (None, Some(keys), Some(threshold_keys)) => Ok(Keys::Files {
+            keys,
+            threshold_keys,
+            password: Password::from_args(password_fd(subm).map_err(|e| e.to_string())?),
+        }),
+        _ => Err("Specify either --keys and --threshold_keys, or --signer".to_string()),
+    }
+}
+
+// The protocol given on the command line, if any.
+fn protocol(subm: &ArgMatches) -> Result<Option<Protocol>, String> {
+    subm.value_of("protocol")
+        .map(|x| x.parse::<Protocol>())
+        .transpose()
+        .map_err(|e| e.to_string())
+}
+
+fn password_fd(subm: &ArgMatches) -> Result<Option<i32>, NodeError> {
+    subm.value_of("password-fd")
+        .map(|fd| fd.parse::<i32>())
+        .transpose()
+        .map_err(|e| NodeError::PasswordError(format!("Invalid file descriptor: {}", e)))
+}
+
+// The password encrypting new key files, if they are to be encrypted.
+fn new_password(subm: &ArgMatches) -> Result<Option<String>, NodeError> {
+    match subm.is_present("encrypt") {
+        true => Password::from_args(password_fd(subm)?).get_new().map(Some),
+        false => Ok(None),
+    }
+}
+
+// Resolves on SIGINT or SIGTERM.
+async fn shutdown_signal() {
+    let mut sigterm = signal(SignalKind::terminate()).expect("Failed to listen to SIGTERM");
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => (),
+        _ = sigterm.recv() => (),
+    }
+}
+
+fn deploy_testbed(
+    nodes: usize,
+    testbed: &ShutdownHandle,
+) -> Result<Vec<JoinHandle<()>>, Box<dyn std::error::Error>> {
     let keys: Vec<_> = (0..nodes).map(|_| Secret::new()).collect();
 
     // Print the committee file.
     let epoch = 1;
-    let mempool_committee = MempoolCommittee::new(
-        keys.iter()
+    let committee = Committee {
+        authorities: keys
+            .iter()
             .enumerate()
-            .map(|(i, key)| {
-                let name = key.name;
-                let front = format!("127.0.0.1:{}", 7000 + i).parse().unwrap();
-                let mempool = format!("127.0.0.1:{}", 7100 + i).parse().unwrap();
-                (name, front, mempool)
+            .map(|(i, key)| Authority {
+                name: key.name,
+                id: i,
+                stake: 1,
+                address: format!("127.0.0.1:{}", 7100 + i).parse().unwrap(),
+                front_address: format!("127.0.0.1:{}", 7000 + i).parse().unwrap(),
             })
             .collect(),
         epoch,
-    );
-    let consensus_committee = ConsensusCommittee::new(
-        keys.iter()
-            .enumerate()
-            .map(|(i, key)| {
-                let name = key.name;
-                let stake = 1;
-                let addresses = format!("127.0.0.1:{}", 7200 + i).parse().unwrap();
-                let smvba_addresses = format!("127.0.0.1:{}", 7300 + i).parse().unwrap();
-                (name, 0, stake, addresses, smvba_addresses) // daniel: not implemented for tss yet
-            })
-            .collect(),
-        epoch,
-    );
+    };
     let committee_file = "committee.json";
     let _ = fs::remove_file(committee_file);
-    Committee {
-        mempool: mempool_committee,
-        consensus: consensus_committee,
-    }
-    .write(committee_file)?;
+    committee.write(committee_file)?;
 
     // Write the key files and spawn all nodes.
     keys.iter()
@@ -165,12 +350,25 @@ fn deploy_testbed(nodes: usize) -> Result<Vec<JoinHandle<()>>, Box<dyn std::erro
             let store_path = format!("db_{}", i);
             let _ = fs::remove_dir_all(&store_path);
 
+            let mut shutdown = testbed.subscribe();
             Ok(tokio::spawn(async move {
-                match Node::new(committee_file, &key_file, &key_file, &store_path, None).await {
+                // The testbed keys are not encrypted.
+                let keys = Keys::Files {
+                    keys: &key_file,
+                    threshold_keys: &key_file,
+                    password: Password::from_args(None),
+                };
+                match Node::new(committee_file, keys, &store_path, None, None, None).await {
                     // daniel: not implemented for tss yet
                     Ok(mut node) => {
-                        // Sink the commit channel.
-                        while node.commit.recv().await.is_some() {}
+                        // Sink the commit channels until the testbed stops.
+                        tokio::select! {
+                            () = node.analyze_block() => (),
+                            () = shutdown.wait() => (),
+                        }
+                        if let Err(e) = node.shutdown.stop().await {
+                            error!("{}", e);
+                        }
                     }
                     Err(e) => error!("{}", e),
                 }
@@ -1,9 +1,16 @@
+use crate::admin::Admin;
 use crate::config::Export as _;
-use crate::config::{Committee, Parameters, Secret};
+use crate::config::{Committee, CommitteeError, Parameters, Secret};
+use crate::keystore::{read_key_file, write_key_file, Password};
+use crate::preflight::{Preflight, PreflightError};
 use consensus::{Block, Consensus, ConsensusError, Protocol};
-use crypto::{SecretShare, SignatureService};
-use log::{info, warn};
-use mempool::{Mempool, MempoolError};
+use crypto::{
+    DoubleSignGuard, Identity, LocalSigner, RemoteSigner, SecretShare, SignatureService, Signer,
+    SignerError, SignerServer,
+};
+use log::info;
+use mempool::{Mempool, MempoolError, Transaction};
+use network::{Authenticator, Mux, ShutdownHandle};
 use store::{Store, StoreError};
 use thiserror::Error;
 use threshold_crypto::serde_impl::SerdeSecret;
@@ -26,73 +33,158 @@ pub enum NodeError {
 
     #[error(transparent)]
     MempoolError(#[from] MempoolError),
+
+    #[error("Invalid committee: {0}")]
+    CommitteeError(#[from] CommitteeError),
+
+    #[error("Threshold QCs require the quorum keys in '{0}'")]
+    MissingQuorumKeys(String),
+
+    #[error("Keystore error: {0}")]
+    KeystoreError(String),
+
+    #[error("Failed to get the key file password: {0}")]
+    PasswordError(String),
+
+    #[error("Signer error: {0}")]
+    SignerError(#[from] SignerError),
+
+    #[error("Admin socket error: {0}")]
+    AdminError(String),
+
+    #[error("Invalid configuration:{}", .0.iter().map(|x| format!("\n  - {}", x)).collect::<String>())]
+    PreflightError(Vec<PreflightError>),
+}
+
+// Where the node gets its keys from.
+pub enum Keys<'a> {
+    // Key files (possibly encrypted) loaded into the node.
+    Files {
+        keys: &'a str,
+        threshold_keys: &'a str,
+        password: Password,
+    },
+    // The socket of a signer process holding the keys.
+    Signer(&'a str),
 }
 
 pub struct Node {
     pub commit: Receiver<Block>,
+    pub transactions: Receiver<Vec<Transaction>>, // fresh transactions of committed payloads
+    pub shutdown: NodeShutdown,
+}
+
+// Stops a running node: signals its tasks and waits for them, then flushes the
+// store to disk.
+pub struct NodeShutdown {
+    handle: ShutdownHandle,
+    store: Store,
+}
+
+impl NodeShutdown {
+    pub async fn stop(mut self) -> Result<(), NodeError> {
+        self.handle.shutdown().await;
+        self.store.flush().await?;
+        info!("Node stopped");
+        Ok(())
+    }
 }
 
 impl Node {
     pub async fn new(
         committee_file: &str,
-        key_file: &str,
-        tss_file: &str,
+        keys: Keys<'_>,
         store_path: &str,
         parameters: Option<&str>,
+        protocol: Option<Protocol>,
+        admin: Option<&str>, // the socket where to accept tunings, see `Admin`
     ) -> Result<Self, NodeError> {
         let (tx_commit, rx_commit) = channel(10000); //commit channel
+        let (tx_transactions, rx_transactions) = channel(10000);
+        let (tx_tuning, rx_tuning) = channel(100);
         let (tx_consensus, rx_consensus) = channel(10000); // 协议交流消息
         let (tx_consensus_mempool, rx_consensus_mempool) = channel(10000);
         let (tx_smvba, rx_smvba) = channel(10000);
         // Read the committee and secret key from file.
-        let committee = Committee::read(committee_file)?;
+        let committee = Committee::load(committee_file)?;
         info!("committee {:?}", committee);
-        let secret = Secret::read(key_file)?;
-        let name = secret.name; //公钥做为ID
-        let secret_key = secret.secret;
-        let tss_keys = SecretShare::read(tss_file)?;
-        let pk_set = tss_keys.pkset.clone();
-
-        // Load default parameters if none are specified.
-        let parameters = match parameters {
-            Some(filename) => Parameters::read(filename)?,
-            None => Parameters::default(),
-        };
+        let mut consensus_committee = committee.consensus();
+        let (identity, signer, keys_source) = Self::open_signer(keys)?;
+        let name = identity.name; //公钥做为ID
+        let pk_set = identity
+            .pkset
+            .clone()
+            .ok_or_else(|| SignerError::MissingKey("threshold".to_string()))?;
+        let mut parameters = Self::read_parameters(parameters, protocol)?;
+
+        // Refuse to boot on a configuration the protocol would choke on.
+        let errors = Preflight::new(&committee, &parameters, &identity).errors();
+        if !errors.is_empty() {
+            return Err(NodeError::PreflightError(errors));
+        }
+        parameters.consensus.verification = parameters.verification;
+        parameters.mempool.verification = parameters.verification;
 
         // Make the data store.
         let store = Store::new(store_path)?;
 
-        // Run the signature service.
-        let signature_service =
-            SignatureService::new(secret_key, Some(tss_keys.secret.into_inner()));
-
-        let protocol = match parameters.protocol {
-            0 => Protocol::HotStuff,
-            1 => Protocol::HotStuffAndSMVBA,
-            2 => Protocol::SMVBA,
-            _ => {
-                warn!("Undefined protocol type!");
-                Protocol::Others
+        // Run the signature service. The quorum keys are only used for threshold QCs.
+        let identity = match parameters.consensus.threshold_qc {
+            true => {
+                let pkset = identity
+                    .quorum_pkset
+                    .clone()
+                    .ok_or_else(|| NodeError::MissingQuorumKeys(keys_source.to_string()))?;
+                consensus_committee.quorum_keys = Some(pkset);
+                identity
             }
+            false => Identity {
+                quorum_pkset: None,
+                ..identity
+            },
         };
+        let signature_service = SignatureService::new_with_signer(signer, &identity);
+
+        // Consensus, SMVBA and mempool messages share one connection per peer.
+        // Only members of the committee may connect to us.
+        let authenticator = Authenticator::new(
+            name,
+            signature_service.clone(),
+            consensus_committee
+                .authorities
+                .values()
+                .map(|x| (x.name, x.address)),
+        );
+        let address = consensus_committee.address(&name).map(|mut x| {
+            x.set_ip("0.0.0.0".parse().unwrap());
+            x
+        })?;
+        let mut mux = Mux::new(address, authenticator);
 
         // Make a new mempool.
+        let shutdown = ShutdownHandle::new();
+        if let Some(path) = admin {
+            Admin::spawn(path, tx_tuning, shutdown.subscribe())?;
+        }
         Mempool::run(
             //用于交易的缓存
-            name,               //公钥->ID
-            committee.mempool,  // 节点信息
-            parameters.mempool, // mempool参数
+            name,                //公钥->ID
+            committee.mempool(), // 节点信息
+            parameters.mempool,  // mempool参数
             store.clone(),
             signature_service.clone(),
             tx_consensus.clone(), //LOOPBACK
             tx_smvba.clone(),
             rx_consensus_mempool, //Get ,Verify,Clean
+            tx_transactions,
+            &mut mux,
+            shutdown.subscribe(),
         )?;
 
         // Run the consensus core.
         Consensus::run(
             name,
-            committee.consensus,
+            consensus_committee,
             parameters.consensus,
             store.clone(),
             signature_service,
@@ -103,38 +195,149 @@ impl Node {
             rx_smvba,
             tx_consensus_mempool,
             tx_commit,
-            protocol,
+            parameters.protocol,
+            rx_tuning,
+            &mut mux,
+            shutdown.subscribe(),
         )
         .await?;
+        shutdown.subscribe().spawn(mux.run());
 
         info!("Node {} successfully booted", name);
-        Ok(Self { commit: rx_commit })
+        Ok(Self {
+            commit: rx_commit,
+            transactions: rx_transactions,
+            shutdown: NodeShutdown {
+                handle: shutdown,
+                store,
+            },
+        })
+    }
+
+    // Check the configuration of a node without running it. Returns the peers
+    // that do not accept connections, which is expected if they are not up yet.
+    pub async fn validate(
+        committee_file: &str,
+        keys: Keys<'_>,
+        parameters: Option<&str>,
+        protocol: Option<Protocol>,
+    ) -> Result<Vec<PreflightError>, NodeError> {
+        let committee = Committee::load(committee_file)?;
+        let (identity, _, _) = Self::open_signer(keys)?;
+        let parameters = Self::read_parameters(parameters, protocol)?;
+        let preflight = Preflight::new(&committee, &parameters, &identity);
+        let errors = preflight.errors();
+        if !errors.is_empty() {
+            return Err(NodeError::PreflightError(errors));
+        }
+        Ok(preflight.unreachable_peers().await)
     }
 
-    pub fn print_key_file(filename: &str) -> Result<(), NodeError> {
-        Secret::new().write(filename)
+    // Returns the signer along with where its keys come from.
+    fn open_signer(keys: Keys<'_>) -> Result<(Identity, Box<dyn Signer>, &str), NodeError> {
+        match keys {
+            Keys::Files {
+                keys,
+                threshold_keys,
+                mut password,
+            } => {
+                // The key files may be encrypted, in which case the password is needed.
+                let secret: Secret = read_key_file(keys, &mut password)?;
+                let tss_keys: SecretShare = read_key_file(threshold_keys, &mut password)?;
+                let signer = LocalSigner::from_share(secret.secret, tss_keys);
+                Ok((signer.identity(), Box::new(signer), threshold_keys))
+            }
+            Keys::Signer(socket) => {
+                let mut signer = RemoteSigner::connect(socket)?;
+                Ok((signer.identity()?, Box::new(signer), socket))
+            }
+        }
+    }
+
+    // Load default parameters if none are specified, the protocol given on the
+    // command line takes precedence. Relaxed verification is refused outside
+    // of benchmark builds.
+    fn read_parameters(
+        filename: Option<&str>,
+        protocol: Option<Protocol>,
+    ) -> Result<Parameters, NodeError> {
+        let mut parameters = match filename {
+            Some(filename) => Parameters::read(filename)?,
+            None => Parameters::default(),
+        };
+        if let Some(protocol) = protocol {
+            parameters.protocol = protocol;
+        }
+        parameters.verification.check()?;
+        Ok(parameters)
+    }
+
+    pub fn print_key_file(filename: &str, password: Option<&str>) -> Result<(), NodeError> {
+        write_key_file(&Secret::new(), filename, password)
     }
 
     // Print the threshold signature keys to the corresponding files
-    pub fn print_threshold_key_file(filenames: Vec<&str>) -> Result<(), NodeError> {
+    pub fn print_threshold_key_file(
+        filenames: Vec<&str>,
+        password: Option<&str>,
+    ) -> Result<(), NodeError> {
         let size = filenames.len();
         let threshold = (size - 1) / 3; // The threshold for TSS is f
         let mut rng = rand::thread_rng();
         let sk_set = SecretKeySet::random(threshold, &mut rng);
         let pk_set = sk_set.public_keys();
+        // The quorum keys have threshold 2f, so that 2f+1 shares make a QC
+        let quorum_sk_set = SecretKeySet::random(2 * threshold, &mut rng);
+        let quorum_pk_set = quorum_sk_set.public_keys();
 
         for id in 0..size {
             let sk_share = sk_set.secret_key_share(id);
             let pk_share = pk_set.public_key_share(id);
-            SecretShare::new(id, pk_share, SerdeSecret(sk_share.clone()), pk_set.clone())
-                .write(filenames[id])?;
+            let tss_keys =
+                SecretShare::new(id, pk_share, SerdeSecret(sk_share.clone()), pk_set.clone());
+            let tss_keys = SecretShare {
+                quorum_secret: Some(SerdeSecret(quorum_sk_set.secret_key_share(id))),
+                quorum_pkset: Some(quorum_pk_set.clone()),
+                ..tss_keys
+            };
+            write_key_file(&tss_keys, filenames[id], password)?;
         }
         return Ok(());
     }
 
+    // Run a signer process holding the keys of a node, see `Keys::Signer`.
+    pub fn run_signer(
+        key_file: &str,
+        tss_file: &str,
+        socket: &str,
+        state_file: &str,
+        mut password: Password,
+    ) -> Result<(), NodeError> {
+        let secret: Secret = read_key_file(key_file, &mut password)?;
+        let tss_keys: SecretShare = read_key_file(tss_file, &mut password)?;
+        let guard = DoubleSignGuard::new(Some(state_file))?;
+        let signer = LocalSigner::from_share(secret.secret, tss_keys).with_guard(guard);
+        info!(
+            "Signer of {} listening on {}",
+            signer.identity().name,
+            socket
+        );
+        SignerServer::new(signer)
+            .serve(socket)
+            .map_err(|e| SignerError::Unavailable(e.to_string()).into())
+    }
+
     pub async fn analyze_block(&mut self) {
-        while let Some(_block) = self.commit.recv().await {
-            // This is where we can further process committed block.
+        loop {
+            tokio::select! {
+                Some(_block) = self.commit.recv() => {
+                    // This is where we can further process committed block.
+                },
+                Some(_transactions) = self.transactions.recv() => {
+                    // Replays are already skipped: each transaction comes out once.
+                },
+                else => break,
+            }
         }
     }
 }
@@ -0,0 +1,218 @@
+use crate::config::{Committee, Parameters};
+use consensus::VerificationPolicy;
+use crypto::{Identity, PublicKey};
+use futures::future::join_all;
+use std::net::{SocketAddr, TcpListener};
+use thiserror::Error;
+use tokio::net::TcpStream;
+use tokio::time::{timeout, Duration};
+
+#[cfg(test)]
+#[path = "tests/preflight_tests.rs"]
+pub mod preflight_tests;
+
+// How long to wait for a peer to accept a connection.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Error, Debug)]
+pub enum PreflightError {
+    #[error("Node {0} is not in the committee: add it, or use the keys of a member")]
+    NotInCommittee(PublicKey),
+
+    #[error(
+        "The threshold share of node {name} does not match id {id} in the public key set: \
+         set the id of the node to the one of its threshold key file"
+    )]
+    ShareMismatch { name: PublicKey, id: usize },
+
+    #[error(
+        "The public key set has threshold {threshold}, expected {expected} for {size} nodes: \
+         regenerate the threshold keys with `node threshold_keys`"
+    )]
+    ThresholdMismatch {
+        threshold: usize,
+        expected: usize,
+        size: usize,
+    },
+
+    #[error("Parameter '{name}' is {value}, expected {expected}")]
+    InvalidParameter {
+        name: &'static str,
+        value: String,
+        expected: String,
+    },
+
+    #[error("Cannot listen on {address}: {message}. Check that no other process uses the port")]
+    PortUnavailable {
+        address: SocketAddr,
+        message: String,
+    },
+
+    #[error("Peer {name} is unreachable at {address}: {message}")]
+    PeerUnreachable {
+        name: PublicKey,
+        address: SocketAddr,
+        message: String,
+    },
+}
+
+// Checks the configuration of a node before it boots, so that mistakes surface
+// as errors rather than as panics or stalls deep in the protocol.
+pub struct Preflight<'a> {
+    committee: &'a Committee,
+    parameters: &'a Parameters,
+    identity: &'a Identity,
+}
+
+impl<'a> Preflight<'a> {
+    pub fn new(
+        committee: &'a Committee,
+        parameters: &'a Parameters,
+        identity: &'a Identity,
+    ) -> Self {
+        Self {
+            committee,
+            parameters,
+            identity,
+        }
+    }
+
+    // The problems preventing the node from running. The committee itself is
+    // validated when loaded.
+    pub fn errors(&self) -> Vec<PreflightError> {
+        let mut errors = Vec::new();
+        self.check_keys(&mut errors);
+        self.check_parameters(&mut errors);
+        self.check_ports(&mut errors);
+        errors
+    }
+
+    // The peers not accepting connections. Only a problem once they should
+    // all be up.
+    pub async fn unreachable_peers(&self) -> Vec<PreflightError> {
+        let probes = self
+            .committee
+            .authorities
+            .iter()
+            .filter(|x| x.name != self.identity.name)
+            .map(|x| async move {
+                let message = match timeout(PROBE_TIMEOUT, TcpStream::connect(x.address)).await {
+                    Ok(Ok(_)) => return None,
+                    Ok(Err(e)) => e.to_string(),
+                    Err(_) => "timed out".to_string(),
+                };
+                Some(PreflightError::PeerUnreachable {
+                    name: x.name,
+                    address: x.address,
+                    message,
+                })
+            });
+        join_all(probes).await.into_iter().flatten().collect()
+    }
+
+    fn check_keys(&self, errors: &mut Vec<PreflightError>) {
+        let name = self.identity.name;
+        let id = match self.committee.authorities.iter().find(|x| x.name == name) {
+            Some(authority) => authority.id,
+            None => return errors.push(PreflightError::NotInCommittee(name)),
+        };
+
+        // The coin of SMVBA needs f + 1 shares.
+        if let Some(pkset) = &self.identity.pkset {
+            let size = self.committee.authorities.len();
+            if pkset.threshold() != (size - 1) / 3 {
+                errors.push(PreflightError::ThresholdMismatch {
+                    threshold: pkset.threshold(),
+                    expected: (size - 1) / 3,
+                    size,
+                });
+            }
+            if let Some(share) = &self.identity.share {
+                if pkset.public_key_share(id) != *share {
+                    errors.push(PreflightError::ShareMismatch { name, id });
+                }
+            }
+        }
+    }
+
+    fn check_parameters(&self, errors: &mut Vec<PreflightError>) {
+        let consensus = &self.parameters.consensus;
+        let mempool = &self.parameters.mempool;
+        let mut positive = |name, value: u64| {
+            if value == 0 {
+                errors.push(PreflightError::InvalidParameter {
+                    name,
+                    value: value.to_string(),
+                    expected: "more than 0".to_string(),
+                });
+            }
+        };
+        positive("consensus.timeout_delay", consensus.timeout_delay);
+        positive("consensus.sync_retry_delay", consensus.sync_retry_delay);
+        positive("consensus.fallback_length", consensus.fallback_length);
+        positive(
+            "consensus.max_payload_size",
+            consensus.max_payload_size as u64,
+        );
+        positive("consensus.max_frame_size", consensus.max_frame_size as u64);
+        positive("mempool.queue_capacity", mempool.queue_capacity as u64);
+        positive("mempool.sync_retry_delay", mempool.sync_retry_delay);
+        positive("mempool.max_payload_size", mempool.max_payload_size as u64);
+        positive("mempool.max_frame_size", mempool.max_frame_size as u64);
+        positive(
+            "mempool.ingestion_workers",
+            mempool.ingestion_workers as u64,
+        );
+        if mempool.dag {
+            positive("mempool.max_header_delay", mempool.max_header_delay);
+        }
+
+        let mut at_most = |name, value: u64, max: u64, hint: &str| {
+            if value > max {
+                errors.push(PreflightError::InvalidParameter {
+                    name,
+                    value: value.to_string(),
+                    expected: format!("at most {}{}", max, hint),
+                });
+            }
+        };
+        at_most(
+            "consensus.random_ddos_chance",
+            consensus.random_ddos_chance,
+            100,
+            " (percent)",
+        );
+        if let VerificationPolicy::Sampled(percent) = self.parameters.verification {
+            at_most("verification", percent as u64, 100, " (percent)");
+        }
+        // Payloads travel in a single frame.
+        at_most(
+            "mempool.max_payload_size",
+            mempool.max_payload_size as u64,
+            mempool.max_frame_size as u64,
+            " (mempool.max_frame_size)",
+        );
+    }
+
+    fn check_ports(&self, errors: &mut Vec<PreflightError>) {
+        let authority = match self
+            .committee
+            .authorities
+            .iter()
+            .find(|x| x.name == self.identity.name)
+        {
+            Some(authority) => authority,
+            None => return,
+        };
+        for mut address in [authority.address, authority.front_address] {
+            // The node listens on all interfaces.
+            address.set_ip("0.0.0.0".parse().unwrap());
+            if let Err(e) = TcpListener::bind(address) {
+                errors.push(PreflightError::PortUnavailable {
+                    address,
+                    message: e.to_string(),
+                });
+            }
+        }
+    }
+}
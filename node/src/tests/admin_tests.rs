@@ -0,0 +1,52 @@
+use super::*;
+use network::ShutdownHandle;
+use tokio::sync::mpsc::channel;
+
+#[tokio::test]
+async fn tune_parameters() {
+    let path = ".test_admin_tune.sock";
+    let (tx_tuning, mut rx_tuning) = channel(10);
+    let shutdown = ShutdownHandle::new();
+    Admin::spawn(path, tx_tuning, shutdown.subscribe()).unwrap();
+
+    Admin::tune(path, r#"{"min_block_delay": 10, "ddos": true}"#)
+        .await
+        .unwrap();
+    let tuning = rx_tuning.recv().await.unwrap();
+    assert_eq!(tuning.min_block_delay, Some(10));
+    assert_eq!(tuning.ddos, Some(true));
+
+    // Invalid tunings are refused, and do not reach the core.
+    for tuning in [
+        r#"{"random_ddos_chance": 150}"#,
+        r#"{"timeout_delay": 10}"#,
+        r#"{"fallback_length": 10}"#,
+        "not json",
+    ] {
+        assert!(matches!(
+            Admin::tune(path, tuning).await,
+            Err(NodeError::AdminError(_))
+        ));
+    }
+    assert!(rx_tuning.try_recv().is_err());
+
+    shutdown.shutdown().await;
+    let _ = fs::remove_file(path);
+}
+
+#[tokio::test]
+async fn private_socket() {
+    let path = ".test_admin_private.sock";
+    let (tx_tuning, _rx_tuning) = channel(10);
+    let shutdown = ShutdownHandle::new();
+    Admin::spawn(path, tx_tuning, shutdown.subscribe()).unwrap();
+
+    // Only the owner may connect, and nothing is left behind.
+    let mode = fs::metadata(path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+    assert!(fs::metadata(format!("{}.bind", path)).is_err());
+    assert!(Admin::tune(path, r#"{"ddos": true}"#).await.is_ok());
+
+    shutdown.shutdown().await;
+    let _ = fs::remove_file(path);
+}
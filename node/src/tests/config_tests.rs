@@ -0,0 +1,98 @@
+use super::*;
+
+// Fixture.
+fn committee() -> Committee {
+    let mut rng = StdRng::from_seed([0; 32]);
+    Committee {
+        authorities: (0..4)
+            .map(|i| Authority {
+                name: generate_keypair(&mut rng).0,
+                id: i,
+                stake: 1,
+                address: format!("127.0.0.1:{}", 100 + i).parse().unwrap(),
+                front_address: format!("127.0.0.1:{}", i).parse().unwrap(),
+            })
+            .collect(),
+        epoch: 1,
+    }
+}
+
+#[test]
+fn derive_views() {
+    let committee = committee();
+    assert!(committee.validate().is_ok());
+
+    let authority = &committee.authorities[2];
+    let consensus = committee.consensus();
+    assert_eq!(consensus.size(), 4);
+    assert_eq!(consensus.id(authority.name), 2);
+    assert_eq!(
+        consensus.address(&authority.name).unwrap(),
+        authority.address
+    );
+    let mempool = committee.mempool();
+    assert_eq!(mempool.address(&authority.name).unwrap(), authority.address);
+    assert_eq!(
+        mempool.front_address(&authority.name).unwrap(),
+        authority.front_address
+    );
+}
+
+#[test]
+fn reject_duplicate_name() {
+    let mut committee = committee();
+    committee.authorities[1].name = committee.authorities[0].name;
+    assert!(matches!(
+        committee.validate(),
+        Err(CommitteeError::DuplicateName(_))
+    ));
+}
+
+#[test]
+fn reject_invalid_ids() {
+    let mut committee = committee();
+    committee.authorities[3].id = 4;
+    assert!(matches!(
+        committee.validate(),
+        Err(CommitteeError::InvalidIds(3))
+    ));
+    committee.authorities[3].id = 0;
+    assert!(matches!(
+        committee.validate(),
+        Err(CommitteeError::InvalidIds(3))
+    ));
+}
+
+#[test]
+fn reject_zero_stake() {
+    let mut committee = committee();
+    committee.authorities[0].stake = 0;
+    assert!(matches!(
+        committee.validate(),
+        Err(CommitteeError::ZeroStake(_))
+    ));
+}
+
+#[test]
+fn reject_shared_address() {
+    let mut committee = committee();
+    committee.authorities[1].front_address = committee.authorities[0].address;
+    assert!(matches!(
+        committee.validate(),
+        Err(CommitteeError::DuplicateAddress(_))
+    ));
+}
+
+#[test]
+fn protocol_by_name() {
+    let parse = |protocol: serde_json::Value| {
+        let mut json = serde_json::to_value(Parameters::default()).unwrap();
+        json["protocol"] = protocol;
+        serde_json::from_value::<Parameters>(json)
+    };
+    assert_eq!(parse("SMVBA".into()).unwrap().protocol, Protocol::SMVBA);
+
+    // Unknown names and the former numbers are refused.
+    assert!(parse("PBFT".into()).is_err());
+    assert!(parse(1.into()).is_err());
+}
@@ -0,0 +1,50 @@
+use super::*;
+use crate::config::Secret;
+
+// Cheap parameters to keep the tests fast.
+fn kdf() -> KdfParams {
+    KdfParams {
+        m_cost: 64,
+        t_cost: 1,
+        p_cost: 1,
+    }
+}
+
+#[test]
+fn encrypt_decrypt() {
+    let keystore = Keystore::encrypt(b"secret", "password", kdf()).unwrap();
+    assert_eq!(keystore.decrypt("password").unwrap(), b"secret");
+}
+
+#[test]
+fn wrong_password() {
+    let keystore = Keystore::encrypt(b"secret", "password", kdf()).unwrap();
+    assert!(keystore.decrypt("wrong").is_err());
+}
+
+#[test]
+fn read_encrypted_and_plain_key_files() {
+    let dir = std::env::temp_dir();
+    let encrypted = dir.join(".keystore_test_encrypted.json");
+    let plain = dir.join(".keystore_test_plain.json");
+    let encrypted = encrypted.to_str().unwrap();
+    let plain = plain.to_str().unwrap();
+
+    let secret = Secret::default();
+    write_key_file(&secret, encrypted, Some("password")).unwrap();
+    write_key_file(&secret, plain, None).unwrap();
+
+    // The password is only needed for the encrypted file.
+    std::env::set_var("NODE_KEY_PASSWORD_TEST", "password");
+    let mut password = Password::new(PasswordSource::Env("NODE_KEY_PASSWORD_TEST".to_string()));
+    let read: Secret = read_key_file(encrypted, &mut password).unwrap();
+    assert_eq!(read.name, secret.name);
+    let mut password = Password::new(PasswordSource::Prompt);
+    let read: Secret = read_key_file(plain, &mut password).unwrap();
+    assert_eq!(read.name, secret.name);
+
+    // A wrong password is refused.
+    let mut password = Password::new(PasswordSource::Env("NODE_KEY_PASSWORD_WRONG".to_string()));
+    std::env::set_var("NODE_KEY_PASSWORD_WRONG", "wrong");
+    assert!(read_key_file::<Secret>(encrypted, &mut password).is_err());
+}
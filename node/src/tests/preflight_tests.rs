@@ -0,0 +1,109 @@
+use super::*;
+use crate::config::Authority;
+use crypto::generate_keypair;
+use rand::rngs::StdRng;
+use rand::SeedableRng as _;
+use threshold_crypto::SecretKeySet;
+
+// Fixture: a committee of 4 nodes, and the identity of the first one.
+fn setup(base_port: u16) -> (Committee, Identity) {
+    let mut rng = StdRng::from_seed([0; 32]);
+    let committee = Committee {
+        authorities: (0..4)
+            .map(|i| Authority {
+                name: generate_keypair(&mut rng).0,
+                id: i,
+                stake: 1,
+                address: format!("127.0.0.1:{}", base_port + i as u16)
+                    .parse()
+                    .unwrap(),
+                front_address: format!("127.0.0.1:{}", base_port + 10 + i as u16)
+                    .parse()
+                    .unwrap(),
+            })
+            .collect(),
+        epoch: 1,
+    };
+    let sk_set = SecretKeySet::random(1, &mut rand::thread_rng());
+    let identity = Identity {
+        name: committee.authorities[0].name,
+        pkset: Some(sk_set.public_keys()),
+        quorum_pkset: None,
+        share: Some(sk_set.secret_key_share(0).public_key_share()),
+    };
+    (committee, identity)
+}
+
+#[test]
+fn valid_configuration() {
+    let (committee, identity) = setup(7900);
+    let parameters = Parameters::default();
+    let preflight = Preflight::new(&committee, &parameters, &identity);
+    assert!(preflight.errors().is_empty());
+}
+
+#[test]
+fn share_mismatch() {
+    let (mut committee, identity) = setup(7920);
+    committee.authorities[0].id = 1;
+    committee.authorities[1].id = 0;
+    let parameters = Parameters::default();
+    let errors = Preflight::new(&committee, &parameters, &identity).errors();
+    assert!(matches!(
+        errors.as_slice(),
+        [PreflightError::ShareMismatch { id: 1, .. }]
+    ));
+}
+
+#[test]
+fn not_in_committee() {
+    let (committee, mut identity) = setup(7940);
+    identity.name = generate_keypair(&mut StdRng::from_seed([1; 32])).0;
+    let parameters = Parameters::default();
+    let errors = Preflight::new(&committee, &parameters, &identity).errors();
+    assert!(matches!(
+        errors.as_slice(),
+        [PreflightError::NotInCommittee(_)]
+    ));
+}
+
+#[test]
+fn invalid_parameters() {
+    let (committee, identity) = setup(7960);
+    let mut parameters = Parameters::default();
+    parameters.consensus.fallback_length = 0;
+    parameters.consensus.random_ddos_chance = 150;
+    let errors = Preflight::new(&committee, &parameters, &identity).errors();
+    let names: Vec<_> = errors
+        .iter()
+        .filter_map(|x| match x {
+            PreflightError::InvalidParameter { name, .. } => Some(*name),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        names,
+        vec!["consensus.fallback_length", "consensus.random_ddos_chance"]
+    );
+}
+
+#[test]
+fn port_in_use() {
+    let (committee, identity) = setup(7980);
+    let _listener = TcpListener::bind("0.0.0.0:7980").unwrap();
+    let parameters = Parameters::default();
+    let errors = Preflight::new(&committee, &parameters, &identity).errors();
+    assert!(matches!(
+        errors.as_slice(),
+        [PreflightError::PortUnavailable { .. }]
+    ));
+}
+
+#[tokio::test]
+async fn unreachable_peers() {
+    let (committee, identity) = setup(8000);
+    let _listener = TcpListener::bind("127.0.0.1:8001").unwrap();
+    let parameters = Parameters::default();
+    let preflight = Preflight::new(&committee, &parameters, &identity);
+    assert_eq!(preflight.unreachable_peers().await.len(), 2);
+}
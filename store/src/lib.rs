@@ -16,6 +16,8 @@ pub enum StoreCommand {
     Write(Key, Value),
     Read(Key, oneshot::Sender<StoreResult<Option<Value>>>),
     NotifyRead(Key, oneshot::Sender<StoreResult<Value>>),
+    Delete(Key),
+    Flush(oneshot::Sender<StoreResult<()>>),
 }
 
 #[derive(Clone)]
@@ -55,6 +57,12 @@ impl Store {
                             }
                         }
                     }
+                    StoreCommand::Delete(key) => {
+                        let _ = db.delete(&key);
+                    }
+                    StoreCommand::Flush(sender) => {
+                        let _ = sender.send(db.flush());
+                    }
                 }
             }
         });
@@ -67,6 +75,12 @@ impl Store {
         }
     }
 
+    pub async fn delete(&mut self, key: Key) {
+        if let Err(e) = self.channel.send(StoreCommand::Delete(key)).await {
+            panic!("Failed to send Delete command to store: {}", e);
+        }
+    }
+
     pub async fn read(&mut self, key: Key) -> StoreResult<Option<Value>> {
         let (sender, receiver) = oneshot::channel();
         if let Err(e) = self.channel.send(StoreCommand::Read(key, sender)).await {
@@ -77,6 +91,17 @@ impl Store {
             .expect("Failed to receive reply to Read command from store")
     }
 
+    // Write the data buffered in memory to disk, e.g. before the node stops.
+    pub async fn flush(&mut self) -> StoreResult<()> {
+        let (sender, receiver) = oneshot::channel();
+        if let Err(e) = self.channel.send(StoreCommand::Flush(sender)).await {
+            panic!("Failed to send Flush command to store: {}", e);
+        }
+        receiver
+            .await
+            .expect("Failed to receive reply to Flush command from store")
+    }
+
     pub async fn notify_read(&mut self, key: Key) -> StoreResult<Value> {
         let (sender, receiver) = oneshot::channel();
         if let Err(e) = self
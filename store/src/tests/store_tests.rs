@@ -71,3 +71,22 @@ async fn read_notify() {
     store.write(key, value).await;
     assert!(handle.await.is_ok());
 }
+
+#[tokio::test]
+async fn delete_value() {
+    // Create new store.
+    let path = ".db_test_delete_value";
+    let _ = fs::remove_dir_all(path);
+    let mut store = Store::new(path).unwrap();
+
+    // Write value to the store and delete it.
+    let key = vec![0u8, 1u8, 2u8, 3u8];
+    let value = vec![4u8, 5u8, 6u8, 7u8];
+    store.write(key.clone(), value).await;
+    store.delete(key.clone()).await;
+
+    // Ensure the value is gone.
+    let result = store.read(key).await;
+    assert!(result.is_ok());
+    assert!(result.unwrap().is_none());
+}